@@ -0,0 +1,631 @@
+use std::{collections::BTreeMap, str::FromStr, sync::Arc, time::Duration};
+
+use anyhow::{Result, anyhow};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use policy_evaluator::policy_fetcher::{
+    oci_client::{
+        self, Reference,
+        manifest::{OCI_IMAGE_MEDIA_TYPE, OciDescriptor, OciImageManifest},
+    },
+    registry::Registry,
+    sigstore::{
+        cosign::{
+            bundle::Bundle,
+            signature_layers::{CertificateSignature, SignatureLayer},
+        },
+        crypto::{
+            CosignVerificationKey, Signature as RawSignature, certificate_pool::CertificatePool,
+        },
+        registry::ClientConfig,
+        trust::{TrustRoot, sigstore::SigstoreTrustRoot},
+    },
+    sources::Sources,
+    verify::{
+        config::{LatestVerificationConfig, Signature},
+        verify_signatures_against_config,
+    },
+};
+use rustls_pki_types::UnixTime;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+use x509_cert::{Certificate, der::Decode};
+
+use crate::{
+    config::verification::VerificationOptions,
+    identity::{IdentityConstraint, IdentityMatcher},
+};
+
+/// Media type of the layers of the attestation image created by cosign
+const DSSE_ENVELOPE_MEDIA_TYPE: &str = "application/vnd.dsse.envelope.v1+json";
+
+/// Payload type of the DSSE envelopes holding in-toto statements
+const IN_TOTO_PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+
+/// Annotation holding the Fulcio certificate of keyless attestations
+const CERTIFICATE_ANNOTATION: &str = "dev.sigstore.cosign/certificate";
+
+/// Annotation holding the Rekor bundle of keyless attestations
+const BUNDLE_ANNOTATION: &str = "dev.sigstore.cosign/bundle";
+
+/// In-toto attestation the policy must have. The attestation must be signed
+/// by one of the signers trusted by the signature constraints of the
+/// verification config.
+///
+/// Inside of the verification config file they are listed under the
+/// `attestations` key, every entry must be satisfied by at least one
+/// attestation.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AttestationConstraint {
+    /// Type of the predicate, like `https://slsa.dev/provenance/v1` or
+    /// `https://spdx.dev/Document`
+    pub predicate_type: String,
+    /// Constraints on the fields of the predicate
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub predicate: Vec<PredicateConstraint>,
+}
+
+/// Constraint on a field of the predicate of an attestation
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct PredicateConstraint {
+    /// Dot separated path of the field, like `runDetails.builder.id`. Array
+    /// items are selected by their index
+    pub path: String,
+    #[serde(flatten)]
+    pub matcher: IdentityMatcher,
+}
+
+impl AttestationConstraint {
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.predicate_type.is_empty() {
+            return Err(anyhow!("attestation constraints require a predicate type"));
+        }
+        for constraint in &self.predicate {
+            if constraint.path.is_empty() {
+                return Err(anyhow!(
+                    "the predicate constraints of attestation {} require a path",
+                    self.predicate_type
+                ));
+            }
+            constraint.matcher.validate()?;
+        }
+        Ok(())
+    }
+
+    fn is_satisfied_by(&self, statement: &Statement) -> bool {
+        statement.predicate_type == self.predicate_type
+            && self
+                .predicate
+                .iter()
+                .all(|constraint| constraint.is_satisfied_by(&statement.predicate))
+    }
+}
+
+impl PredicateConstraint {
+    fn is_satisfied_by(&self, predicate: &serde_json::Value) -> bool {
+        let value = self
+            .path
+            .split('.')
+            .try_fold(predicate, |value, key| match value {
+                serde_json::Value::Array(items) => {
+                    key.parse::<usize>().ok().and_then(|index| items.get(index))
+                }
+                _ => value.get(key),
+            });
+        match value {
+            Some(serde_json::Value::String(value)) => self.matcher.matches(value),
+            Some(value @ (serde_json::Value::Number(_) | serde_json::Value::Bool(_))) => {
+                self.matcher.matches(&value.to_string())
+            }
+            _ => false,
+        }
+    }
+}
+
+/// In-toto statement carried by an attestation
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Statement {
+    pub predicate_type: String,
+    pub subject: Vec<StatementSubject>,
+    #[serde(default)]
+    pub predicate: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct StatementSubject {
+    #[serde(default)]
+    pub name: String,
+    pub digest: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Envelope {
+    payload_type: String,
+    payload: String,
+    signatures: Vec<EnvelopeSignature>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EnvelopeSignature {
+    sig: String,
+}
+
+/// An attestation of the policy whose signature has been checked
+pub(crate) struct Attestation {
+    pub statement: Statement,
+    /// The signature of the attestation, shaped like a cosign signature layer
+    /// so that it can be matched against the constraints of the verification
+    /// config
+    pub signature: SignatureLayer,
+}
+
+impl Attestation {
+    fn new(
+        descriptor: &OciDescriptor,
+        data: &[u8],
+        image_digest: &str,
+        rekor_pub_keys: Option<&BTreeMap<String, CosignVerificationKey>>,
+        fulcio_cert_pool: Option<&CertificatePool>,
+    ) -> Result<Self> {
+        if format!("sha256:{:x}", Sha256::digest(data)) != descriptor.digest {
+            return Err(anyhow!("attestation layer does not match its digest"));
+        }
+        let envelope: Envelope = serde_json::from_slice(data)
+            .map_err(|e| anyhow!("cannot parse DSSE envelope: {}", e))?;
+        if envelope.payload_type != IN_TOTO_PAYLOAD_TYPE {
+            return Err(anyhow!("unexpected payload type {}", envelope.payload_type));
+        }
+        let payload = STANDARD
+            .decode(&envelope.payload)
+            .map_err(|e| anyhow!("cannot decode DSSE payload: {}", e))?;
+        let statement: Statement = serde_json::from_slice(&payload)
+            .map_err(|e| anyhow!("cannot parse in-toto statement: {}", e))?;
+        let digest = image_digest.strip_prefix("sha256:").unwrap_or(image_digest);
+        if !statement
+            .subject
+            .iter()
+            .any(|subject| subject.digest.get("sha256").is_some_and(|d| d == digest))
+        {
+            return Err(anyhow!("the attestation is not about {}", image_digest));
+        }
+
+        let signature = envelope
+            .signatures
+            .first()
+            .ok_or_else(|| anyhow!("the DSSE envelope is not signed"))?;
+        let pae = pre_authentication_encoding(&envelope.payload_type, &payload);
+
+        let annotation = |key: &str| {
+            descriptor
+                .annotations
+                .as_ref()
+                .and_then(|annotations| annotations.get(key))
+        };
+        // like for signatures, certificates can be trusted only when the
+        // Sigstore trust root is available
+        let certificate_signature = match (
+            annotation(CERTIFICATE_ANNOTATION),
+            rekor_pub_keys,
+            fulcio_cert_pool,
+        ) {
+            (Some(certificate), Some(rekor_pub_keys), Some(fulcio_cert_pool)) => {
+                let bundle = annotation(BUNDLE_ANNOTATION)
+                    .ok_or_else(|| anyhow!("keyless attestation without Rekor bundle"))?;
+                let bundle = Bundle::new_verified(bundle, rekor_pub_keys)
+                    .map_err(|e| anyhow!("cannot verify Rekor bundle: {}", e))?;
+                let certificate_signature = certificate_signature(
+                    certificate,
+                    fulcio_cert_pool,
+                    bundle.payload.integrated_time,
+                )?;
+                certificate_signature
+                    .verification_key
+                    .verify_signature(RawSignature::Base64Encoded(signature.sig.as_bytes()), &pae)
+                    .map_err(|e| anyhow!("invalid attestation signature: {}", e))?;
+                Some(certificate_signature)
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            statement,
+            signature: SignatureLayer {
+                simple_signing: crate::signatures::simple_signing(image_digest)?,
+                oci_digest: descriptor.digest.clone(),
+                certificate_signature,
+                bundle: None,
+                signature: Some(signature.sig.clone()),
+                raw_data: pae,
+            },
+        })
+    }
+}
+
+/// Fetches the layers of the attestation image attached by cosign to the
+/// policy manifest with the given digest
+pub(crate) async fn fetch(
+    uri: &str,
+    sources: Option<&Sources>,
+    image_digest: &str,
+) -> Result<Vec<(OciDescriptor, Vec<u8>)>> {
+    let image_name = uri
+        .strip_prefix("registry://")
+        .ok_or_else(|| anyhow!("attestations can be fetched only for registry:// policies"))?;
+    let image = Reference::from_str(image_name)?;
+    let attestations_image = Reference::with_tag(
+        image.registry().to_string(),
+        image.repository().to_string(),
+        format!("{}.att", image_digest.replace(':', "-")),
+    );
+    debug!(image = %attestations_image, "fetching attestations");
+
+    let registry_auth = Registry::auth(image_name);
+    let client_config: ClientConfig = sources.cloned().unwrap_or_default().into();
+    let oci_client = oci_client::Client::new(client_config.into());
+    let (manifest, _) = oci_client
+        .pull_manifest_raw(&attestations_image, &registry_auth, &[OCI_IMAGE_MEDIA_TYPE])
+        .await
+        .map_err(|e| anyhow!("cannot fetch attestations of policy {}: {}", uri, e))?;
+    let manifest: OciImageManifest = serde_json::from_slice(&manifest).map_err(|e| {
+        anyhow!(
+            "cannot parse attestations manifest of policy {}: {}",
+            uri,
+            e
+        )
+    })?;
+
+    let mut layers = Vec::new();
+    for descriptor in manifest.layers {
+        if descriptor.media_type != DSSE_ENVELOPE_MEDIA_TYPE {
+            continue;
+        }
+        let mut data: Vec<u8> = Vec::new();
+        oci_client
+            .pull_blob(&attestations_image, &descriptor, &mut data)
+            .await
+            .map_err(|e| anyhow!("cannot fetch attestation {}: {}", descriptor.digest, e))?;
+        layers.push((descriptor, data));
+    }
+    Ok(layers)
+}
+
+/// The attestations about `image_digest` whose signature is valid. Keyless
+/// attestations are trusted only when a Sigstore trust root is provided,
+/// attestations that cannot be trusted are ignored.
+pub(crate) fn trusted_attestations(
+    layers: &[(OciDescriptor, Vec<u8>)],
+    image_digest: &str,
+    sigstore_trust_root: Option<Arc<SigstoreTrustRoot>>,
+) -> Result<Vec<Attestation>> {
+    let (rekor_pub_keys, fulcio_certs) = match &sigstore_trust_root {
+        Some(trust_root) => (
+            Some(crate::signatures::rekor_pub_keys(trust_root)?),
+            Some(trust_root.fulcio_certs()?),
+        ),
+        None => (None, None),
+    };
+    let fulcio_cert_pool = fulcio_certs
+        .map(|certs| CertificatePool::from_certificates(certs, []))
+        .transpose()?;
+
+    Ok(layers
+        .iter()
+        .filter_map(|(descriptor, data)| {
+            Attestation::new(
+                descriptor,
+                data,
+                image_digest,
+                rekor_pub_keys.as_ref(),
+                fulcio_cert_pool.as_ref(),
+            )
+            .inspect_err(|e| {
+                warn!(attestation = descriptor.digest, error = %e, "ignoring attestation");
+            })
+            .ok()
+        })
+        .collect())
+}
+
+/// Ensures every attestation constraint is satisfied by at least one
+/// attestation signed by a signer trusted by the verification options
+pub(crate) fn verify_attestations(
+    verification_options: &VerificationOptions,
+    attestations: &[Attestation],
+) -> Result<()> {
+    let trusted: Vec<&Attestation> = attestations
+        .iter()
+        .filter(|attestation| is_trusted_signer(verification_options, &attestation.signature))
+        .collect();
+    for constraint in &verification_options.attestations {
+        if !trusted
+            .iter()
+            .any(|attestation| constraint.is_satisfied_by(&attestation.statement))
+        {
+            return Err(anyhow!(
+                "Attestation verification failed: no trusted attestation satisfies the constraint {}",
+                serde_json::to_string(constraint).unwrap_or_default()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Whether the attestation has been signed by one of the signers accepted by
+/// the verification options. Attestations carry no annotations, the ones
+/// required by the constraints are not taken into account
+fn is_trusted_signer(verification_options: &VerificationOptions, layer: &SignatureLayer) -> bool {
+    let config = &verification_options.config;
+    let mut signatures = config
+        .all_of
+        .iter()
+        .flatten()
+        .chain(config.any_of.iter().flat_map(|any_of| &any_of.signatures));
+    signatures.any(|signature| {
+        let config = LatestVerificationConfig {
+            all_of: Some(vec![without_annotations(signature)]),
+            any_of: None,
+        };
+        verify_signatures_against_config(&config, std::slice::from_ref(layer)).is_ok()
+    }) || verification_options.identities.iter().any(|identity| {
+        IdentityConstraint {
+            annotations: None,
+            ..identity.clone()
+        }
+        .is_satisfied_by(layer)
+    })
+}
+
+fn without_annotations(signature: &Signature) -> Signature {
+    match signature.clone() {
+        Signature::PubKey { owner, key, .. } => Signature::PubKey {
+            owner,
+            key,
+            annotations: None,
+        },
+        Signature::GenericIssuer {
+            issuer, subject, ..
+        } => Signature::GenericIssuer {
+            issuer,
+            subject,
+            annotations: None,
+        },
+        Signature::GithubAction { owner, repo, .. } => Signature::GithubAction {
+            owner,
+            repo,
+            annotations: None,
+        },
+    }
+}
+
+/// The message signed inside of a DSSE envelope
+fn pre_authentication_encoding(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut pae = format!(
+        "DSSEv1 {} {} {} ",
+        payload_type.len(),
+        payload_type,
+        payload.len()
+    )
+    .into_bytes();
+    pae.extend_from_slice(payload);
+    pae
+}
+
+/// Ensures the Fulcio certificate was valid when the attestation was added to
+/// Rekor, then extracts its details
+fn certificate_signature(
+    certificate_pem: &str,
+    fulcio_cert_pool: &CertificatePool,
+    integrated_time: i64,
+) -> Result<CertificateSignature> {
+    let verification_time = UnixTime::since_unix_epoch(Duration::from_secs(
+        u64::try_from(integrated_time)
+            .map_err(|_| anyhow!("invalid Rekor integrated time {}", integrated_time))?,
+    ));
+    fulcio_cert_pool
+        .verify_pem_cert(certificate_pem.as_bytes(), Some(verification_time))
+        .map_err(|e| anyhow!("certificate is not trusted: {}", e))?;
+
+    let certificate = pem::parse(certificate_pem)
+        .map_err(|e| anyhow!("cannot parse certificate: {}", e))
+        .and_then(|pem| {
+            Certificate::from_der(pem.contents())
+                .map_err(|e| anyhow!("cannot parse certificate: {}", e))
+        })?;
+    crate::identity::certificate_signature(&certificate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    const IMAGE_DIGEST: &str =
+        "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a";
+
+    fn provenance() -> Statement {
+        serde_json::from_value(serde_json::json!({
+            "_type": "https://in-toto.io/Statement/v1",
+            "predicateType": "https://slsa.dev/provenance/v1",
+            "subject": [{
+                "name": "ghcr.io/kubewarden/policies/pod-privileged",
+                "digest": { "sha256": IMAGE_DIGEST.strip_prefix("sha256:").unwrap() }
+            }],
+            "predicate": {
+                "buildDefinition": {
+                    "resolvedDependencies": [{ "uri": "git+https://github.com/kubewarden/pod-privileged-policy@refs/tags/v1.0.0" }]
+                },
+                "runDetails": {
+                    "builder": { "id": "https://github.com/slsa-framework/slsa-github-generator/.github/workflows/generator_container_slsa3.yml@refs/tags/v2.0.0" }
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    fn envelope(payload_type: &str, statement: serde_json::Value) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "payloadType": payload_type,
+            "payload": STANDARD.encode(statement.to_string()),
+            "signatures": [{ "keyid": "", "sig": "" }]
+        }))
+        .unwrap()
+    }
+
+    fn descriptor(data: &[u8]) -> OciDescriptor {
+        OciDescriptor {
+            media_type: DSSE_ENVELOPE_MEDIA_TYPE.to_string(),
+            digest: format!("sha256:{:x}", Sha256::digest(data)),
+            size: data.len() as i64,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_pre_authentication_encoding() {
+        // test vector of the DSSE specification
+        assert_eq!(
+            pre_authentication_encoding("http://example.com/HelloWorld", b"hello world"),
+            b"DSSEv1 29 http://example.com/HelloWorld 11 hello world"
+        );
+    }
+
+    #[rstest]
+    #[case::predicate_type("https://slsa.dev/provenance/v1", vec![], true)]
+    #[case::other_predicate_type("https://spdx.dev/Document", vec![], false)]
+    #[case::builder_id(
+        "https://slsa.dev/provenance/v1",
+        vec![PredicateConstraint {
+            path: "runDetails.builder.id".to_string(),
+            matcher: IdentityMatcher::Prefix("https://github.com/slsa-framework/slsa-github-generator/".to_string()),
+        }],
+        true
+    )]
+    #[case::other_builder_id(
+        "https://slsa.dev/provenance/v1",
+        vec![PredicateConstraint {
+            path: "runDetails.builder.id".to_string(),
+            matcher: IdentityMatcher::Equal("https://example.com/builder".to_string()),
+        }],
+        false
+    )]
+    #[case::array_index(
+        "https://slsa.dev/provenance/v1",
+        vec![PredicateConstraint {
+            path: "buildDefinition.resolvedDependencies.0.uri".to_string(),
+            matcher: IdentityMatcher::Regexp("^git\\+https://github.com/kubewarden/".to_string()),
+        }],
+        true
+    )]
+    #[case::missing_field(
+        "https://slsa.dev/provenance/v1",
+        vec![PredicateConstraint {
+            path: "runDetails.metadata.invocationId".to_string(),
+            matcher: IdentityMatcher::Regexp(".*".to_string()),
+        }],
+        false
+    )]
+    fn test_attestation_constraint(
+        #[case] predicate_type: &str,
+        #[case] predicate: Vec<PredicateConstraint>,
+        #[case] satisfied: bool,
+    ) {
+        let constraint = AttestationConstraint {
+            predicate_type: predicate_type.to_string(),
+            predicate,
+        };
+        assert_eq!(constraint.is_satisfied_by(&provenance()), satisfied);
+    }
+
+    #[test]
+    fn test_deserialize_constraint() {
+        let yaml = r#"
+predicateType: https://slsa.dev/provenance/v1
+predicate:
+  - path: runDetails.builder.id
+    equal: https://example.com/builder
+"#;
+        let constraint: AttestationConstraint = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            constraint.predicate,
+            vec![PredicateConstraint {
+                path: "runDetails.builder.id".to_string(),
+                matcher: IdentityMatcher::Equal("https://example.com/builder".to_string()),
+            }]
+        );
+        assert!(constraint.validate().is_ok());
+    }
+
+    #[rstest]
+    #[case::other_image(
+        IN_TOTO_PAYLOAD_TYPE,
+        "sha256:0000000000000000000000000000000000000000000000000000000000000000",
+        "the attestation is not about"
+    )]
+    #[case::payload_type("text/plain", IMAGE_DIGEST, "unexpected payload type")]
+    fn test_untrusted_attestation(
+        #[case] payload_type: &str,
+        #[case] image_digest: &str,
+        #[case] error: &str,
+    ) {
+        let data = envelope(
+            payload_type,
+            serde_json::json!({
+                "predicateType": "https://spdx.dev/Document",
+                "subject": [{ "digest": { "sha256": IMAGE_DIGEST.strip_prefix("sha256:").unwrap() } }],
+                "predicate": {}
+            }),
+        );
+        let result = Attestation::new(&descriptor(&data), &data, image_digest, None, None);
+        assert!(result.is_err_and(|e| e.to_string().contains(error)));
+    }
+
+    #[test]
+    fn test_tampered_attestation() {
+        let data = envelope(IN_TOTO_PAYLOAD_TYPE, serde_json::json!({}));
+        let result = Attestation::new(&descriptor(b"{}"), &data, IMAGE_DIGEST, None, None);
+        assert!(result.is_err_and(|e| e.to_string().contains("does not match its digest")));
+    }
+
+    #[test]
+    fn test_unsigned_attestation_is_not_trusted() {
+        let data = envelope(
+            IN_TOTO_PAYLOAD_TYPE,
+            serde_json::json!({
+                "predicateType": "https://spdx.dev/Document",
+                "subject": [{ "digest": { "sha256": IMAGE_DIGEST.strip_prefix("sha256:").unwrap() } }],
+                "predicate": {}
+            }),
+        );
+        let attestation =
+            Attestation::new(&descriptor(&data), &data, IMAGE_DIGEST, None, None).unwrap();
+        let verification_options = VerificationOptions {
+            config: LatestVerificationConfig {
+                all_of: Some(vec![Signature::PubKey {
+                    owner: None,
+                    key: "-----BEGIN PUBLIC KEY-----
+MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEQiTy5S+2JFvVlhUwWPLziM7iTM2j
+byLgh2IjpNQN0Uio/9pZOTP/CsJmXoUNshfpTUHd3OxgHgz/6adtf2nBwQ==
+-----END PUBLIC KEY-----"
+                        .to_string(),
+                    annotations: None,
+                }]),
+                any_of: None,
+            },
+            identities: Vec::new(),
+            attestations: vec![AttestationConstraint {
+                predicate_type: "https://spdx.dev/Document".to_string(),
+                predicate: Vec::new(),
+            }],
+        };
+
+        let error = verify_attestations(&verification_options, &[attestation]).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("Attestation verification failed")
+        );
+    }
+}
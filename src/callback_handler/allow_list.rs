@@ -0,0 +1,261 @@
+use std::{collections::BTreeSet, fmt, str::FromStr};
+
+use anyhow::{Result, anyhow};
+use policy_evaluator::callback_requests::{CallbackRequest, CallbackRequestType};
+use tokio::sync::mpsc;
+use tracing::{error, warn};
+
+/// The families of host capabilities a policy can use. The crypto host
+/// capabilities are answered by the policy evaluator itself, they never reach
+/// the callback handler and cannot be restricted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum HostCapability {
+    Dns,
+    Oci,
+    Sigstore,
+    Kubernetes,
+}
+
+impl HostCapability {
+    pub const ALL: [HostCapability; 4] = [
+        HostCapability::Dns,
+        HostCapability::Oci,
+        HostCapability::Sigstore,
+        HostCapability::Kubernetes,
+    ];
+
+    /// Returns the family the request belongs to. The match is exhaustive,
+    /// new kinds of requests must be classified before they can be used
+    fn of(request: &CallbackRequestType) -> HostCapability {
+        match request {
+            CallbackRequestType::OciManifestDigest { .. }
+            | CallbackRequestType::OciManifest { .. }
+            | CallbackRequestType::OciManifestAndConfig { .. } => HostCapability::Oci,
+            CallbackRequestType::SigstorePubKeyVerify { .. }
+            | CallbackRequestType::SigstoreKeylessVerify { .. }
+            | CallbackRequestType::SigstoreKeylessPrefixVerify { .. }
+            | CallbackRequestType::SigstoreGithubActionsVerify { .. }
+            | CallbackRequestType::SigstoreCertificateVerify { .. } => HostCapability::Sigstore,
+            CallbackRequestType::DNSLookupHost { .. } => HostCapability::Dns,
+            CallbackRequestType::KubernetesListResourceAll { .. }
+            | CallbackRequestType::KubernetesListResourceNamespace { .. }
+            | CallbackRequestType::KubernetesGetResource { .. }
+            | CallbackRequestType::KubernetesGetResourcePluralName { .. }
+            | CallbackRequestType::HasKubernetesListResourceAllResultChangedSinceInstant {
+                ..
+            }
+            | CallbackRequestType::KubernetesCanI { .. } => HostCapability::Kubernetes,
+        }
+    }
+}
+
+impl FromStr for HostCapability {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "dns" => Ok(HostCapability::Dns),
+            "oci" => Ok(HostCapability::Oci),
+            "sigstore" => Ok(HostCapability::Sigstore),
+            "kubernetes" => Ok(HostCapability::Kubernetes),
+            _ => Err(anyhow!("unknown host capability: {}", value)),
+        }
+    }
+}
+
+impl fmt::Display for HostCapability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HostCapability::Dns => write!(f, "dns"),
+            HostCapability::Oci => write!(f, "oci"),
+            HostCapability::Sigstore => write!(f, "sigstore"),
+            HostCapability::Kubernetes => write!(f, "kubernetes"),
+        }
+    }
+}
+
+/// Ensures the request belongs to one of the allowed families
+fn check_request(allowed: &BTreeSet<HostCapability>, request: &CallbackRequestType) -> Result<()> {
+    let capability = HostCapability::of(request);
+    if allowed.contains(&capability) {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "access to the '{}' host capabilities has been denied by kwctl",
+            capability
+        ))
+    }
+}
+
+/// Spawns a tokio task that forwards to `handler_channel` only the requests
+/// belonging to one of the allowed families. The other requests are answered
+/// with an error.
+///
+/// Returns the channel the policy has to use to send its requests.
+/// The task is stopped once the handler is gone.
+pub(crate) fn spawn_filter(
+    allowed: BTreeSet<HostCapability>,
+    handler_channel: mpsc::Sender<CallbackRequest>,
+) -> mpsc::Sender<CallbackRequest> {
+    let (tx, mut rx) = mpsc::channel::<CallbackRequest>(200);
+
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = handler_channel.closed() => return,
+                maybe_req = rx.recv() => {
+                    let Some(req) = maybe_req else {
+                        return;
+                    };
+
+                    if let Err(e) = check_request(&allowed, &req.request) {
+                        warn!(request = ?req.request, error = %e, "host capabilities request denied");
+                        if req.response_channel.send(Err(e)).is_err() {
+                            error!("Cannot send back response to policy");
+                        }
+                        continue;
+                    }
+
+                    if let Err(mpsc::error::SendError(req)) = handler_channel.send(req).await
+                        && req
+                            .response_channel
+                            .send(Err(anyhow!("the host capabilities handler is not running")))
+                            .is_err()
+                    {
+                        error!("Cannot send back response to policy");
+                    }
+                }
+            }
+        }
+    });
+
+    tx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::dns(
+        CallbackRequestType::DNSLookupHost { host: "kubewarden.io".to_string() },
+        HostCapability::Dns
+    )]
+    #[case::oci(
+        CallbackRequestType::OciManifestDigest { image: "busybox".to_string() },
+        HostCapability::Oci
+    )]
+    #[case::kubernetes(
+        CallbackRequestType::KubernetesGetResource {
+            api_version: "v1".to_string(),
+            kind: "Namespace".to_string(),
+            name: "default".to_string(),
+            namespace: None,
+            disable_cache: false,
+        },
+        HostCapability::Kubernetes
+    )]
+    fn host_capability_of_request(
+        #[case] request: CallbackRequestType,
+        #[case] expected: HostCapability,
+    ) {
+        assert_eq!(HostCapability::of(&request), expected);
+    }
+
+    #[test]
+    fn check_request_denied_family() {
+        let allowed = BTreeSet::from([HostCapability::Kubernetes]);
+
+        let request = CallbackRequestType::DNSLookupHost {
+            host: "kubewarden.io".to_string(),
+        };
+        let err = check_request(&allowed, &request).unwrap_err();
+        assert!(err.to_string().contains("'dns'"));
+
+        let request = CallbackRequestType::KubernetesGetResource {
+            api_version: "v1".to_string(),
+            kind: "Namespace".to_string(),
+            name: "default".to_string(),
+            namespace: None,
+            disable_cache: false,
+        };
+        assert!(check_request(&allowed, &request).is_ok());
+    }
+
+    /// A request of the given family, the match ensures every family that can
+    /// be denied has requests reaching the callback handler
+    fn request_of(capability: HostCapability) -> CallbackRequestType {
+        match capability {
+            HostCapability::Dns => CallbackRequestType::DNSLookupHost {
+                host: "kubewarden.io".to_string(),
+            },
+            HostCapability::Oci => CallbackRequestType::OciManifestDigest {
+                image: "busybox".to_string(),
+            },
+            HostCapability::Sigstore => CallbackRequestType::SigstorePubKeyVerify {
+                image: "busybox".to_string(),
+                pub_keys: Vec::new(),
+                annotations: None,
+            },
+            HostCapability::Kubernetes => CallbackRequestType::KubernetesGetResource {
+                api_version: "v1".to_string(),
+                kind: "Namespace".to_string(),
+                name: "default".to_string(),
+                namespace: None,
+                disable_cache: false,
+            },
+        }
+    }
+
+    #[test]
+    fn check_request_denied_capabilities() {
+        for denied in HostCapability::ALL {
+            let request = request_of(denied);
+            assert_eq!(HostCapability::of(&request), denied);
+
+            let allowed = HostCapability::ALL
+                .into_iter()
+                .filter(|capability| *capability != denied)
+                .collect::<BTreeSet<_>>();
+            let err = check_request(&allowed, &request).unwrap_err();
+            assert!(err.to_string().contains(&format!("'{denied}'")));
+            assert!(check_request(&BTreeSet::from(HostCapability::ALL), &request).is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn filter_answers_denied_requests() {
+        let (handler_tx, mut handler_rx) = mpsc::channel::<CallbackRequest>(10);
+        let filter_tx = spawn_filter(BTreeSet::from([HostCapability::Oci]), handler_tx);
+
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+        filter_tx
+            .send(CallbackRequest {
+                request: CallbackRequestType::DNSLookupHost {
+                    host: "kubewarden.io".to_string(),
+                },
+                response_channel: response_tx,
+            })
+            .await
+            .unwrap();
+        let response = response_rx.await.unwrap();
+        assert!(response.is_err());
+
+        let (response_tx, _response_rx) = tokio::sync::oneshot::channel();
+        filter_tx
+            .send(CallbackRequest {
+                request: CallbackRequestType::OciManifestDigest {
+                    image: "busybox".to_string(),
+                },
+                response_channel: response_tx,
+            })
+            .await
+            .unwrap();
+        let forwarded = handler_rx.recv().await.unwrap();
+        assert!(matches!(
+            forwarded.request,
+            CallbackRequestType::OciManifestDigest { .. }
+        ));
+    }
+}
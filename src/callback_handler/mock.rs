@@ -0,0 +1,207 @@
+use std::{collections::BTreeMap, fs::File, path::Path};
+
+use anyhow::{Result, anyhow};
+use policy_evaluator::callback_requests::CallbackRequestType;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::session::Response;
+
+/// A canned answer to the host capabilities requests matching `request`
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct Mock {
+    pub request: RequestMatcher,
+    pub response: Response,
+}
+
+/// Describes the requests a mock applies to.
+///
+/// `type` is the name of the `CallbackRequestType` variant, all the other
+/// keys are compared against the fields of the request. String values can
+/// use the `*` and `?` wildcards. Fields that are not listed are ignored.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RequestMatcher {
+    #[serde(rename = "type")]
+    pub request_type: String,
+    #[serde(flatten)]
+    pub fields: BTreeMap<String, Value>,
+}
+
+/// Reads the list of mocks defined inside of the given file
+pub(crate) fn read_mocks_file(path: &Path) -> Result<Vec<Mock>> {
+    let file = File::open(path)
+        .map_err(|e| anyhow!("Cannot open host capabilities mocks file {:?}: {}", path, e))?;
+    serde_yaml::from_reader(file).map_err(|e| {
+        anyhow!(
+            "Cannot parse host capabilities mocks file {:?}: {}",
+            path,
+            e
+        )
+    })
+}
+
+/// Returns the response of the first mock matching the request, if any
+pub(crate) fn find_response(
+    mocks: &[Mock],
+    request: &CallbackRequestType,
+) -> Result<Option<Response>> {
+    let (request_type, fields) = split_request(request)?;
+
+    Ok(mocks
+        .iter()
+        .find(|mock| mock.request.matches(&request_type, &fields))
+        .map(|mock| mock.response.clone()))
+}
+
+/// Splits the request into the name of its variant and its fields
+pub(super) fn split_request(
+    request: &CallbackRequestType,
+) -> Result<(String, serde_json::Map<String, Value>)> {
+    match serde_json::to_value(request)
+        .map_err(|e| anyhow!("cannot convert request to json: {}", e))?
+    {
+        Value::String(request_type) => Ok((request_type, serde_json::Map::new())),
+        Value::Object(object) if object.len() == 1 => {
+            let (request_type, value) = object.into_iter().next().expect("object is not empty");
+            let fields = match value {
+                Value::Object(fields) => fields,
+                value => serde_json::Map::from_iter([("value".to_string(), value)]),
+            };
+            Ok((request_type, fields))
+        }
+        value => Err(anyhow!("unexpected request format: {}", value)),
+    }
+}
+
+impl RequestMatcher {
+    fn matches(&self, request_type: &str, fields: &serde_json::Map<String, Value>) -> bool {
+        wildcard_match(&self.request_type, request_type)
+            && self
+                .fields
+                .iter()
+                .all(|(key, expected)| match (expected, fields.get(key)) {
+                    (_, None) => false,
+                    (Value::String(pattern), Some(Value::String(actual))) => {
+                        wildcard_match(pattern, actual)
+                    }
+                    (Value::String(pattern), Some(actual)) => {
+                        wildcard_match(pattern, &actual.to_string())
+                    }
+                    (expected, Some(actual)) => expected == actual,
+                })
+    }
+}
+
+/// Shell-like matching: `*` matches any sequence of characters, `?` matches
+/// exactly one character
+fn wildcard_match(pattern: &str, value: &str) -> bool {
+    let regex = pattern
+        .split('*')
+        .map(|part| {
+            part.split('?')
+                .map(regex::escape)
+                .collect::<Vec<String>>()
+                .join(".")
+        })
+        .collect::<Vec<String>>()
+        .join(".*");
+
+    Regex::new(&format!("^{regex}$"))
+        .map(|re| re.is_match(value))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn mocks() -> Vec<Mock> {
+        serde_yaml::from_str(
+            r#"
+- request:
+    type: OciManifestDigest
+    image: "ghcr.io/*"
+  response:
+    type: Success
+    payload: '"sha256:1234"'
+- request:
+    type: DNSLookupHost
+  response:
+    type: Error
+    message: DNS lookups are not allowed
+- request:
+    type: Kubernetes*
+    kind: Namespace
+    namespace: null
+  response:
+    type: Success
+    payload: '{}'
+"#,
+        )
+        .expect("cannot parse mocks")
+    }
+
+    #[rstest]
+    #[case::exact("busybox", "busybox", true)]
+    #[case::exact_mismatch("busybox", "alpine", false)]
+    #[case::star("ghcr.io/*", "ghcr.io/kubewarden/policy:v1", true)]
+    #[case::star_mismatch("ghcr.io/*", "docker.io/library/busybox", false)]
+    #[case::question_mark("v?.0", "v1.0", true)]
+    #[case::question_mark_one_char("v?.0", "v10.0", false)]
+    #[case::regex_chars_are_literal("a.b", "axb", false)]
+    fn test_wildcard_match(#[case] pattern: &str, #[case] value: &str, #[case] expected: bool) {
+        assert_eq!(wildcard_match(pattern, value), expected);
+    }
+
+    #[test]
+    fn find_response_with_wildcard_field() {
+        let request = CallbackRequestType::OciManifestDigest {
+            image: "ghcr.io/kubewarden/policies/psp:v1".to_string(),
+        };
+        let response = find_response(&mocks(), &request).unwrap();
+        assert!(matches!(
+            response,
+            Some(Response::Success { payload }) if payload == "\"sha256:1234\""
+        ));
+
+        let request = CallbackRequestType::OciManifestDigest {
+            image: "docker.io/library/busybox".to_string(),
+        };
+        assert!(find_response(&mocks(), &request).unwrap().is_none());
+    }
+
+    #[test]
+    fn find_response_any_request_of_a_type() {
+        let request = CallbackRequestType::DNSLookupHost {
+            host: "kubewarden.io".to_string(),
+        };
+        let response = find_response(&mocks(), &request).unwrap();
+        assert!(matches!(
+            response,
+            Some(Response::Error { message }) if message == "DNS lookups are not allowed"
+        ));
+    }
+
+    #[test]
+    fn find_response_wildcard_type_and_null_field() {
+        let request = CallbackRequestType::KubernetesGetResource {
+            api_version: "v1".to_string(),
+            kind: "Namespace".to_string(),
+            name: "default".to_string(),
+            namespace: None,
+            disable_cache: false,
+        };
+        assert!(find_response(&mocks(), &request).unwrap().is_some());
+
+        let request = CallbackRequestType::KubernetesGetResource {
+            api_version: "v1".to_string(),
+            kind: "Pod".to_string(),
+            name: "nginx".to_string(),
+            namespace: Some("default".to_string()),
+            disable_cache: false,
+        };
+        assert!(find_response(&mocks(), &request).unwrap().is_none());
+    }
+}
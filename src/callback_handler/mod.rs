@@ -4,66 +4,113 @@ use anyhow::Result;
 use policy_evaluator::{callback_requests::CallbackRequest, kube};
 use tokio::sync::{mpsc, oneshot};
 
+mod allow_list;
+mod mock;
 mod proxy;
+mod session;
 
 use crate::{
     callback_handler::proxy::CallbackHandlerProxy,
     config::{HostCapabilitiesMode, pull_and_run::PullAndRunSettings},
 };
+pub(crate) use allow_list::HostCapability;
+pub(crate) use mock::read_mocks_file;
 
 #[derive(Clone)]
 pub(crate) enum ProxyMode {
-    Record { destination: PathBuf },
-    Replay { source: PathBuf },
+    Record {
+        destination: PathBuf,
+        /// Shared by all the proxies writing to `destination`
+        recorded_session: session::RecordedSession,
+    },
+    Replay {
+        source: PathBuf,
+    },
+    Mock {
+        mocks: Vec<mock::Mock>,
+        /// When enabled, the requests that do not match any mock
+        /// are rejected instead of being handled by the host
+        strict: bool,
+    },
 }
 
 /// This is an abstraction over the callback_handler provided by the
 /// policy_evaluator crate.
 /// The goal is to allow kwctl to have a proxy handler, that can
 /// record and reply any kind of policy <-> host capability exchange
-pub(crate) enum CallbackHandler {
+pub(crate) struct CallbackHandler {
+    handler: Handler,
+    /// When some host capabilities are denied, the policy sends its
+    /// requests to this channel. The requests are checked against the
+    /// allow list before being forwarded to `handler`
+    filter_channel: Option<mpsc::Sender<CallbackRequest>>,
+}
+
+enum Handler {
     Direct(policy_evaluator::callback_handler::CallbackHandler),
     Proxy(proxy::CallbackHandlerProxy),
 }
 
 impl CallbackHandler {
     pub async fn new(
+        policy_id: &str,
         cfg: &PullAndRunSettings,
         kube_client: Option<kube::Client>,
         shutdown_channel_rx: oneshot::Receiver<()>,
     ) -> Result<CallbackHandler> {
-        match &cfg.host_capabilities_mode {
+        let handler = match &cfg.host_capabilities_mode {
             HostCapabilitiesMode::Proxy(proxy_mode) => {
-                new_proxy(proxy_mode, cfg, kube_client, shutdown_channel_rx).await
+                new_proxy(policy_id, proxy_mode, cfg, kube_client, shutdown_channel_rx).await?
             }
             HostCapabilitiesMode::Direct => {
-                new_transparent(cfg, kube_client, shutdown_channel_rx).await
+                new_transparent(cfg, kube_client, shutdown_channel_rx).await?
             }
-        }
+        };
+
+        let filter_channel = cfg
+            .allowed_host_capabilities
+            .as_ref()
+            .map(|allowed| allow_list::spawn_filter(allowed.clone(), handler.sender_channel()));
+
+        Ok(CallbackHandler {
+            handler,
+            filter_channel,
+        })
     }
 
     pub fn sender_channel(&self) -> mpsc::Sender<CallbackRequest> {
-        match self {
-            CallbackHandler::Direct(handler) => handler.sender_channel(),
-            CallbackHandler::Proxy(handler) => handler.sender_channel(),
+        match &self.filter_channel {
+            Some(filter_channel) => filter_channel.clone(),
+            None => self.handler.sender_channel(),
         }
     }
 
     pub async fn loop_eval(self) {
+        match self.handler {
+            Handler::Direct(mut handler) => handler.loop_eval().await,
+            Handler::Proxy(mut handler) => handler.loop_eval().await,
+        }
+    }
+}
+
+impl Handler {
+    fn sender_channel(&self) -> mpsc::Sender<CallbackRequest> {
         match self {
-            CallbackHandler::Direct(mut handler) => handler.loop_eval().await,
-            CallbackHandler::Proxy(mut handler) => handler.loop_eval().await,
+            Handler::Direct(handler) => handler.sender_channel(),
+            Handler::Proxy(handler) => handler.sender_channel(),
         }
     }
 }
 
 async fn new_proxy(
+    policy_id: &str,
     mode: &ProxyMode,
     cfg: &PullAndRunSettings,
     kube_client: Option<kube::Client>,
     shutdown_channel_rx: oneshot::Receiver<()>,
-) -> Result<CallbackHandler> {
+) -> Result<Handler> {
     let proxy = CallbackHandlerProxy::new(
+        policy_id,
         mode,
         shutdown_channel_rx,
         cfg.sources.clone(),
@@ -72,14 +119,14 @@ async fn new_proxy(
     )
     .await?;
 
-    Ok(CallbackHandler::Proxy(proxy))
+    Ok(Handler::Proxy(proxy))
 }
 
 async fn new_transparent(
     cfg: &PullAndRunSettings,
     kube_client: Option<kube::Client>,
     shutdown_channel_rx: oneshot::Receiver<()>,
-) -> Result<CallbackHandler> {
+) -> Result<Handler> {
     let mut callback_handler_builder =
         policy_evaluator::callback_handler::CallbackHandlerBuilder::new(shutdown_channel_rx)
             .registry_config(cfg.sources.clone())
@@ -90,5 +137,5 @@ async fn new_transparent(
 
     let real_callback_handler = callback_handler_builder.build().await?;
 
-    Ok(CallbackHandler::Direct(real_callback_handler))
+    Ok(Handler::Direct(real_callback_handler))
 }
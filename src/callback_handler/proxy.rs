@@ -1,4 +1,7 @@
-use super::ProxyMode;
+use super::{
+    ProxyMode, mock,
+    session::{Exchange, Response, SessionFile},
+};
 use anyhow::{Result, anyhow};
 use policy_evaluator::{
     callback_handler::CallbackHandlerBuilder,
@@ -6,28 +9,17 @@ use policy_evaluator::{
     kube,
     policy_fetcher::{sigstore::trust::sigstore::SigstoreTrustRoot, sources::Sources},
 };
-use serde::{Deserialize, Serialize};
-use std::{collections::VecDeque, fs::File, path::PathBuf, sync::Arc};
+use std::{collections::VecDeque, sync::Arc};
 use tokio::sync::{mpsc, oneshot};
 use tracing::{error, info, warn};
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(tag = "type")]
-enum Response {
-    Success { payload: String },
-    Error { message: String },
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(tag = "type")]
-struct Exchange {
-    pub request: String,
-    pub response: Response,
-}
-
 /// A proxy against a `policy_evaluator::CallbackHandler`
-/// Can record guest requests, save them to file and reply them back
+/// Can record guest requests, save them to file and reply them back.
+/// It can also answer guest requests using user defined mocks
 pub(crate) struct CallbackHandlerProxy {
+    /// The id of the policy being evaluated. Used to keep the exchanges
+    /// of the different policies separated inside of the session file.
+    policy_id: String,
     sources: Option<Sources>,
     sigstore_trust_root: Option<Arc<SigstoreTrustRoot>>,
     kube_client: Option<kube::Client>,
@@ -50,6 +42,7 @@ pub(crate) struct CallbackHandlerProxy {
 
 impl CallbackHandlerProxy {
     pub async fn new(
+        policy_id: &str,
         mode: &ProxyMode,
         shutdown_channel: oneshot::Receiver<()>,
         sources: Option<Sources>,
@@ -63,6 +56,7 @@ impl CallbackHandlerProxy {
         let (tx, rx) = mpsc::channel(200);
 
         Ok(Self {
+            policy_id: policy_id.to_owned(),
             mode: mode.to_owned(),
             tx,
             rx,
@@ -126,10 +120,20 @@ impl CallbackHandlerProxy {
         self.recorded_exchanges.push(exchange);
     }
 
-    /// Write all the captured exchange messages to a file
+    /// Add all the captured exchange messages to the recorded session,
+    /// then write the whole session to a file.
     /// An error message is print to the stderr if there was some
     /// recording error
-    fn dump_records(&self, destination: &PathBuf) {
+    fn dump_records(&mut self) {
+        let ProxyMode::Record {
+            destination,
+            recorded_session,
+        } = &self.mode
+        else {
+            // this should never happen
+            unreachable!()
+        };
+
         let errors: Vec<&anyhow::Error> = self
             .recorded_exchanges
             .iter()
@@ -138,26 +142,35 @@ impl CallbackHandlerProxy {
 
         if !errors.is_empty() {
             error!(errors = ?errors, "Cannot record communication between host and policy, something went wrong while capturing the exchange");
-        } else {
-            let exchanges: Vec<&Exchange> = self
-                .recorded_exchanges
-                .iter()
-                .filter_map(|exchange| exchange.as_ref().ok())
-                .collect();
-            match File::create(destination) {
-                Err(e) => error!(e = ?e, ?destination, "Cannot save context aware session to file"),
-                Ok(file) => match serde_yaml::to_writer(file, &exchanges) {
-                    Ok(_) => info!(?destination, "Context aware session saved to file"),
-                    Err(e) => error!(error = ?e, "Cannot save context aware session to file"),
-                },
-            }
+            return;
+        }
+
+        let exchanges: Vec<Exchange> = std::mem::take(&mut self.recorded_exchanges)
+            .into_iter()
+            .filter_map(|exchange| exchange.ok())
+            .collect();
+
+        let mut session = recorded_session
+            .lock()
+            .expect("cannot lock the recorded session");
+        if session.insert(self.policy_id.clone(), exchanges).is_some() {
+            warn!(
+                policy_id = self.policy_id.as_str(),
+                "Multiple policies share the same id, only the exchanges of the last one are kept"
+            );
+        }
+
+        match SessionFile::save(destination, &session) {
+            Ok(_) => info!(?destination, "Context aware session saved to file"),
+            Err(e) => error!(error = ?e, ?destination, "Cannot save context aware session to file"),
         }
     }
 
     pub async fn loop_eval(&mut self) {
         match &self.mode {
-            ProxyMode::Record { destination: _ } => self.loop_eval_recoder().await,
-            ProxyMode::Replay { source: _ } => self.loop_eval_replay().await,
+            ProxyMode::Record { .. } => self.loop_eval_recoder().await,
+            ProxyMode::Replay { .. } => self.loop_eval_replay().await,
+            ProxyMode::Mock { .. } => self.loop_eval_mock().await,
         }
     }
 
@@ -169,11 +182,9 @@ impl CallbackHandlerProxy {
         // there's no nice way to handle errors here.
 
         let mut exchanges: VecDeque<Exchange> = if let ProxyMode::Replay { source } = &self.mode {
-            let file = File::open(source).unwrap_or_else(|_| {
-                panic!("Cannot open host capabilities interactions file {source:?}")
-            });
-            serde_yaml::from_reader(file)
-                .unwrap_or_else(|_| panic!("cannot deserialize contents of {source:?}"))
+            SessionFile::load(source)
+                .unwrap_or_else(|e| panic!("{e}"))
+                .into_exchanges(&self.policy_id)
         } else {
             // this should never happen
             unreachable!()
@@ -210,12 +221,7 @@ impl CallbackHandlerProxy {
                 let expected_request: CallbackRequestType = serde_yaml::from_str(&exchange.request)
                     .expect("Cannot deserialize recorded request into `CallbackRequestType`");
                 if expected_request == req.request {
-                    match exchange.response {
-                        Response::Success { payload } => Ok(CallbackResponse {
-                            payload: payload.into_bytes(),
-                        }),
-                        Response::Error { message } => Err(anyhow!("{message}")),
-                    }
+                    exchange.response.into_callback_response()
                 } else {
                     Err(anyhow!(
                         "Replay error: unexpected request. Was expecting {:?}, got {:?} instead",
@@ -227,8 +233,11 @@ impl CallbackHandlerProxy {
         }
     }
 
-    /// The code used by the handler when running in `record` mode
-    async fn loop_eval_recoder(&mut self) {
+    /// Build the real CallbackHandler and spawn the tokio task running it.
+    ///
+    /// Returns the channel used to send requests to the real CallbackHandler,
+    /// plus the channel used to stop its tokio task
+    async fn start_callback_handler(&self) -> (mpsc::Sender<CallbackRequest>, oneshot::Sender<()>) {
         // This is a channel used to stop the tokio task that is run
         // inside of the CallbackHandler
         let (callback_handler_shutdown_channel_tx, callback_handler_shutdown_channel_rx) =
@@ -254,16 +263,57 @@ impl CallbackHandlerProxy {
             callback_handler.loop_eval().await;
         });
 
+        (
+            callback_handler_sender,
+            callback_handler_shutdown_channel_tx,
+        )
+    }
+
+    /// Send the request to the real CallbackHandler and wait for its response
+    async fn forward_request(
+        callback_handler_sender: &mpsc::Sender<CallbackRequest>,
+        request: CallbackRequestType,
+    ) -> Result<CallbackResponse> {
+        // Create a CallbackRequest object based on the incoming
+        // request. This is sent to the real CallbackHandler,
+        // we have to provide a different `response_channel`
+        // because we want to intercept the response
+        let (response_tx, response_rx) = oneshot::channel::<Result<CallbackResponse>>();
+        let proxy_req = CallbackRequest {
+            request,
+            response_channel: response_tx,
+        };
+
+        // forward the message to the real CallbackHandler,
+        // here we panic if the message cannot be sent. There's
+        // no purpose in going forward if the communication
+        // with the real CallbackHandler doesn't work
+        callback_handler_sender
+            .send(proxy_req)
+            .await
+            .expect("cannot forward request to real callback handler");
+
+        // same here, if we cannot get a response from the
+        // real CallbackHandler there's no reason to keep
+        // going. We can interrupt the execution if something
+        // goes wrong.
+        response_rx
+            .await
+            .expect("failure while waiting for response from real callback_handler")
+    }
+
+    /// The code used by the handler when running in `record` mode
+    async fn loop_eval_recoder(&mut self) {
+        let (callback_handler_sender, callback_handler_shutdown_channel_tx) =
+            self.start_callback_handler().await;
+
         // loop of the proxy handler
         loop {
             tokio::select! {
                 // place the shutdown check before the message evaluation,
                 // as recommended by tokio's documentation about select!
                 _ = &mut self.shutdown_channel => {
-                    match &self.mode {
-                        ProxyMode::Record{destination} =>  self.dump_records(destination),
-                        _ => unreachable!()
-                    }
+                    self.dump_records();
                     if let Err(e) = callback_handler_shutdown_channel_tx.send(()) {
                         error!(error = ?e, "Cannot shutdown the real callback_handler");
                     }
@@ -287,32 +337,7 @@ impl CallbackHandlerProxy {
                                 anyhow!("cannot convert request to yaml: {}", e)
                             });
 
-                        // Create a CallbackRequest object based on the incoming
-                        // request. This is sent to the real CallbackHandler,
-                        // we have to provide a different `response_channel`
-                        // because we want to intercept the response
-                        let (response_tx, response_rx) = oneshot::channel::<Result<CallbackResponse>>();
-                        let proxy_req = CallbackRequest {
-                            request: req.request,
-                            response_channel: response_tx,
-                        };
-
-                        // forward the message to the real CallbackHandler,
-                        // here we panic if the message cannot be sent. There's
-                        // no purpose in going forward if the communication
-                        // with the real CallbackHandler doesn't work
-                        callback_handler_sender
-                            .send(proxy_req)
-                            .await
-                            .expect("cannot forward request to real callback handler");
-
-                        // same here, if we cannot get a response from the
-                        // real CallbackHandler there's no reason to keep
-                        // going. We can interrupt the execution if something
-                        // goes wrong.
-                        let response = response_rx
-                            .await
-                            .expect("failure while waiting for response from real callback_handler");
+                        let response = Self::forward_request(&callback_handler_sender, req.request).await;
 
                         self.record_exchange(request, response.as_ref());
 
@@ -327,6 +352,61 @@ impl CallbackHandlerProxy {
             }
         }
     }
+
+    /// The code used by the handler when running in `mock` mode
+    async fn loop_eval_mock(&mut self) {
+        let (mocks, strict) = if let ProxyMode::Mock { mocks, strict } = &self.mode {
+            (mocks.clone(), *strict)
+        } else {
+            // this should never happen
+            unreachable!()
+        };
+
+        // The requests that do not match any mock are handled by the
+        // real CallbackHandler, unless we are running in strict mode
+        let mut real_callback_handler = if strict {
+            None
+        } else {
+            Some(self.start_callback_handler().await)
+        };
+
+        loop {
+            tokio::select! {
+                // place the shutdown check before the message evaluation,
+                // as recommended by tokio's documentation about select!
+                _ = &mut self.shutdown_channel => {
+                    if let Some((_, callback_handler_shutdown_channel_tx)) = real_callback_handler.take()
+                        && let Err(e) = callback_handler_shutdown_channel_tx.send(())
+                    {
+                        error!(error = ?e, "Cannot shutdown the real callback_handler");
+                    }
+                    return;
+                },
+                maybe_req = self.rx.recv() => {
+                    if let Some(req) = maybe_req {
+                        let response = match mock::find_response(&mocks, &req.request) {
+                            Ok(Some(mocked_response)) => mocked_response.into_callback_response(),
+                            Ok(None) => match &real_callback_handler {
+                                Some((callback_handler_sender, _)) => {
+                                    Self::forward_request(callback_handler_sender, req.request).await
+                                }
+                                None => {
+                                    error!(request = ?req.request, "Strict mode: the request does not match any mock");
+                                    Err(anyhow!(
+                                        "Mock error: no mock matches the request {:?}",
+                                        req.request
+                                    ))
+                                }
+                            },
+                            Err(e) => Err(e),
+                        };
+
+                        req.response_channel.send(response).expect("Cannot send back response to policy");
+                    }
+                }
+            }
+        }
+    }
 }
 
 #[cfg(test)]
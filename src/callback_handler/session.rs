@@ -0,0 +1,159 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs::File,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Result, anyhow};
+use policy_evaluator::callback_requests::CallbackResponse;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub(crate) enum Response {
+    Success { payload: String },
+    Error { message: String },
+}
+
+impl Response {
+    /// Converts the response into the value sent back to the policy
+    pub fn into_callback_response(self) -> Result<CallbackResponse> {
+        match self {
+            Response::Success { payload } => Ok(CallbackResponse {
+                payload: payload.into_bytes(),
+            }),
+            Response::Error { message } => Err(anyhow!("{message}")),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub(crate) struct Exchange {
+    pub request: String,
+    pub response: Response,
+}
+
+/// The exchanges recorded during a `kwctl` invocation, indexed by the id
+/// of the policy that performed them.
+///
+/// All the proxies created while running a YAML file holding multiple
+/// policies share the same instance, each one of them adds its own
+/// exchanges once the evaluation is done.
+pub(crate) type RecordedSession = Arc<Mutex<BTreeMap<String, Vec<Exchange>>>>;
+
+/// The contents of a host capabilities session file
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum SessionFile {
+    /// Exchanges grouped by the id of the policy that performed them.
+    /// This is the format written by the `record` mode.
+    ByPolicy(BTreeMap<String, Vec<Exchange>>),
+    /// A plain list of exchanges, the format used by older versions
+    /// of kwctl. The same exchanges are replayed to every policy.
+    Flat(Vec<Exchange>),
+}
+
+impl SessionFile {
+    pub fn load(source: &Path) -> Result<Self> {
+        let file = File::open(source).map_err(|e| {
+            anyhow!(
+                "Cannot open host capabilities interactions file {:?}: {}",
+                source,
+                e
+            )
+        })?;
+        serde_yaml::from_reader(file)
+            .map_err(|e| anyhow!("cannot deserialize contents of {:?}: {}", source, e))
+    }
+
+    pub fn save(destination: &Path, session: &BTreeMap<String, Vec<Exchange>>) -> Result<()> {
+        let file = File::create(destination)?;
+        serde_yaml::to_writer(file, session)?;
+        Ok(())
+    }
+
+    /// Returns the exchanges that have to be replayed to the given policy
+    pub fn into_exchanges(self, policy_id: &str) -> VecDeque<Exchange> {
+        match self {
+            SessionFile::Flat(exchanges) => exchanges.into(),
+            SessionFile::ByPolicy(mut policies) => policies.remove(policy_id).map_or_else(
+                || {
+                    warn!(
+                        policy_id,
+                        "The session file does not contain any exchange for this policy"
+                    );
+                    VecDeque::new()
+                },
+                |exchanges| exchanges.into(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exchange(payload: &str) -> Exchange {
+        Exchange {
+            request: "!DNSLookupHost\nhost: kubewarden.io\n".to_string(),
+            response: Response::Success {
+                payload: payload.to_string(),
+            },
+        }
+    }
+
+    fn payloads(exchanges: &VecDeque<Exchange>) -> Vec<&str> {
+        exchanges
+            .iter()
+            .map(|exchange| match &exchange.response {
+                Response::Success { payload } => payload.as_str(),
+                Response::Error { message } => message.as_str(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn flat_session_is_replayed_to_every_policy() {
+        let session = serde_yaml::to_string(&vec![exchange("first")]).unwrap();
+
+        for policy_id in ["policy-a", "policy-b"] {
+            let session_file: SessionFile = serde_yaml::from_str(&session).unwrap();
+            assert!(matches!(session_file, SessionFile::Flat(_)));
+            assert_eq!(
+                payloads(&session_file.into_exchanges(policy_id)),
+                vec!["first"]
+            );
+        }
+    }
+
+    #[test]
+    fn session_by_policy_round_trip() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let destination = tempdir.path().join("session.yml");
+
+        let recorded = BTreeMap::from([
+            ("policy-a".to_string(), vec![exchange("a1"), exchange("a2")]),
+            ("policy-b".to_string(), vec![exchange("b1")]),
+        ]);
+        SessionFile::save(&destination, &recorded).unwrap();
+
+        let exchanges = SessionFile::load(&destination)
+            .unwrap()
+            .into_exchanges("policy-a");
+        assert_eq!(payloads(&exchanges), vec!["a1", "a2"]);
+
+        let exchanges = SessionFile::load(&destination)
+            .unwrap()
+            .into_exchanges("policy-b");
+        assert_eq!(payloads(&exchanges), vec!["b1"]);
+
+        let exchanges = SessionFile::load(&destination)
+            .unwrap()
+            .into_exchanges("policy-c");
+        assert!(exchanges.is_empty());
+    }
+}
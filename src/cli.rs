@@ -61,7 +61,7 @@ fn pull_shared_flags() -> Vec<Arg> {
         Arg::new("verification-config-path")
             .long("verification-config-path")
             .value_name("PATH")
-            .help("YAML file holding verification config information (signatures, public keys...)"),
+            .help("YAML file holding verification config information (signatures, public keys, attestations...)"),
         Arg::new("verification-key")
             .short('k')
             .long("verification-key")
@@ -96,6 +96,36 @@ fn pull_shared_flags() -> Vec<Arg> {
             .number_of_values(1)
             .value_name("VALUE")
             .help("GitHub repository expected in the certificates generated in CD pipelines"),
+        Arg::new("cert-identity-prefix")
+            .long("cert-identity-prefix")
+            .number_of_values(1)
+            .value_name("PREFIX")
+            .conflicts_with_all(["cert-email", "cert-identity-regexp"])
+            .help("Prefix of the URI identity expected in Fulcio certificates, matched on path segment boundaries"),
+        Arg::new("cert-identity-regexp")
+            .long("cert-identity-regexp")
+            .number_of_values(1)
+            .value_name("REGEX")
+            .conflicts_with("cert-email")
+            .help("Regular expression matching the email or URI identity expected in Fulcio certificates"),
+        Arg::new("cert-oidc-issuer-regexp")
+            .long("cert-oidc-issuer-regexp")
+            .number_of_values(1)
+            .value_name("REGEX")
+            .conflicts_with("cert-oidc-issuer")
+            .help("Regular expression matching the OIDC issuer expected in Fulcio certificates"),
+        Arg::new("github-owner-regexp")
+            .long("github-owner-regexp")
+            .number_of_values(1)
+            .value_name("REGEX")
+            .conflicts_with("github-owner")
+            .help("Regular expression matching the GitHub owner expected in the certificates generated in CD pipelines"),
+        Arg::new("github-repo-regexp")
+            .long("github-repo-regexp")
+            .number_of_values(1)
+            .value_name("REGEX")
+            .conflicts_with("github-repo")
+            .help("Regular expression matching the GitHub repository expected in the certificates generated in CD pipelines"),
        Arg::new("sigstore-trust-config")
            .long("sigstore-trust-config")
            .value_parser(value_parser!(PathBuf))
@@ -106,17 +136,23 @@ fn pull_shared_flags() -> Vec<Arg> {
 
 fn subcommand_pull() -> Command {
     let mut args = pull_shared_flags();
-    args.extend_from_slice(&[Arg::new("output-path")
-        .short('o')
-        .long("output-path")
-        .value_name("PATH")
-        .help("Output file. If not provided will be downloaded to the Kubewarden store")]);
+    args.extend_from_slice(&[
+        Arg::new("output-path")
+            .short('o')
+            .long("output-path")
+            .value_name("PATH")
+            .help("Output file. If not provided will be downloaded to the Kubewarden store"),
+        Arg::new("sha256")
+            .long("sha256")
+            .value_name("DIGEST")
+            .help("Expected SHA-256 digest of the Wasm module. The policy is not stored when the downloaded module does not match. Useful with https:// policies, which cannot be verified with Sigstore"),
+    ]);
     args.sort_by(|a, b| a.get_id().cmp(b.get_id()));
     args.push(
         Arg::new("uri")
             .required(true)
             .index(1)
-            .help("Policy URI. Supported schemes: registry://, https://, file://"),
+            .help("Policy URI. Supported schemes: registry://, https://, file://, oci-layout://"),
     );
 
     Command::new("pull")
@@ -126,6 +162,24 @@ fn subcommand_pull() -> Command {
 
 fn subcommand_verify() -> Command {
     let mut args = vec![
+        Arg::new("bundle")
+            .long("bundle")
+            .value_name("PATH")
+            .value_parser(value_parser!(PathBuf))
+            .requires("sigstore-trust-config")
+            .conflicts_with_all(["offline", "output"])
+            .help("Sigstore bundle of a file:// policy. The signature, the certificate identity and the transparency log inclusion proof are verified against the --sigstore-trust-config, without any network access"),
+        Arg::new("offline")
+            .long("offline")
+            .num_args(0)
+            .help("Verify a policy of the store using the signatures loaded from an air-gap bundle, without any network access. Keyless signatures require a --sigstore-trust-config"),
+        Arg::new("output")
+            .long("output")
+            .short('o')
+            .value_name("FORMAT")
+            .value_parser(PossibleValuesParser::new(["text", "json"]))
+            .default_value("text")
+            .help("Output format. json reports which signature satisfied each constraint of the verification config"),
         Arg::new("docker-config-json-path")
             .long("docker-config-json-path")
             .value_name("PATH")
@@ -137,7 +191,7 @@ fn subcommand_verify() -> Command {
         Arg::new("verification-config-path")
             .long("verification-config-path")
             .value_name("PATH")
-            .help("YAML file holding verification config information (signatures, public keys...)"),
+            .help("YAML file holding verification config information (signatures, public keys, attestations...)"),
         Arg::new("verification-key")
             .short('k')
             .long("verification-key")
@@ -172,6 +226,36 @@ fn subcommand_verify() -> Command {
             .number_of_values(1)
             .value_name("VALUE")
             .help("GitHub repository expected in the certificates generated in CD pipelines"),
+        Arg::new("cert-identity-prefix")
+            .long("cert-identity-prefix")
+            .number_of_values(1)
+            .value_name("PREFIX")
+            .conflicts_with_all(["cert-email", "cert-identity-regexp"])
+            .help("Prefix of the URI identity expected in Fulcio certificates, matched on path segment boundaries"),
+        Arg::new("cert-identity-regexp")
+            .long("cert-identity-regexp")
+            .number_of_values(1)
+            .value_name("REGEX")
+            .conflicts_with("cert-email")
+            .help("Regular expression matching the email or URI identity expected in Fulcio certificates"),
+        Arg::new("cert-oidc-issuer-regexp")
+            .long("cert-oidc-issuer-regexp")
+            .number_of_values(1)
+            .value_name("REGEX")
+            .conflicts_with("cert-oidc-issuer")
+            .help("Regular expression matching the OIDC issuer expected in Fulcio certificates"),
+        Arg::new("github-owner-regexp")
+            .long("github-owner-regexp")
+            .number_of_values(1)
+            .value_name("REGEX")
+            .conflicts_with("github-owner")
+            .help("Regular expression matching the GitHub owner expected in the certificates generated in CD pipelines"),
+        Arg::new("github-repo-regexp")
+            .long("github-repo-regexp")
+            .number_of_values(1)
+            .value_name("REGEX")
+            .conflicts_with("github-repo")
+            .help("Regular expression matching the GitHub repository expected in the certificates generated in CD pipelines"),
        Arg::new("sigstore-trust-config")
            .long("sigstore-trust-config")
            .value_parser(value_parser!(PathBuf))
@@ -183,7 +267,7 @@ fn subcommand_verify() -> Command {
         Arg::new("uri")
             .required(true)
             .index(1)
-            .help("Policy URI. Supported schemes: registry://"),
+            .help("Policy URI. Supported schemes: registry://, and file:// together with --bundle"),
     );
 
     Command::new("verify")
@@ -238,8 +322,30 @@ renovatebot to detect policy updates."#,
         .args(args)
 }
 
-fn run_args() -> Vec<Arg> {
+fn kube_client_args() -> Vec<Arg> {
     vec![
+        Arg::new("kubeconfig")
+            .long("kubeconfig")
+            .value_name("PATH")
+            .help("Path to the kubeconfig file to use when connecting to Kubernetes. Defaults to the usual kubeconfig lookup"),
+        Arg::new("context")
+            .long("context")
+            .value_name("NAME")
+            .help("The kubeconfig context to use when connecting to Kubernetes"),
+        Arg::new("as")
+            .long("as")
+            .value_name("USER")
+            .help("Username to impersonate when connecting to Kubernetes. For example, `system:serviceaccount:kubewarden:policy-server-default` performs the lookups with the identity of the default Policy Server"),
+        Arg::new("as-group")
+            .long("as-group")
+            .value_name("GROUP")
+            .action(ArgAction::Append)
+            .help("Group to impersonate when connecting to Kubernetes. Can be repeated to specify multiple groups"),
+    ]
+}
+
+fn run_args() -> Vec<Arg> {
+    let mut args = vec![
        Arg::new("docker-config-json-path")
            .long("docker-config-json-path")
            .value_name("PATH")
@@ -251,7 +357,7 @@ fn run_args() -> Vec<Arg> {
        Arg::new("verification-config-path")
            .long("verification-config-path")
            .value_name("PATH")
-           .help("YAML file holding verification config information (signatures, public keys...)"),
+           .help("YAML file holding verification config information (signatures, public keys, attestations...)"),
        Arg::new("request-path")
            .long("request-path")
            .short('r')
@@ -301,6 +407,36 @@ fn run_args() -> Vec<Arg> {
            .number_of_values(1)
            .value_name("VALUE")
            .help("GitHub repository expected in the certificates generated in CD pipelines"),
+       Arg::new("cert-identity-prefix")
+           .long("cert-identity-prefix")
+           .number_of_values(1)
+           .value_name("PREFIX")
+           .conflicts_with_all(["cert-email", "cert-identity-regexp"])
+           .help("Prefix of the URI identity expected in Fulcio certificates, matched on path segment boundaries"),
+       Arg::new("cert-identity-regexp")
+           .long("cert-identity-regexp")
+           .number_of_values(1)
+           .value_name("REGEX")
+           .conflicts_with("cert-email")
+           .help("Regular expression matching the email or URI identity expected in Fulcio certificates"),
+       Arg::new("cert-oidc-issuer-regexp")
+           .long("cert-oidc-issuer-regexp")
+           .number_of_values(1)
+           .value_name("REGEX")
+           .conflicts_with("cert-oidc-issuer")
+           .help("Regular expression matching the OIDC issuer expected in Fulcio certificates"),
+       Arg::new("github-owner-regexp")
+           .long("github-owner-regexp")
+           .number_of_values(1)
+           .value_name("REGEX")
+           .conflicts_with("github-owner")
+           .help("Regular expression matching the GitHub owner expected in the certificates generated in CD pipelines"),
+       Arg::new("github-repo-regexp")
+           .long("github-repo-regexp")
+           .number_of_values(1)
+           .value_name("REGEX")
+           .conflicts_with("github-repo")
+           .help("Regular expression matching the GitHub repository expected in the certificates generated in CD pipelines"),
        Arg::new("execution-mode")
            .long("execution-mode")
            .short('e')
@@ -325,6 +461,8 @@ fn run_args() -> Vec<Arg> {
            .value_name("FILE")
            .long_help(r#"Record all the policy and host capabilities
 communications to the given file.
+When running a YAML file holding multiple policies, the exchanges
+of each policy are stored separately, indexed by the policy name.
 Useful to be combined later with '--replay-host-capabilities-interactions' flag"#),
        Arg::new("replay-host-capabilities-interactions")
            .long("replay-host-capabilities-interactions")
@@ -333,12 +471,50 @@ Useful to be combined later with '--replay-host-capabilities-interactions' flag"
 the host replays back the answers found inside of the provided file.
 This is useful to test policies in a reproducible way, given no external
 interactions with OCI registries, DNS, Kubernetes are performed."#),
+       Arg::new("host-capabilities-mocks")
+           .long("host-capabilities-mocks")
+           .value_name("FILE")
+           .long_help(r#"Answer the policy and host capabilities exchanges
+using the mocks defined inside of the provided file.
+Each mock matches requests by their type and, optionally, by their
+fields. String values can use the `*` and `?` wildcards.
+Requests that do not match any mock are handled by the host."#),
+       Arg::new("host-capabilities-mocks-strict")
+           .long("host-capabilities-mocks-strict")
+           .num_args(0)
+           .requires("host-capabilities-mocks")
+           .help("Reject the host capabilities requests that do not match any of the mocks"),
+       Arg::new("locked")
+           .long("locked")
+           .num_args(0)
+           .help("Refuse to run the modules whose digest differs from the one pinned by the lock file of the YAML file. Registry modules are checked against their manifest digest, https:// modules against the SHA-256 of the Wasm module. Create the lock file using `kwctl lock`"),
+       Arg::new("require-verified")
+           .long("require-verified")
+           .num_args(0)
+           .help("Refuse to run the policies whose provenance record does not report a successful Sigstore verification"),
+       Arg::new("allow-host-capabilities")
+           .long("allow-host-capabilities")
+           .value_name("CAPABILITIES")
+           .value_delimiter(',')
+           .action(ArgAction::Append)
+           .value_parser(PossibleValuesParser::new(["dns", "oci", "sigstore", "kubernetes"]))
+           .conflicts_with("deny-host-capabilities")
+           .help("Comma separated list of the host capabilities the policy is allowed to use. All the other host capabilities requests are rejected. The crypto host capabilities are always available, they cannot be restricted"),
+       Arg::new("deny-host-capabilities")
+           .long("deny-host-capabilities")
+           .value_name("CAPABILITIES")
+           .value_delimiter(',')
+           .action(ArgAction::Append)
+           .value_parser(PossibleValuesParser::new(["dns", "oci", "sigstore", "kubernetes"]))
+           .help("Comma separated list of the host capabilities the policy is not allowed to use. The crypto host capabilities are always available, they cannot be restricted"),
        Arg::new("sigstore-trust-config")
            .long("sigstore-trust-config")
            .value_parser(value_parser!(PathBuf))
            .value_name("PATH")
            .help("JSON-formatted file conforming to the ClientTrustConfig message in the Sigstore protobuf specs. This file configures the entire Sigstore instance state, including the URIs used to access the CA and artifact transparency services as well as the cryptographic root of trust itself"),
-    ]
+    ];
+    args.extend(kube_client_args());
+    args
 }
 
 fn subcommand_run() -> Command {
@@ -348,7 +524,7 @@ fn subcommand_run() -> Command {
         Arg::new("uri_or_sha_prefix_or_yaml_file")
             .required(true)
             .index(1)
-            .help("Policy URI, SHA prefix or YAML file containing Kubewarden policy resources. Supported schemes: registry://, https://, file://. If schema is omitted, file:// is assumed, rooted on the current directory.")
+            .help("Policy URI, SHA prefix or YAML file containing Kubewarden policy resources. Supported schemes: registry://, https://, file://, oci-layout://. If schema is omitted, file:// is assumed, rooted on the current directory.")
     );
 
     Command::new("run")
@@ -365,6 +541,7 @@ fn subcommand_run() -> Command {
             ArgGroup::new("host-capabilities-proxy").args([
                 "record-host-capabilities-interactions",
                 "replay-host-capabilities-interactions",
+                "host-capabilities-mocks",
             ]),
         )
 }
@@ -428,7 +605,7 @@ fn subcommand_inspect() -> Command {
         Arg::new("uri_or_sha_prefix")
             .required(true)
             .index(1)
-            .help("Policy URI or SHA prefix. Supported schemes: registry://, https://, file://. If schema is omitted, file:// is assumed, rooted on the current directory."),
+            .help("Policy URI or SHA prefix. Supported schemes: registry://, https://, file://, oci-layout://. If schema is omitted, file:// is assumed, rooted on the current directory."),
     );
 
     Command::new("inspect")
@@ -496,7 +673,7 @@ fn subcommand_scaffold() -> Command {
         Arg::new("uri_or_sha_prefix")
             .required(true)
             .index(1)
-            .help("Policy URI or SHA prefix. Supported schemes: registry://, https://, file://. If schema is omitted, file:// is assumed, rooted on the current directory."),
+            .help("Policy URI or SHA prefix. Supported schemes: registry://, https://, file://, oci-layout://. If schema is omitted, file:// is assumed, rooted on the current directory."),
     );
 
     let mut vap_args = vec![
@@ -537,6 +714,7 @@ fn subcommand_scaffold() -> Command {
             .value_name("PATH")
             .help("The file containing the existing object"),
     ];
+    admission_request_args.extend(kube_client_args());
     admission_request_args.sort_by(|a, b| a.get_id().cmp(b.get_id()));
 
     let mut subcommands = vec![
@@ -583,6 +761,66 @@ fn subcommand_digest() -> Command {
         .args(args)
 }
 
+fn subcommand_sign() -> Command {
+    let mut args = vec![
+        Arg::new("annotation")
+            .short('a')
+            .long("annotation")
+            .action(ArgAction::Append)
+            .number_of_values(1)
+            .value_name("KEY=VALUE")
+            .help("Annotation in key=value format added to the signature. Can be repeated multiple times"),
+        Arg::new("docker-config-json-path")
+            .long("docker-config-json-path")
+            .value_name("PATH")
+            .help("Path to a directory containing the Docker 'config.json' file. Can be used to indicate registry authentication details"),
+        Arg::new("key")
+            .short('k')
+            .long("key")
+            .value_name("PATH")
+            .required(true)
+            .help("Path to the private key used to sign the policy"),
+        Arg::new("sources-path")
+            .long("sources-path")
+            .value_name("PATH")
+            .help("YAML file holding source information (https, registry insecure hosts, custom CA's...)"),
+    ];
+    args.sort_by(|a, b| a.get_id().cmp(b.get_id()));
+    args.push(
+        Arg::new("uri")
+            .required(true)
+            .index(1)
+            .help("Policy URI. Supported schemes: registry://"),
+    );
+
+    Command::new("sign")
+        .about("Signs a Kubewarden policy pushed to an OCI registry with a private key")
+        .after_long_help(
+            r#"The manifest digest of the policy is signed and the signature is pushed to the registry, next to the policy.
+The signature is compatible with cosign and can be verified with `kwctl verify -k <public key>`.
+
+The private key can be created by `kwctl generate-key-pair` or `cosign generate-key-pair`. The password of
+encrypted keys is read from the COSIGN_PASSWORD environment variable."#,
+        )
+        .args(args)
+}
+
+fn subcommand_generate_key_pair() -> Command {
+    Command::new("generate-key-pair")
+        .about("Generates a key pair to sign policies")
+        .after_long_help(
+            r#"The private key is encrypted with the password read from the COSIGN_PASSWORD environment variable.
+The keys are compatible with cosign."#,
+        )
+        .arg(
+            Arg::new("output-key-prefix")
+                .long("output-key-prefix")
+                .value_name("PREFIX")
+                .default_value("cosign")
+                .help("Prefix of the generated files, the private key is written to <PREFIX>.key and the public key to <PREFIX>.pub"),
+        )
+}
+
 fn subcommand_bench() -> Command {
     let mut args = vec![
         Arg::new("measurement_time")
@@ -616,7 +854,7 @@ fn subcommand_bench() -> Command {
         Arg::new("uri_or_sha_prefix_or_yaml_file")
             .required(true)
             .index(1)
-            .help("Policy URI, SHA prefix or YAML file containing Kubewarden policy resources. Supported schemes: registry://, https://, file://. If schema is omitted, file:// is assumed, rooted on the current directory.")
+            .help("Policy URI, SHA prefix or YAML file containing Kubewarden policy resources. Supported schemes: registry://, https://, file://, oci-layout://. If schema is omitted, file:// is assumed, rooted on the current directory.")
     );
 
     Command::new("bench")
@@ -633,20 +871,42 @@ fn subcommand_bench() -> Command {
             ArgGroup::new("host-capabilities-proxy").args([
                 "record-host-capabilities-interactions",
                 "replay-host-capabilities-interactions",
+                "host-capabilities-mocks",
             ]),
         )
 }
 
 fn subcommand_save() -> Command {
     Command::new("save")
-        .about("save policies to a tar.gz file")
+        .about("save policies to a tar.gz file or an OCI image layout")
         .arg(
             Arg::new("output")
                 .long("output")
                 .short('o')
                 .required(true)
                 .value_name("FILE")
-                .help("path where the file will be stored"),
+                .help(
+                    "path where the file, or the directory of the OCI image layout, will be stored",
+                ),
+        )
+        .arg(bundle_format_arg())
+        .arg(
+            Arg::new("include-signatures")
+                .long("include-signatures")
+                .num_args(0)
+                .help("Fetch the Sigstore signatures of the registry policies and save them too. They can be used to verify the policies offline"),
+        )
+        .arg(
+            Arg::new("docker-config-json-path")
+                .long("docker-config-json-path")
+                .value_name("PATH")
+                .help("Path to a directory containing the Docker 'config.json' file. Can be used to indicate registry authentication details"),
+        )
+        .arg(
+            Arg::new("sources-path")
+                .long("sources-path")
+                .value_name("PATH")
+                .help("YAML file holding source information (https, registry insecure hosts, custom CA's...)"),
         )
         .arg(
             Arg::new("policies")
@@ -656,6 +916,17 @@ fn subcommand_save() -> Command {
         )
 }
 
+fn bundle_format_arg() -> Arg {
+    Arg::new("format")
+        .long("format")
+        .value_name("FORMAT")
+        .value_parser(PossibleValuesParser::new(["tar.gz", "oci-layout"]))
+        .default_value("tar.gz")
+        .help(
+            "Format of the bundle. oci-layout can be read by other OCI tools, like oras and skopeo",
+        )
+}
+
 fn subcommand_docs() -> Command {
     Command::new("docs")
         .about("Generates the markdown documentation for kwctl commands")
@@ -669,9 +940,259 @@ fn subcommand_docs() -> Command {
         )
 }
 
+fn subcommand_prune() -> Command {
+    let mut args = vec![
+        Arg::new("not-referenced-by")
+            .long("not-referenced-by")
+            .value_name("FILE")
+            .action(ArgAction::Append)
+            .help("Remove the policies that are not referenced by the Kubewarden Custom Resources defined inside of the given YAML file. Can be repeated multiple times"),
+        Arg::new("older-than")
+            .long("older-than")
+            .value_name("DAYS")
+            .value_parser(value_parser!(u64))
+            .help("Remove the policies that have not been pulled or used for more than the given number of days"),
+        Arg::new("keep-latest")
+            .long("keep-latest")
+            .value_name("NUM")
+            .value_parser(value_parser!(usize))
+            .help("Remove all but the latest NUM tags of each policy repository. Tags are ordered by semantic version, then by last usage"),
+        Arg::new("dry-run")
+            .long("dry-run")
+            .num_args(0)
+            .help("List the policies that would be removed, without removing them"),
+    ];
+    args.sort_by(|a, b| a.get_id().cmp(b.get_id()));
+
+    Command::new("prune")
+        .about("Removes the policies matching all the given criteria from the store")
+        .args(args)
+        .group(
+            ArgGroup::new("criteria")
+                .args(["keep-latest", "not-referenced-by", "older-than"])
+                .multiple(true)
+                .required(true),
+        )
+}
+
+fn subcommand_lock() -> Command {
+    let mut args = vec![
+        Arg::new("output")
+            .long("output")
+            .short('o')
+            .value_name("FILE")
+            .help("Path of the lock file. Defaults to the path of the YAML file with the `.lock` suffix"),
+        Arg::new("docker-config-json-path")
+            .long("docker-config-json-path")
+            .value_name("PATH")
+            .help("Path to a directory containing the Docker 'config.json' file. Can be used to indicate registry authentication details"),
+        Arg::new("sources-path")
+            .long("sources-path")
+            .value_name("PATH")
+            .help("YAML file holding source information (https, registry insecure hosts, custom CA's...)"),
+    ];
+    args.sort_by(|a, b| a.get_id().cmp(b.get_id()));
+    args.push(
+        Arg::new("yaml_file")
+            .required(true)
+            .index(1)
+            .help("YAML file containing Kubewarden policy resources"),
+    );
+
+    Command::new("lock")
+        .about("Pins the policy modules of a YAML file to the digest of their OCI manifest")
+        .args(args)
+}
+
+fn subcommand_outdated() -> Command {
+    let mut args = vec![
+        Arg::new("update")
+            .long("update")
+            .num_args(0)
+            .requires("yaml_files")
+            .help("Rewrite the modules referenced by the YAML files to the newest release allowed by --update-level"),
+        Arg::new("update-level")
+            .long("update-level")
+            .value_name("LEVEL")
+            .value_parser(PossibleValuesParser::new(["patch", "minor", "major"]))
+            .default_value("minor")
+            .help("Newest release --update moves the policies to"),
+        Arg::new("docker-config-json-path")
+            .long("docker-config-json-path")
+            .value_name("PATH")
+            .help("Path to a directory containing the Docker 'config.json' file. Can be used to indicate registry authentication details"),
+        Arg::new("sources-path")
+            .long("sources-path")
+            .value_name("PATH")
+            .help("YAML file holding source information (https, registry insecure hosts, custom CA's...)"),
+    ];
+    args.sort_by(|a, b| a.get_id().cmp(b.get_id()));
+    args.push(
+        Arg::new("yaml_files")
+            .num_args(1..)
+            .help("YAML files containing Kubewarden policy resources. When omitted, the policies of the store are checked"),
+    );
+
+    Command::new("outdated")
+        .about("Lists the policies having newer releases")
+        .long_about(
+            r#"Lists the policies having newer releases.
+
+The tags of the registry holding each policy are compared with the one being
+used. Only tags that are semantic versions, optionally prefixed by `v`, are
+taken into account. The newest patch, minor and major releases are reported."#,
+        )
+        .args(args)
+}
+
+fn subcommand_pin() -> Command {
+    let mut args = vec![
+        Arg::new("dry-run")
+            .long("dry-run")
+            .num_args(0)
+            .help("List the modules that would be pinned, without changing the files"),
+        Arg::new("docker-config-json-path")
+            .long("docker-config-json-path")
+            .value_name("PATH")
+            .help("Path to a directory containing the Docker 'config.json' file. Can be used to indicate registry authentication details"),
+        Arg::new("sources-path")
+            .long("sources-path")
+            .value_name("PATH")
+            .help("YAML file holding source information (https, registry insecure hosts, custom CA's...)"),
+    ];
+    args.sort_by(|a, b| a.get_id().cmp(b.get_id()));
+    args.push(
+        Arg::new("yaml_files")
+            .required(true)
+            .num_args(1..)
+            .help("YAML files containing Kubewarden policy resources"),
+    );
+
+    Command::new("pin")
+        .about("Rewrites the policy modules of YAML files to immutable digest references")
+        .long_about(
+            r#"Rewrites the policy modules of YAML files to immutable digest references.
+
+The registry modules of policies and policy group members, like
+`registry://ghcr.io/kubewarden/policies/pod-privileged:v0.2.5`, are turned
+into `ghcr.io/kubewarden/policies/pod-privileged:v0.2.5@sha256:...`.
+The files are changed in place, preserving comments and the order of the
+documents."#,
+        )
+        .args(args)
+}
+
+fn subcommand_mirror() -> Command {
+    let mut args = vec![
+        Arg::new("destination")
+            .long("destination")
+            .value_name("REGISTRY")
+            .required(true)
+            .help("Registry the policies are copied to. It can include a repository prefix, like `harbor.local/kubewarden`"),
+        Arg::new("policies-file")
+            .long("policies-file")
+            .value_name("FILE")
+            .action(ArgAction::Append)
+            .help("File listing the URIs of the policies to mirror, one per line. Lines starting with `#` are ignored. Can be repeated multiple times"),
+        Arg::new("crd")
+            .long("crd")
+            .value_name("FILE")
+            .action(ArgAction::Append)
+            .help("Mirror the policies referenced by the Kubewarden Custom Resources defined inside of the given YAML file. Can be repeated multiple times"),
+        Arg::new("include-signatures")
+            .long("include-signatures")
+            .num_args(0)
+            .help("Copy the Sigstore signatures of the policies too"),
+        Arg::new("dry-run")
+            .long("dry-run")
+            .num_args(0)
+            .help("List the policies that would be mirrored, without copying them"),
+        Arg::new("docker-config-json-path")
+            .long("docker-config-json-path")
+            .value_name("PATH")
+            .help("Path to a directory containing the Docker 'config.json' file. Can be used to indicate registry authentication details"),
+        Arg::new("sources-path")
+            .long("sources-path")
+            .value_name("PATH")
+            .help("YAML file holding source information (https, registry insecure hosts, custom CA's...)"),
+    ];
+    args.sort_by(|a, b| a.get_id().cmp(b.get_id()));
+    args.push(
+        Arg::new("uris")
+            .num_args(1..)
+            .help("URIs of the policies to mirror. Supported scheme: registry://"),
+    );
+
+    Command::new("mirror")
+        .about("Copies policies and their annotations to another registry")
+        .args(args)
+        .group(
+            ArgGroup::new("policies")
+                .args(["uris", "policies-file", "crd"])
+                .multiple(true)
+                .required(true),
+        )
+}
+
+fn subcommand_store() -> Command {
+    Command::new("store")
+        .about("Manage the local policy store")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("verify")
+                .about("Verify the integrity of the policies inside of the store")
+                .long_about(
+                    r#"Verify the integrity of the policies inside of the store.
+
+The SHA-256 digest of each policy is compared with the one recorded when the
+policy was pulled or loaded. Truncated, corrupted and tampered modules are
+reported and make the command fail."#,
+                ),
+        )
+}
+
+fn subcommand_policies() -> Command {
+    let mut args = vec![
+        Arg::new("output")
+            .long("output")
+            .short('o')
+            .value_name("FORMAT")
+            .value_parser(PossibleValuesParser::new(["json", "yaml"]))
+            .help("Output format"),
+        Arg::new("registry")
+            .long("registry")
+            .value_name("HOST")
+            .help("Only list the policies pulled from the given registry, e.g. `ghcr.io`"),
+        Arg::new("mutating")
+            .long("mutating")
+            .num_args(0)
+            .help("Only list the mutating policies"),
+        Arg::new("context-aware")
+            .long("context-aware")
+            .num_args(0)
+            .help("Only list the context aware policies"),
+        Arg::new("unannotated")
+            .long("unannotated")
+            .num_args(0)
+            .conflicts_with_all(["mutating", "context-aware"])
+            .help("Only list the policies that have not been annotated"),
+    ];
+    args.sort_by(|a, b| a.get_id().cmp(b.get_id()));
+
+    Command::new("policies")
+        .about("Lists all downloaded policies")
+        .args(args)
+}
+
 pub fn build_cli() -> Command {
     let mut subcommands = vec![
-        Command::new("policies").about("Lists all downloaded policies"),
+        subcommand_policies(),
+        subcommand_prune(),
+        subcommand_mirror(),
+        subcommand_lock(),
+        subcommand_pin(),
+        subcommand_outdated(),
+        subcommand_store(),
         Command::new("info").about("Display system information"),
         Command::new("rm")
             .about("Removes a Kubewarden policy from the store")
@@ -699,12 +1220,22 @@ pub fn build_cli() -> Command {
                     .help("Shell type"),
             ),
         Command::new("load")
-            .about("load policies from a tar.gz file")
+            .about("load policies from a tar.gz file or an OCI image layout")
             .arg(
                 Arg::new("input")
                     .long("input")
                     .required(true)
-                    .help("load policies from tarball"),
+                    .help("load policies from tarball or OCI image layout"),
+            )
+            .arg(bundle_format_arg())
+            .arg(
+                Arg::new("output")
+                    .long("output")
+                    .short('o')
+                    .value_name("FORMAT")
+                    .value_parser(PossibleValuesParser::new(["text", "json"]))
+                    .default_value("text")
+                    .help("Output format of the list of loaded policies"),
             ),
         subcommand_pull(),
         subcommand_verify(),
@@ -714,6 +1245,8 @@ pub fn build_cli() -> Command {
         subcommand_inspect(),
         subcommand_scaffold(),
         subcommand_digest(),
+        subcommand_sign(),
+        subcommand_generate_key_pair(),
         subcommand_bench(),
         subcommand_save(),
         subcommand_docs(),
@@ -737,6 +1270,26 @@ pub fn build_cli() -> Command {
                 .num_args(0)
                 .help("Disable colorful output"),
         )
+        .arg(
+            Arg::new("store-path")
+                .long("store-path")
+                .value_name("PATH")
+                .env("KWCTL_STORE")
+                .global(true)
+                .help("Path of the policy store. Defaults to the kwctl cache directory"),
+        )
+        .arg(
+            Arg::new("retries")
+                .long("retries")
+                .value_name("RETRIES")
+                .env("KWCTL_RETRIES")
+                .global(true)
+                .default_value("3")
+                .value_parser(value_parser!(u32))
+                .help(
+                    "How many times the registry and HTTPS operations are retried when they fail",
+                ),
+        )
         .subcommands(subcommands)
         .long_version(VERSION_AND_BUILTINS.as_str())
         .subcommand_required(true)
@@ -21,6 +21,7 @@ use crate::{
     command::run::{local_data::LocalData, policy_execution_mode::determine_execution_mode},
     config::{
         HostCapabilitiesMode,
+        kubernetes::KubeClientSettings,
         policy_definition::{
             ContextAwareConfiguration, PolicyDefinition, PolicyExecutionConfiguration,
         },
@@ -37,6 +38,7 @@ fn has_raw_policy_type(metadata: Option<&Metadata>) -> bool {
 }
 
 async fn build_callback_handler(
+    policy_id: &str,
     kube_client_needed: bool,
     cfg: &PullAndRunSettings,
     shutdown_channel_rx: oneshot::Receiver<()>,
@@ -46,10 +48,11 @@ async fn build_callback_handler(
     } else {
         match &cfg.host_capabilities_mode {
             HostCapabilitiesMode::Proxy(ProxyMode::Replay { source: _ }) => None,
-            _ => Some(build_kube_client().await?),
+            HostCapabilitiesMode::Proxy(ProxyMode::Mock { strict: true, .. }) => None,
+            _ => Some(build_kube_client(&cfg.kube_client_settings).await?),
         }
     };
-    CallbackHandler::new(cfg, kube_client, shutdown_channel_rx).await
+    CallbackHandler::new(policy_id, cfg, kube_client, shutdown_channel_rx).await
 }
 
 pub(crate) enum Evaluator {
@@ -74,6 +77,7 @@ impl Evaluator {
 
         match policy {
             PolicyDefinition::Policy {
+                id,
                 uri,
                 user_execution_cfg,
                 raw,
@@ -103,6 +107,7 @@ impl Evaluator {
                     build_validate_request(&cfg.request, *raw || has_raw_policy_type(metadata))?;
 
                 let callback_handler = build_callback_handler(
+                    id,
                     !context_aware_allowed_resources.is_empty(),
                     cfg,
                     shutdown_channel_rx,
@@ -146,7 +151,7 @@ impl Evaluator {
                     .any(|(_, pm)| !pm.settings.ctx_aware_resources_allow_list.is_empty());
 
                 let callback_handler =
-                    build_callback_handler(is_context_aware, cfg, shutdown_channel_rx).await?;
+                    build_callback_handler(id, is_context_aware, cfg, shutdown_channel_rx).await?;
 
                 // group policies cannot be raw right now
                 let request = build_validate_request(&cfg.request, false)?;
@@ -298,9 +303,8 @@ fn build_context_aware_allowed_resources(
 /// yet (see https://github.com/kube-rs/kube/issues/1003).
 ///
 /// This function provides a workaround to this limitation.
-async fn build_kube_client() -> Result<kube::Client> {
-    // This is the usual way of obtaining a kubeconfig
-    let mut kube_config = kube::Config::infer().await.map_err(anyhow::Error::new)?;
+async fn build_kube_client(settings: &KubeClientSettings) -> Result<kube::Client> {
+    let mut kube_config = settings.kube_config().await?;
 
     // Does the cluster_url have an host? This is probably true 99.999% of the times
     if let Some(host) = kube_config.cluster_url.host() {
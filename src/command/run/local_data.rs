@@ -1,12 +1,25 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::PathBuf,
+    sync::Arc,
+};
 
 use anyhow::{Result, anyhow};
-use policy_evaluator::{policy_fetcher::PullDestination, policy_metadata::Metadata};
+use policy_evaluator::{
+    policy_fetcher::{PullDestination, policy::Policy},
+    policy_metadata::Metadata,
+};
 
 use crate::{
     backend::has_minimum_kubewarden_version,
-    config::{policy_definition::PolicyDefinition, pull_and_run::PullAndRunSettings},
-    pull, verify,
+    concurrency,
+    config::{
+        policy_definition::{PolicyDefinition, normalize_uri},
+        pull_and_run::PullAndRunSettings,
+    },
+    prune, pull,
+    store::PolicyRecord,
+    verify,
 };
 
 pub(crate) struct LocalData {
@@ -42,39 +55,127 @@ impl LocalData {
 }
 
 // Pulls all policy definitions and returns a map of URIs to local paths.
+// The policies are pulled and checked concurrently.
 async fn pull_all(
     policy_definitions: &[PolicyDefinition],
     cfg: &PullAndRunSettings,
 ) -> Result<HashMap<String, PathBuf>> {
-    let sources = cfg.sources.as_ref();
-
-    let mut local_paths = HashMap::new();
-
-    for policy_definition in policy_definitions {
-        for uri in policy_definition.uris() {
-            if local_paths.contains_key(&uri) {
-                continue;
-            }
-            let policy = pull::pull(&uri, sources, PullDestination::MainStore).await?;
-
-            if let Some(digests) = cfg.verified_manifest_digests.as_ref() {
-                let digest = digests
+    // The same policy can be referenced by equivalent URIs, like
+    // `ghcr.io/...` and `registry://ghcr.io/...`: it is pulled only once
+    let mut uris: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for uri in policy_definitions
+        .iter()
+        .flat_map(|policy_definition| policy_definition.uris())
+    {
+        uris.entry(normalize_uri(&uri)).or_default().insert(uri);
+    }
+    let policies = uris
+        .into_values()
+        .filter_map(|aliases| Some((aliases.first()?.clone(), aliases)))
+        .collect();
+
+    let multi_progress = concurrency::multi_progress();
+    let lock_file = cfg.lock_file.clone().map(Arc::new);
+    let pulled = concurrency::run_concurrently(policies, |(uri, aliases)| {
+        let sources = cfg.sources.clone();
+        let lock_file = lock_file.clone();
+        let verified_manifest_digest = cfg
+            .verified_manifest_digests
+            .as_ref()
+            .map(|digests| {
+                digests
                     .get(&uri)
-                    .ok_or_else(|| anyhow!("No digest found for {}", uri))?;
-
+                    .cloned()
+                    .ok_or_else(|| anyhow!("No digest found for {}", uri))
+            })
+            .transpose();
+        let sigstore_trust_root = cfg.sigstore_trust_root.clone();
+        let require_verified = cfg.require_verified;
+        let pb = multi_progress.add(pull::spinner());
+
+        async move {
+            let verified_manifest_digest = verified_manifest_digest?;
+            let mut checksum = None;
+            let mut locked_digest = None;
+            if let Some(lock_file) = lock_file {
+                locked_digest = lock_file
+                    .ensure_locked(&uri, sources.as_ref())
+                    .await?
+                    .map(str::to_string);
+                checksum = lock_file.checksum(&uri).cloned();
+            }
+            let policy = match (checksum, locked_digest) {
+                (Some(checksum), _) => {
+                    pull::pull_checked(
+                        &uri,
+                        None,
+                        sources.as_ref(),
+                        PullDestination::MainStore,
+                        &checksum,
+                        pb,
+                    )
+                    .await?
+                }
+                (None, Some(locked_digest)) => {
+                    pull::pull_by_digest(
+                        &uri,
+                        &locked_digest,
+                        sources.as_ref(),
+                        PullDestination::MainStore,
+                        pb,
+                    )
+                    .await?
+                }
+                (None, None) => {
+                    pull::pull_with_progress(&uri, sources.as_ref(), PullDestination::MainStore, pb)
+                        .await?
+                }
+            };
+
+            if let Some(digest) = verified_manifest_digest {
                 verify::verify_local_checksum(
                     &policy,
-                    sources,
-                    digest,
-                    cfg.sigstore_trust_root.clone(),
+                    sources.as_ref(),
+                    &digest,
+                    sigstore_trust_root,
                 )
                 .await?
             }
 
-            local_paths.insert(uri, policy.local_path);
+            if require_verified {
+                ensure_verified(&policy)?;
+            }
+
+            prune::mark_as_used(&policy.local_path);
+            Ok((aliases, policy.local_path))
         }
+    })
+    .await?;
+
+    Ok(pulled
+        .into_iter()
+        .flat_map(|(aliases, local_path)| {
+            aliases
+                .into_iter()
+                .map(move |uri| (uri, local_path.clone()))
+        })
+        .collect())
+}
+
+/// Ensures the provenance record of the policy reports a successful
+/// verification of its current contents
+fn ensure_verified(policy: &Policy) -> Result<()> {
+    let verified = match PolicyRecord::load(&crate::store::open(), &policy.uri)? {
+        Some(record) => record.is_verified(policy)?,
+        None => false,
+    };
+    if !verified {
+        return Err(anyhow!(
+            "policy {} has not been verified. Pull it using the verification flags or provide them to this command",
+            policy.uri
+        ));
     }
-    Ok(local_paths)
+    Ok(())
 }
 
 fn build_metadata(local_paths: &HashMap<String, PathBuf>) -> Result<HashMap<String, Metadata>> {
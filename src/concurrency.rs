@@ -0,0 +1,108 @@
+use std::{future::Future, io, sync::Arc};
+
+use anyhow::{Result, anyhow};
+use indicatif::{MultiProgress, ProgressDrawTarget};
+use is_terminal::IsTerminal;
+use tokio::{sync::Semaphore, task::JoinSet};
+
+/// Maximum number of policies pulled or verified at the same time
+pub(crate) const MAX_CONCURRENT_OPERATIONS: usize = 4;
+
+/// Groups the progress bars of the operations running at the same time.
+/// Nothing is drawn when stderr is not a terminal
+pub(crate) fn multi_progress() -> MultiProgress {
+    if io::stderr().is_terminal() {
+        MultiProgress::new()
+    } else {
+        MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+    }
+}
+
+/// Runs `operation` over all the items, with at most
+/// `MAX_CONCURRENT_OPERATIONS` of them running at the same time.
+///
+/// The results keep the order of the items. The first failure is returned,
+/// and the operations that are still running are cancelled.
+pub(crate) async fn run_concurrently<T, R, F, Fut>(items: Vec<T>, operation: F) -> Result<Vec<R>>
+where
+    F: Fn(T) -> Fut,
+    Fut: Future<Output = Result<R>> + Send + 'static,
+    R: Send + 'static,
+{
+    let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_OPERATIONS));
+    let mut tasks = JoinSet::new();
+    let total = items.len();
+    for (index, item) in items.into_iter().enumerate() {
+        let semaphore = semaphore.clone();
+        let future = operation(item);
+        tasks.spawn(async move {
+            let _permit = semaphore
+                .acquire_owned()
+                .await
+                .map_err(|e| anyhow!("cannot schedule operation: {}", e))?;
+            future.await.map(|result| (index, result))
+        });
+    }
+
+    let mut results: Vec<Option<R>> = (0..total).map(|_| None).collect();
+    while let Some(outcome) = tasks.join_next().await {
+        let (index, result) = outcome.map_err(|e| anyhow!("operation failed: {}", e))??;
+        results[index] = Some(result);
+    }
+    Ok(results.into_iter().flatten().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    #[tokio::test]
+    async fn test_run_concurrently_keeps_order() {
+        let results = run_concurrently(vec![30u64, 10, 20], |delay| async move {
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            Ok(delay * 2)
+        })
+        .await
+        .unwrap();
+        assert_eq!(results, vec![60, 20, 40]);
+    }
+
+    #[tokio::test]
+    async fn test_run_concurrently_bounds_concurrency() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let items: Vec<usize> = (0..MAX_CONCURRENT_OPERATIONS * 3).collect();
+
+        run_concurrently(items, |_| {
+            let running = running.clone();
+            let peak = peak.clone();
+            async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                Ok(())
+            }
+        })
+        .await
+        .unwrap();
+        assert!(peak.load(Ordering::SeqCst) <= MAX_CONCURRENT_OPERATIONS);
+    }
+
+    #[tokio::test]
+    async fn test_run_concurrently_returns_failure() {
+        let result = run_concurrently(vec![1, 2, 3], |item| async move {
+            if item == 2 {
+                Err(anyhow!("cannot process item {}", item))
+            } else {
+                Ok(item)
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap_err().to_string(), "cannot process item 2");
+    }
+}
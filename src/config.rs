@@ -1,3 +1,4 @@
+pub(crate) mod kubernetes;
 pub(crate) mod policy_definition;
 pub(crate) mod pull_and_run;
 pub(crate) mod sources;
@@ -0,0 +1,141 @@
+use std::path::PathBuf;
+
+use anyhow::{Result, anyhow};
+use clap::ArgMatches;
+use policy_evaluator::kube::{
+    self,
+    config::{KubeConfigOptions, Kubeconfig},
+};
+
+/// Defines how to connect to the Kubernetes API server
+#[derive(Debug, Default, Clone)]
+pub(crate) struct KubeClientSettings {
+    /// Path to the kubeconfig file. When not provided, the usual
+    /// lookup is performed (`KUBECONFIG`, `~/.kube/config`, in-cluster)
+    pub kubeconfig: Option<PathBuf>,
+    /// The kubeconfig context to use instead of the current one
+    pub context: Option<String>,
+    /// The user to impersonate
+    pub impersonate_user: Option<String>,
+    /// The groups to impersonate
+    pub impersonate_groups: Vec<String>,
+}
+
+impl KubeClientSettings {
+    pub(crate) fn from_matches(matches: &ArgMatches) -> KubeClientSettings {
+        KubeClientSettings {
+            kubeconfig: matches.get_one::<String>("kubeconfig").map(PathBuf::from),
+            context: matches.get_one::<String>("context").cloned(),
+            impersonate_user: matches.get_one::<String>("as").cloned(),
+            impersonate_groups: matches
+                .get_many::<String>("as-group")
+                .map(|groups| groups.cloned().collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Builds the configuration of the Kubernetes client
+    pub(crate) async fn kube_config(&self) -> Result<kube::Config> {
+        let options = KubeConfigOptions {
+            context: self.context.clone(),
+            ..Default::default()
+        };
+
+        let mut config = match (&self.kubeconfig, &self.context) {
+            (Some(kubeconfig), _) => {
+                let kubeconfig = Kubeconfig::read_from(kubeconfig)
+                    .map_err(|e| anyhow!("cannot read kubeconfig file {:?}: {}", kubeconfig, e))?;
+                kube::Config::from_custom_kubeconfig(kubeconfig, &options)
+                    .await
+                    .map_err(anyhow::Error::new)?
+            }
+            (None, Some(_)) => kube::Config::from_kubeconfig(&options)
+                .await
+                .map_err(anyhow::Error::new)?,
+            // This is the usual way of obtaining a kubeconfig
+            (None, None) => kube::Config::infer().await.map_err(anyhow::Error::new)?,
+        };
+
+        if self.impersonate_user.is_some() {
+            config.auth_info.impersonate = self.impersonate_user.clone();
+        }
+        if !self.impersonate_groups.is_empty() {
+            config.auth_info.impersonate_groups = Some(self.impersonate_groups.clone());
+        }
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const KUBECONFIG: &str = r#"
+apiVersion: v1
+kind: Config
+current-context: dev
+clusters:
+- name: dev
+  cluster:
+    server: https://dev.example.com:6443
+- name: prod
+  cluster:
+    server: https://prod.example.com:6443
+contexts:
+- name: dev
+  context:
+    cluster: dev
+    user: admin
+- name: prod
+  context:
+    cluster: prod
+    user: admin
+users:
+- name: admin
+  user:
+    token: secret
+"#;
+
+    #[tokio::test]
+    async fn kube_config_with_context_and_impersonation() {
+        let mut kubeconfig = tempfile::NamedTempFile::new().unwrap();
+        kubeconfig.write_all(KUBECONFIG.as_bytes()).unwrap();
+
+        let settings = KubeClientSettings {
+            kubeconfig: Some(kubeconfig.path().to_path_buf()),
+            context: Some("prod".to_string()),
+            impersonate_user: Some(
+                "system:serviceaccount:kubewarden:policy-server-default".to_string(),
+            ),
+            impersonate_groups: vec!["system:serviceaccounts".to_string()],
+        };
+        let config = settings.kube_config().await.unwrap();
+
+        assert_eq!(config.cluster_url.host(), Some("prod.example.com"));
+        assert_eq!(
+            config.auth_info.impersonate.as_deref(),
+            Some("system:serviceaccount:kubewarden:policy-server-default")
+        );
+        assert_eq!(
+            config.auth_info.impersonate_groups,
+            Some(vec!["system:serviceaccounts".to_string()])
+        );
+    }
+
+    #[tokio::test]
+    async fn kube_config_uses_current_context_by_default() {
+        let mut kubeconfig = tempfile::NamedTempFile::new().unwrap();
+        kubeconfig.write_all(KUBECONFIG.as_bytes()).unwrap();
+
+        let settings = KubeClientSettings {
+            kubeconfig: Some(kubeconfig.path().to_path_buf()),
+            ..Default::default()
+        };
+        let config = settings.kube_config().await.unwrap();
+
+        assert_eq!(config.cluster_url.host(), Some("dev.example.com"));
+        assert!(config.auth_info.impersonate.is_none());
+    }
+}
@@ -417,6 +417,33 @@ impl PolicyDefinition {
     }
 }
 
+/// The Custom Resources can reference a policy without providing a scheme,
+/// in that case the policy comes from a registry
+pub(crate) fn normalize_uri(uri: &str) -> String {
+    if uri.contains("://") {
+        uri.to_string()
+    } else {
+        format!("registry://{uri}")
+    }
+}
+
+/// Collects the normalized URIs of the policies referenced by the Kubewarden
+/// Custom Resources defined inside of the given files
+pub(crate) fn referenced_uris_from_files(files: &[String]) -> Result<HashSet<String>> {
+    let mut uris = HashSet::new();
+    for file in files {
+        for policy_definition in PolicyDefinition::from_yaml_file(file)? {
+            uris.extend(
+                policy_definition
+                    .uris()
+                    .iter()
+                    .map(|uri| normalize_uri(uri)),
+            );
+        }
+    }
+    Ok(uris)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,29 +1,33 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap},
     fs,
     io::{self, Read},
-    path::PathBuf,
+    path::{Path, PathBuf},
     str::FromStr,
     sync::Arc,
+    time::Duration,
 };
 
 use anyhow::{Result, anyhow};
 use clap::ArgMatches;
 use policy_evaluator::policy_fetcher::{
     sigstore::trust::sigstore::SigstoreTrustRoot, sources::Sources,
-    verify::config::LatestVerificationConfig,
 };
 use tracing::{info, warn};
 
 use crate::{
-    callback_handler,
+    callback_handler, concurrency,
     config::{
         HostCapabilitiesMode,
+        kubernetes::KubeClientSettings,
         policy_definition::PolicyDefinition,
         sources::remote_server_options,
-        verification::{build_sigstore_trust_root, build_verification_options},
+        verification::{
+            VerificationOptions, build_sigstore_trust_root, build_verification_options,
+        },
     },
-    verify,
+    lock::LockFile,
+    pull, verify,
 };
 
 #[derive(Default)]
@@ -37,6 +41,16 @@ pub(crate) struct PullAndRunSettings {
     pub sigstore_trust_root: Option<Arc<SigstoreTrustRoot>>,
     pub enable_wasmtime_cache: bool,
     pub host_capabilities_mode: HostCapabilitiesMode,
+    /// The host capabilities the policies are allowed to use.
+    /// When `None`, all of them are allowed
+    pub allowed_host_capabilities: Option<BTreeSet<callback_handler::HostCapability>>,
+    /// Used to connect to Kubernetes when the policies are context aware
+    pub kube_client_settings: KubeClientSettings,
+    /// Refuse the policies that have not been successfully verified
+    pub require_verified: bool,
+    /// Refuse the registry modules whose digest differs from the one pinned
+    /// by the lock file
+    pub lock_file: Option<LockFile>,
 }
 
 pub(crate) fn parse_policy_definitions(matches: &ArgMatches) -> Result<Vec<PolicyDefinition>> {
@@ -94,6 +108,8 @@ pub(crate) async fn parse_pull_and_run_settings(
     let sources = remote_server_options(matches)
         .map_err(|e| anyhow!("Error getting remote server options: {}", e))?;
 
+    let lock_file = parse_lock_file(matches)?;
+
     let verification_options = build_verification_options(matches)?;
 
     let sigstore_trust_root = match build_sigstore_trust_root(
@@ -146,8 +162,10 @@ pub(crate) async fn parse_pull_and_run_settings(
             .ok_or_else(|| anyhow!("Cannot parse 'record-host-capabilities-interactions' file"))?;
 
         info!(session_file = ?destination, "host capabilities proxy enabled with record mode");
-        host_capabilities_mode =
-            HostCapabilitiesMode::Proxy(callback_handler::ProxyMode::Record { destination });
+        host_capabilities_mode = HostCapabilitiesMode::Proxy(callback_handler::ProxyMode::Record {
+            destination,
+            recorded_session: Default::default(),
+        });
     }
     if matches.contains_id("replay-host-capabilities-interactions") {
         let source = matches
@@ -159,6 +177,19 @@ pub(crate) async fn parse_pull_and_run_settings(
         host_capabilities_mode =
             HostCapabilitiesMode::Proxy(callback_handler::ProxyMode::Replay { source });
     }
+    if let Some(mocks_file) = matches.get_one::<String>("host-capabilities-mocks") {
+        let mocks = callback_handler::read_mocks_file(Path::new(mocks_file))?;
+        let strict = matches
+            .get_one::<bool>("host-capabilities-mocks-strict")
+            .unwrap_or(&false)
+            .to_owned();
+
+        info!(mocks_file = ?mocks_file, strict, "host capabilities proxy enabled with mock mode");
+        host_capabilities_mode =
+            HostCapabilitiesMode::Proxy(callback_handler::ProxyMode::Mock { mocks, strict });
+    }
+
+    let allowed_host_capabilities = parse_allowed_host_capabilities(matches)?;
 
     Ok(PullAndRunSettings {
         sources,
@@ -167,35 +198,103 @@ pub(crate) async fn parse_pull_and_run_settings(
         sigstore_trust_root,
         enable_wasmtime_cache,
         host_capabilities_mode,
+        allowed_host_capabilities,
+        kube_client_settings: KubeClientSettings::from_matches(matches),
+        require_verified: matches
+            .get_one::<bool>("require-verified")
+            .unwrap_or(&false)
+            .to_owned(),
+        lock_file,
     })
 }
 
+/// Loads the lock file of the YAML file being run, when `--locked` is set
+fn parse_lock_file(matches: &ArgMatches) -> Result<Option<LockFile>> {
+    if !matches.get_one::<bool>("locked").unwrap_or(&false) {
+        return Ok(None);
+    }
+    let uri = matches
+        .get_one::<String>("uri_or_sha_prefix_or_yaml_file")
+        .expect("uri_or_sha_prefix is guaranteed to be Some here");
+    if !(uri.ends_with(".yaml") || uri.ends_with(".yml")) {
+        return Err(anyhow!(
+            "The --locked option can be used only with a YAML file: {}",
+            uri
+        ));
+    }
+    LockFile::load(&LockFile::path_for(Path::new(uri))).map(Some)
+}
+
+fn parse_allowed_host_capabilities(
+    matches: &ArgMatches,
+) -> Result<Option<BTreeSet<callback_handler::HostCapability>>> {
+    if let Some(allowed) = matches.get_many::<String>("allow-host-capabilities") {
+        let allowed = allowed
+            .map(|capability| callback_handler::HostCapability::from_str(capability))
+            .collect::<Result<BTreeSet<_>>>()?;
+        info!(?allowed, "host capabilities allow list enabled");
+        return Ok(Some(allowed));
+    }
+
+    if let Some(denied) = matches.get_many::<String>("deny-host-capabilities") {
+        let denied = denied
+            .map(|capability| callback_handler::HostCapability::from_str(capability))
+            .collect::<Result<BTreeSet<_>>>()?;
+        let allowed = callback_handler::HostCapability::ALL
+            .into_iter()
+            .filter(|capability| !denied.contains(capability))
+            .collect::<BTreeSet<_>>();
+        info!(?allowed, "host capabilities allow list enabled");
+        return Ok(Some(allowed));
+    }
+
+    Ok(None)
+}
+
 async fn build_verified_manifest_digests(
     policy_definitions: &[PolicyDefinition],
-    verification_options: &LatestVerificationConfig,
+    verification_options: &VerificationOptions,
     sources: &Option<Sources>,
     sigstore_trust_root: Option<Arc<SigstoreTrustRoot>>,
 ) -> Result<HashMap<String, String>> {
-    let mut uris: HashSet<String> = HashSet::new();
-    for policy_definition in policy_definitions {
-        uris = uris.union(&policy_definition.uris()).cloned().collect();
-    }
+    let uris: BTreeSet<String> = policy_definitions
+        .iter()
+        .flat_map(|policy_definition| policy_definition.uris())
+        .collect();
 
-    let mut verified_manifest_digests = HashMap::new();
+    let multi_progress = concurrency::multi_progress();
+    let verification_options = Arc::new(verification_options.clone());
+    let verified_manifest_digests =
+        concurrency::run_concurrently(uris.into_iter().collect(), |uri| {
+            let sources = sources.clone();
+            let verification_options = verification_options.clone();
+            let sigstore_trust_root = sigstore_trust_root.clone();
+            let pb = multi_progress.add(pull::spinner());
 
-    for uri in &uris {
-        // verify policy prior to pulling if keys listed, and keep the
-        // verified manifest digest:
-        let verified_manifest_digest = verify::verify(
-            uri.as_str(),
-            sources.as_ref(),
-            verification_options,
-            sigstore_trust_root.clone(),
-        )
-        .await
-        .map_err(|e| anyhow!("Policy {} cannot be validated\n{:?}", uri, e))?;
-        verified_manifest_digests.insert(uri.clone(), verified_manifest_digest);
-    }
+            async move {
+                pb.set_message(format!("Verifying policy {}", uri));
+                pb.enable_steady_tick(Duration::from_millis(100));
+                // verify policy prior to pulling if keys listed, and keep the
+                // verified manifest digest:
+                let result = verify::verify(
+                    uri.as_str(),
+                    sources.as_ref(),
+                    &verification_options,
+                    sigstore_trust_root,
+                )
+                .await;
+                match &result {
+                    Ok(_) => {
+                        pb.finish_with_message(format!("Successfully verified policy {}", uri))
+                    }
+                    Err(_) => pb.finish_with_message(format!("Failed to verify policy {}", uri)),
+                }
+                let verified_manifest_digest =
+                    result.map_err(|e| anyhow!("Policy {} cannot be validated\n{:?}", uri, e))?;
+                Ok((uri, verified_manifest_digest))
+            }
+        })
+        .await?;
 
-    Ok(verified_manifest_digests)
+    Ok(verified_manifest_digests.into_iter().collect())
 }
@@ -12,26 +12,75 @@ use policy_evaluator::policy_fetcher::{
     store::DEFAULT_ROOT,
     verify::config::{LatestVerificationConfig, Signature, Subject, read_verification_file},
 };
+use serde::de::DeserializeOwned;
 use sigstore_protobuf_specs::dev::sigstore::trustroot::v1::ClientTrustConfig;
 use tracing::{debug, info};
+use url::Url;
 
-use crate::{KWCTL_VERIFICATION_CONFIG, verify::VerificationAnnotations};
+use crate::{
+    KWCTL_VERIFICATION_CONFIG,
+    attestations::AttestationConstraint,
+    identity::{IdentityConstraint, IdentityMatcher},
+    verify::VerificationAnnotations,
+};
+
+/// Key of the verification config file listing the certificate identity
+/// constraints checked by kwctl
+const CERTIFICATE_IDENTITIES_KEY: &str = "certificateIdentities";
+
+/// Key of the verification config file listing the attestations the policy
+/// must have
+const ATTESTATIONS_KEY: &str = "attestations";
+
+/// Constraints the signatures of a policy must satisfy
+#[derive(Clone, Debug)]
+pub(crate) struct VerificationOptions {
+    /// Constraints checked by the policy fetcher
+    pub config: LatestVerificationConfig,
+    /// Certificate identities matched with prefixes or regular expressions,
+    /// checked by kwctl
+    pub identities: Vec<IdentityConstraint>,
+    /// In-toto attestations the policy must have, signed by one of the
+    /// signers trusted by the other constraints
+    pub attestations: Vec<AttestationConstraint>,
+}
+
+impl VerificationOptions {
+    /// Whether the policy fetcher has any constraint to check
+    pub(crate) fn has_signature_constraints(&self) -> bool {
+        self.config
+            .all_of
+            .as_ref()
+            .is_some_and(|signatures| !signatures.is_empty())
+            || self.config.any_of.is_some()
+    }
+}
+
+impl From<LatestVerificationConfig> for VerificationOptions {
+    fn from(config: LatestVerificationConfig) -> Self {
+        Self {
+            config,
+            identities: Vec::new(),
+            attestations: Vec::new(),
+        }
+    }
+}
 
 pub(crate) fn build_verification_options(
     matches: &ArgMatches,
-) -> Result<Option<LatestVerificationConfig>> {
-    if let Some(verification_config) = build_verification_options_from_flags(matches)? {
+) -> Result<Option<VerificationOptions>> {
+    if let Some(verification_options) = build_verification_options_from_flags(matches)? {
         // flags present, built configmap from them:
         if matches.contains_id("verification-config-path") {
             return Err(anyhow!(
                 "verification-config-path cannot be used in conjunction with other verification flags"
             ));
         }
-        return Ok(Some(verification_config));
+        return Ok(Some(verification_options));
     }
     if let Some(verification_config_path) = matches.get_one::<String>("verification-config-path") {
         // config flag present, read it:
-        Ok(Some(read_verification_file(Path::new(
+        Ok(Some(read_verification_options(Path::new(
             &verification_config_path,
         ))?))
     } else {
@@ -39,19 +88,94 @@ pub(crate) fn build_verification_options(
         if Path::exists(&verification_config_path) {
             // default config flag present, read it:
             info!(path = ?verification_config_path, "Default verification config present, using it");
-            Ok(Some(read_verification_file(&verification_config_path)?))
+            Ok(Some(read_verification_options(&verification_config_path)?))
         } else {
             Ok(None)
         }
     }
 }
 
-/// Takes clap flags and builds a Some(LatestVerificationConfig) containing all
+/// Reads a verification config file. On top of the format understood by the
+/// policy fetcher, the file can list certificate identity constraints under
+/// the `certificateIdentities` key and attestation constraints under the
+/// `attestations` key
+fn read_verification_options(path: &Path) -> Result<VerificationOptions> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| anyhow!("cannot read verification config {}: {}", path.display(), e))?;
+    let mut document: serde_yaml::Value = serde_yaml::from_str(&contents)
+        .map_err(|e| anyhow!("cannot parse verification config {}: {}", path.display(), e))?;
+    let identities = document
+        .as_mapping_mut()
+        .and_then(|mapping| mapping.remove(CERTIFICATE_IDENTITIES_KEY));
+    let attestations = document
+        .as_mapping_mut()
+        .and_then(|mapping| mapping.remove(ATTESTATIONS_KEY));
+    if identities.is_none() && attestations.is_none() {
+        return Ok(read_verification_file(path)?.into());
+    }
+
+    let identities: Vec<IdentityConstraint> =
+        parse_kwctl_constraints(identities, CERTIFICATE_IDENTITIES_KEY, path)?;
+    for identity in &identities {
+        identity.validate()?;
+    }
+    let attestations: Vec<AttestationConstraint> =
+        parse_kwctl_constraints(attestations, ATTESTATIONS_KEY, path)?;
+    for attestation in &attestations {
+        attestation.validate()?;
+    }
+
+    // the policy fetcher does not know about the constraints checked by kwctl
+    let config_file = tempfile::NamedTempFile::new()
+        .map_err(|e| anyhow!("cannot create temporary file: {}", e))?;
+    serde_yaml::to_writer(&config_file, &document)
+        .map_err(|e| anyhow!("cannot write verification config: {}", e))?;
+    let verification_options = VerificationOptions {
+        config: read_verification_file(config_file.path())?,
+        identities,
+        attestations,
+    };
+    if !verification_options.attestations.is_empty()
+        && !verification_options.has_signature_constraints()
+        && verification_options.identities.is_empty()
+    {
+        return Err(anyhow!(
+            "verification config {} requires attestations, but it does not define who must sign them",
+            path.display()
+        ));
+    }
+    Ok(verification_options)
+}
+
+fn parse_kwctl_constraints<T: DeserializeOwned>(
+    value: Option<serde_yaml::Value>,
+    key: &str,
+    path: &Path,
+) -> Result<Vec<T>> {
+    value
+        .map(|value| {
+            serde_yaml::from_value(value).map_err(|e| {
+                anyhow!(
+                    "cannot parse {} of verification config {}: {}",
+                    key,
+                    path.display(),
+                    e
+                )
+            })
+        })
+        .transpose()
+        .map(Option::unwrap_or_default)
+}
+
+/// Takes clap flags and builds a Some(VerificationOptions) containing all
 /// passed pub keys and annotations in LatestVerificationConfig.AllOf.
+/// Identities matched with prefixes or regular expressions that cannot be
+/// expressed by the LatestVerificationConfig become certificate identity
+/// constraints.
 /// If no verification flags where used, it returns a None.
 fn build_verification_options_from_flags(
     matches: &ArgMatches,
-) -> Result<Option<LatestVerificationConfig>> {
+) -> Result<Option<VerificationOptions>> {
     let key_files: Option<Vec<String>> = matches
         .get_many::<String>("verification-key")
         .map(|items| items.into_iter().map(|i| i.to_string()).collect());
@@ -75,33 +199,35 @@ fn build_verification_options_from_flags(
             }
         };
 
-    let cert_email: Option<String> = matches
-        .get_many::<String>("cert-email")
-        .map(|items| items.into_iter().map(|i| i.to_string()).collect());
-    let cert_oidc_issuer: Option<String> = matches
-        .get_many::<String>("cert-oidc-issuer")
-        .map(|items| items.into_iter().map(|i| i.to_string()).collect());
+    let flag = |name: &str| matches.get_one::<String>(name).cloned();
+    let cert_identity = flag("cert-email")
+        .map(IdentityMatcher::Equal)
+        .or_else(|| flag("cert-identity-prefix").map(IdentityMatcher::UrlPrefix))
+        .or_else(|| flag("cert-identity-regexp").map(IdentityMatcher::Regexp));
+    let cert_oidc_issuer = flag("cert-oidc-issuer")
+        .map(IdentityMatcher::Equal)
+        .or_else(|| flag("cert-oidc-issuer-regexp").map(IdentityMatcher::Regexp));
 
-    let github_owner: Option<String> = matches
-        .get_many::<String>("github-owner")
-        .map(|items| items.into_iter().map(|i| i.to_string()).collect());
-    let github_repo: Option<String> = matches
-        .get_many::<String>("github-repo")
-        .map(|items| items.into_iter().map(|i| i.to_string()).collect());
+    let github_owner = flag("github-owner")
+        .map(IdentityMatcher::Equal)
+        .or_else(|| flag("github-owner-regexp").map(IdentityMatcher::Regexp));
+    let github_repo = flag("github-repo")
+        .map(IdentityMatcher::Equal)
+        .or_else(|| flag("github-repo-regexp").map(IdentityMatcher::Regexp));
 
     if key_files.is_none()
         && annotations.is_none()
-        && cert_email.is_none()
+        && cert_identity.is_none()
         && cert_oidc_issuer.is_none()
         && github_owner.is_none()
         && github_repo.is_none()
     {
-        // no verification flags were used, don't create a LatestVerificationConfig
+        // no verification flags were used, don't create a VerificationOptions
         return Ok(None);
     }
 
     if key_files.is_none()
-        && cert_email.is_none()
+        && cert_identity.is_none()
         && cert_oidc_issuer.is_none()
         && github_owner.is_none()
         && annotations.is_some()
@@ -118,29 +244,57 @@ fn build_verification_options_from_flags(
     }
 
     let mut signatures: Vec<Signature> = Vec::new();
+    let mut identities: Vec<IdentityConstraint> = Vec::new();
 
-    if (cert_email.is_some() && cert_oidc_issuer.is_none())
-        || (cert_email.is_none() && cert_oidc_issuer.is_some())
-    {
-        return Err(anyhow!(
-            "Intending to verify OIDC issuer, but no email or issuer were provided. You must pass the email and OIDC issuer to be validated together "
-        ));
-    } else if cert_email.is_some() && cert_oidc_issuer.is_some() {
-        let sig = Signature::GenericIssuer {
-            issuer: cert_oidc_issuer.unwrap(),
-            subject: Subject::Equal(cert_email.unwrap()),
+    match (cert_identity, cert_oidc_issuer) {
+        (None, None) => {}
+        (Some(_), None) | (None, Some(_)) => {
+            return Err(anyhow!(
+                "Intending to verify OIDC issuer, but no email or issuer were provided. You must pass the email and OIDC issuer to be validated together "
+            ));
+        }
+        (Some(IdentityMatcher::Equal(email)), Some(IdentityMatcher::Equal(issuer))) => signatures
+            .push(Signature::GenericIssuer {
+                issuer,
+                subject: Subject::Equal(email),
+                annotations: annotations.clone(),
+            }),
+        (Some(IdentityMatcher::UrlPrefix(prefix)), Some(IdentityMatcher::Equal(issuer))) => {
+            let prefix = Url::parse(&prefix)
+                .map_err(|e| anyhow!("invalid certificate identity prefix {}: {}", prefix, e))?;
+            signatures.push(Signature::GenericIssuer {
+                issuer,
+                subject: Subject::UrlPrefix(prefix),
+                annotations: annotations.clone(),
+            })
+        }
+        (identity, issuer) => identities.push(IdentityConstraint {
+            issuer,
+            identity,
             annotations: annotations.clone(),
-        };
-        signatures.push(sig)
+            ..Default::default()
+        }),
     }
 
-    if let Some(repo_owner) = github_owner {
-        let sig = Signature::GithubAction {
-            owner: repo_owner,
-            repo: github_repo,
+    match (github_owner, github_repo) {
+        (None, _) => {}
+        (Some(IdentityMatcher::Equal(owner)), None) => signatures.push(Signature::GithubAction {
+            owner,
+            repo: None,
             annotations: annotations.clone(),
-        };
-        signatures.push(sig)
+        }),
+        (Some(IdentityMatcher::Equal(owner)), Some(IdentityMatcher::Equal(repo))) => signatures
+            .push(Signature::GithubAction {
+                owner,
+                repo: Some(repo),
+                annotations: annotations.clone(),
+            }),
+        (github_owner, github_repo) => identities.push(IdentityConstraint {
+            github_owner,
+            github_repo,
+            annotations: annotations.clone(),
+            ..Default::default()
+        }),
     }
 
     for key_path in key_files.iter().flatten() {
@@ -153,6 +307,10 @@ fn build_verification_options_from_flags(
         };
         signatures.push(sig);
     }
+    for identity in &identities {
+        identity.validate()?;
+    }
+
     let signatures_all_of: Option<Vec<Signature>> = if signatures.is_empty() {
         None
     } else {
@@ -162,7 +320,11 @@ fn build_verification_options_from_flags(
         all_of: signatures_all_of,
         any_of: None,
     };
-    Ok(Some(verification_config))
+    Ok(Some(VerificationOptions {
+        config: verification_config,
+        identities,
+        attestations: Vec::new(),
+    }))
 }
 
 /// Function that builds the Sigstore trust root used for verification. If a trust-config flag is
@@ -172,7 +334,8 @@ pub(crate) async fn build_sigstore_trust_root(
     sigstore_trust_config: Option<&PathBuf>,
 ) -> Result<Option<Arc<SigstoreTrustRoot>>> {
     if let Some(pki_file) = sigstore_trust_config {
-        return build_sigstore_trust_root_from_config(pki_file);
+        return build_sigstore_trust_root_from_config(pki_file)
+            .map(|trust_root| Some(Arc::new(trust_root)));
     }
     debug!("building Sigstore trust root from Sigstore's TUF repository");
     let checkout_path = DEFAULT_ROOT.config_dir().join("fulcio_and_rekor_data");
@@ -185,9 +348,12 @@ pub(crate) async fn build_sigstore_trust_root(
     Ok(Some(Arc::new(trust_root)))
 }
 
-fn build_sigstore_trust_root_from_config(
+/// Builds the Sigstore trust root described by the given trust config file.
+/// The trust root is owned by the caller, unlike the shared one returned by
+/// `build_sigstore_trust_root`
+pub(crate) fn build_sigstore_trust_root_from_config(
     pki_file: &PathBuf,
-) -> Result<Option<Arc<SigstoreTrustRoot>>> {
+) -> Result<SigstoreTrustRoot> {
     debug!(
         "Using user specified Sigstore trust root location: {}",
         pki_file.display()
@@ -220,5 +386,5 @@ fn build_sigstore_trust_root_from_config(
         sigstore::trust::sigstore::SigstoreTrustRoot::from_trusted_root_json_unchecked(
             trust_root_bytes.as_slice(),
         )?;
-    Ok(Some(Arc::new(trust_root)))
+    Ok(trust_root)
 }
@@ -0,0 +1,345 @@
+use anyhow::{Result, anyhow};
+use policy_evaluator::policy_fetcher::sigstore::{
+    cosign::signature_layers::{CertificateSignature, CertificateSubject, SignatureLayer},
+    crypto::CosignVerificationKey,
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use url::Url;
+use x509_cert::{
+    Certificate,
+    der::{Decode, asn1::Utf8StringRef, oid::ObjectIdentifier},
+    ext::pkix::{SubjectAltName, name::GeneralName},
+};
+
+use crate::verify::VerificationAnnotations;
+
+/// Issuer of the certificates of the signatures produced inside of GitHub Actions
+pub(crate) const GITHUB_ACTIONS_ISSUER: &str = "https://token.actions.githubusercontent.com";
+
+// Extensions added by Fulcio to its certificates
+const OIDC_ISSUER_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.57264.1.1");
+const OIDC_ISSUER_V2_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.57264.1.8");
+const GITHUB_WORKFLOW_TRIGGER_OID: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("1.3.6.1.4.1.57264.1.2");
+const GITHUB_WORKFLOW_SHA_OID: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("1.3.6.1.4.1.57264.1.3");
+const GITHUB_WORKFLOW_NAME_OID: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("1.3.6.1.4.1.57264.1.4");
+const GITHUB_WORKFLOW_REPOSITORY_OID: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("1.3.6.1.4.1.57264.1.5");
+const GITHUB_WORKFLOW_REF_OID: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("1.3.6.1.4.1.57264.1.6");
+
+/// Matches a value found inside of the certificate of keyless signatures or
+/// inside of the predicate of attestations
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum IdentityMatcher {
+    Equal(String),
+    Prefix(String),
+    /// Prefix of a URL, matched on path segment boundaries like the
+    /// `urlPrefix` subjects of the verification config:
+    /// `https://github.com/kubewarden` does not match
+    /// `https://github.com/kubewarden-evil/...`
+    UrlPrefix(String),
+    /// Unanchored regular expression, like the ones of cosign's `--*-regexp` flags
+    Regexp(String),
+}
+
+impl IdentityMatcher {
+    pub(crate) fn validate(&self) -> Result<()> {
+        match self {
+            Self::Regexp(regexp) => {
+                Regex::new(regexp)
+                    .map_err(|e| anyhow!("invalid regular expression {}: {}", regexp, e))?;
+            }
+            Self::UrlPrefix(prefix) => {
+                Url::parse(prefix).map_err(|e| anyhow!("invalid URL prefix {}: {}", prefix, e))?;
+            }
+            Self::Equal(_) | Self::Prefix(_) => {}
+        }
+        Ok(())
+    }
+
+    pub(crate) fn matches(&self, value: &str) -> bool {
+        match self {
+            Self::Equal(expected) => value == expected,
+            Self::Prefix(prefix) => value.starts_with(prefix),
+            Self::UrlPrefix(prefix) => {
+                if prefix.ends_with('/') {
+                    value.starts_with(prefix)
+                } else {
+                    value.starts_with(&format!("{prefix}/"))
+                }
+            }
+            Self::Regexp(regexp) => Regex::new(regexp).is_ok_and(|regex| regex.is_match(value)),
+        }
+    }
+}
+
+/// Keyless signature constraint matching the identity recorded inside of the
+/// certificate with prefixes or regular expressions. These constraints are
+/// not supported by the verification config of the policy fetcher, kwctl
+/// checks them against the signatures of the policy.
+///
+/// Inside of the verification config file they are listed under the
+/// `certificateIdentities` key, every entry must be satisfied by at least one
+/// signature.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IdentityConstraint {
+    /// OIDC issuer of the certificate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<IdentityMatcher>,
+    /// Email or URI identity of the certificate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<IdentityMatcher>,
+    /// Owner of the repository running the GitHub workflow
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub github_owner: Option<IdentityMatcher>,
+    /// Name of the repository running the GitHub workflow, without its owner
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub github_repo: Option<IdentityMatcher>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<VerificationAnnotations>,
+}
+
+impl IdentityConstraint {
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.issuer.is_none() && self.github_owner.is_none() {
+            return Err(anyhow!(
+                "certificate identity constraints require an issuer or a GitHub owner"
+            ));
+        }
+        if self.github_repo.is_some() && self.github_owner.is_none() {
+            return Err(anyhow!(
+                "certificate identity constraints matching the GitHub repository require the GitHub owner too"
+            ));
+        }
+        [
+            &self.issuer,
+            &self.identity,
+            &self.github_owner,
+            &self.github_repo,
+        ]
+        .into_iter()
+        .flatten()
+        .try_for_each(IdentityMatcher::validate)
+    }
+
+    /// Whether the signature has been produced by a matching identity. The
+    /// certificate of the layer must have been checked against the Fulcio
+    /// trust root already
+    pub(crate) fn is_satisfied_by(&self, layer: &SignatureLayer) -> bool {
+        let Some(certificate) = &layer.certificate_signature else {
+            return false;
+        };
+        let issuer = certificate.issuer.as_deref().unwrap_or_default();
+        let subject = match &certificate.subject {
+            CertificateSubject::Email(email) => email.as_str(),
+            CertificateSubject::Uri(uri) => uri.as_str(),
+        };
+        let (owner, repo) = certificate
+            .github_workflow_repository
+            .as_deref()
+            .and_then(|repository| repository.split_once('/'))
+            .unwrap_or_default();
+
+        let is_github = self.github_owner.is_some();
+        let matches = |matcher: &Option<IdentityMatcher>, value: &str| {
+            matcher
+                .as_ref()
+                .is_none_or(|matcher| matcher.matches(value))
+        };
+        (!is_github || issuer == GITHUB_ACTIONS_ISSUER)
+            && matches(&self.issuer, issuer)
+            && matches(&self.identity, subject)
+            && (!is_github || !owner.is_empty())
+            && matches(&self.github_owner, owner)
+            && matches(&self.github_repo, repo)
+            && self.annotations_match(layer)
+    }
+
+    fn annotations_match(&self, layer: &SignatureLayer) -> bool {
+        let Some(annotations) = &self.annotations else {
+            return true;
+        };
+        let extra = layer
+            .simple_signing
+            .optional
+            .as_ref()
+            .map(|optional| &optional.extra);
+        annotations.iter().all(|(key, value)| {
+            extra
+                .and_then(|extra| extra.get(key))
+                .and_then(|signed| signed.as_str())
+                .is_some_and(|signed| signed == value)
+        })
+    }
+}
+
+/// Ensures every identity constraint is satisfied by at least one of the
+/// trusted signatures
+pub(crate) fn verify_identities(
+    identities: &[IdentityConstraint],
+    trusted_layers: &[SignatureLayer],
+) -> Result<()> {
+    for identity in identities {
+        if !trusted_layers
+            .iter()
+            .any(|layer| identity.is_satisfied_by(layer))
+        {
+            return Err(anyhow!(
+                "Image verification failed: no signature satisfies the certificate identity constraint {}",
+                serde_json::to_string(identity).unwrap_or_default()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Extracts the identity and the public key of a Fulcio certificate. The
+/// certificate must have been checked against the Fulcio trust root already
+pub(crate) fn certificate_signature(certificate: &Certificate) -> Result<CertificateSignature> {
+    let tbs_certificate = &certificate.tbs_certificate;
+    let verification_key =
+        CosignVerificationKey::try_from(&tbs_certificate.subject_public_key_info)?;
+    let subject = tbs_certificate
+        .get::<SubjectAltName>()
+        .map_err(|e| anyhow!("cannot parse certificate identity: {}", e))?
+        .into_iter()
+        .flat_map(|(_, san)| san.0)
+        .find_map(|name| match name {
+            GeneralName::Rfc822Name(email) => Some(CertificateSubject::Email(email.to_string())),
+            GeneralName::UniformResourceIdentifier(uri) => {
+                Some(CertificateSubject::Uri(uri.to_string()))
+            }
+            _ => None,
+        })
+        .ok_or_else(|| anyhow!("certificate without email or URI identity"))?;
+
+    let extension = |oid: ObjectIdentifier| {
+        tbs_certificate
+            .extensions
+            .iter()
+            .flatten()
+            .find(|extension| extension.extn_id == oid)
+            .map(|extension| extension.extn_value.as_bytes())
+    };
+    let raw_extension = |oid: ObjectIdentifier| {
+        extension(oid).and_then(|value| String::from_utf8(value.to_vec()).ok())
+    };
+    let issuer = extension(OIDC_ISSUER_V2_OID)
+        .and_then(|value| Utf8StringRef::from_der(value).ok())
+        .map(|issuer| issuer.as_str().to_string())
+        .or_else(|| raw_extension(OIDC_ISSUER_OID));
+
+    Ok(CertificateSignature {
+        verification_key,
+        issuer,
+        github_workflow_trigger: raw_extension(GITHUB_WORKFLOW_TRIGGER_OID),
+        github_workflow_sha: raw_extension(GITHUB_WORKFLOW_SHA_OID),
+        github_workflow_name: raw_extension(GITHUB_WORKFLOW_NAME_OID),
+        github_workflow_repository: raw_extension(GITHUB_WORKFLOW_REPOSITORY_OID),
+        github_workflow_ref: raw_extension(GITHUB_WORKFLOW_REF_OID),
+        subject,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::equal(IdentityMatcher::Equal("user@example.com".to_string()), "user@example.com", true)]
+    #[case::not_equal(IdentityMatcher::Equal("user@example.com".to_string()), "other@example.com", false)]
+    #[case::prefix(
+        IdentityMatcher::Prefix("https://github.com/kubewarden/".to_string()),
+        "https://github.com/kubewarden/policies/.github/workflows/release.yml@refs/heads/main",
+        true
+    )]
+    #[case::url_prefix(
+        IdentityMatcher::UrlPrefix("https://github.com/kubewarden".to_string()),
+        "https://github.com/kubewarden/policies/.github/workflows/release.yml@refs/heads/main",
+        true
+    )]
+    #[case::url_prefix_boundary(
+        IdentityMatcher::UrlPrefix("https://github.com/kubewarden".to_string()),
+        "https://github.com/kubewarden-evil/policies/.github/workflows/release.yml@refs/heads/main",
+        false
+    )]
+    #[case::regexp(
+        IdentityMatcher::Regexp(r"^https://github\.com/kubewarden/.+/release\.yml@refs/tags/v.+$".to_string()),
+        "https://github.com/kubewarden/policies/.github/workflows/release.yml@refs/tags/v1.0.0",
+        true
+    )]
+    #[case::regexp_branch(
+        IdentityMatcher::Regexp(r"^https://github\.com/kubewarden/.+/release\.yml@refs/tags/v.+$".to_string()),
+        "https://github.com/kubewarden/policies/.github/workflows/release.yml@refs/heads/main",
+        false
+    )]
+    fn test_identity_matcher(
+        #[case] matcher: IdentityMatcher,
+        #[case] value: &str,
+        #[case] expected: bool,
+    ) {
+        assert_eq!(matcher.matches(value), expected);
+    }
+
+    #[rstest]
+    #[case::issuer_and_identity(
+        IdentityConstraint {
+            issuer: Some(IdentityMatcher::Equal(GITHUB_ACTIONS_ISSUER.to_string())),
+            identity: Some(IdentityMatcher::Regexp("^https://github.com/".to_string())),
+            ..Default::default()
+        },
+        true
+    )]
+    #[case::invalid_regexp(
+        IdentityConstraint {
+            issuer: Some(IdentityMatcher::Regexp("(".to_string())),
+            ..Default::default()
+        },
+        false
+    )]
+    #[case::no_issuer(
+        IdentityConstraint {
+            identity: Some(IdentityMatcher::Equal("user@example.com".to_string())),
+            ..Default::default()
+        },
+        false
+    )]
+    #[case::repo_without_owner(
+        IdentityConstraint {
+            issuer: Some(IdentityMatcher::Equal(GITHUB_ACTIONS_ISSUER.to_string())),
+            github_repo: Some(IdentityMatcher::Regexp("^policy-".to_string())),
+            ..Default::default()
+        },
+        false
+    )]
+    fn test_validate(#[case] constraint: IdentityConstraint, #[case] valid: bool) {
+        assert_eq!(constraint.validate().is_ok(), valid);
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let yaml = r#"
+issuer:
+  equal: https://token.actions.githubusercontent.com
+identity:
+  regexp: ^https://github.com/kubewarden/
+annotations:
+  env: prod
+"#;
+        let constraint: IdentityConstraint = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            constraint.identity,
+            Some(IdentityMatcher::Regexp(
+                "^https://github.com/kubewarden/".to_string()
+            ))
+        );
+        assert_eq!(constraint.github_owner, None);
+    }
+}
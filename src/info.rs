@@ -1,10 +1,7 @@
 use anyhow::Result;
 use clap::crate_version;
 use itertools::Itertools;
-use policy_evaluator::{
-    burrego,
-    policy_fetcher::store::{DEFAULT_ROOT, Store},
-};
+use policy_evaluator::{burrego, policy_fetcher::store::DEFAULT_ROOT};
 
 pub(crate) fn info() -> Result<()> {
     let builtins: String = burrego::get_builtins()
@@ -13,7 +10,7 @@ pub(crate) fn info() -> Result<()> {
         .map(|builtin| format!("  - {builtin}"))
         .join("\n");
 
-    let store = Store::default();
+    let store = crate::store::open();
 
     println!(
         r#"kwctl version: {}
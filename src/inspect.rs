@@ -27,6 +27,8 @@ use policy_evaluator::{
 use prettytable::{Table, format::FormatBuilder, row};
 use termimad::{FmtText, MadSkin, terminal_size};
 
+use crate::store::{PolicyRecord, Provenance};
+
 pub(crate) async fn inspect(
     uri_or_sha_prefix: &str,
     output: OutputType,
@@ -51,6 +53,17 @@ pub(crate) async fn inspect(
         }
     };
 
+    // Policies outside of the store, or pulled by older versions of kwctl,
+    // do not have a provenance record
+    if let Some(provenance) = PolicyRecord::load(&crate::store::open(), &uri)
+        .ok()
+        .flatten()
+        .and_then(|record| record.provenance)
+    {
+        let provenance_printer = ProvenancePrinter::from(&output);
+        provenance_printer.print(&provenance)?;
+    }
+
     if no_signatures {
         return Ok(());
     }
@@ -273,6 +286,51 @@ impl MetadataPrinter {
     }
 }
 
+enum ProvenancePrinter {
+    Yaml,
+    Pretty,
+}
+
+impl From<&OutputType> for ProvenancePrinter {
+    fn from(output_type: &OutputType) -> Self {
+        match output_type {
+            OutputType::Yaml => Self::Yaml,
+            OutputType::Pretty => Self::Pretty,
+        }
+    }
+}
+
+impl ProvenancePrinter {
+    fn print(&self, provenance: &Provenance) -> Result<()> {
+        match self {
+            ProvenancePrinter::Yaml => {
+                let mut doc_entry: HashMap<String, &Provenance> = HashMap::new();
+                doc_entry.insert("provenance".to_string(), provenance);
+
+                print!("{}", serde_yaml::to_string(&doc_entry)?);
+            }
+            ProvenancePrinter::Pretty => {
+                println!();
+                let mut table = Table::new();
+                table.set_format(FormatBuilder::new().padding(0, 1).build());
+                table.add_row(row![Fmbl -> "Provenance"]);
+                table.add_row(row![Fgbl -> "pulled at:", provenance.pulled_at]);
+                if let Some(manifest_digest) = &provenance.manifest_digest {
+                    table.add_row(row![Fgbl -> "manifest digest:", manifest_digest]);
+                }
+                table.add_row(row![Fgbl -> "insecure source:", provenance.insecure_source]);
+                table.add_row(row![
+                    Fgbl -> "custom certificate authorities:",
+                    provenance.custom_certificate_authorities
+                ]);
+                table.add_row(row![Fgbl -> "verified:", provenance.verified]);
+                table.printstd();
+            }
+        }
+        Ok(())
+    }
+}
+
 enum SignaturesPrinter {
     Yaml,
     Pretty,
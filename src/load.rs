@@ -1,20 +1,379 @@
 use anyhow::{Result, anyhow};
 use flate2::read::GzDecoder;
-use policy_evaluator::policy_fetcher::store::Store;
-use std::fs::File;
-use tar::Archive;
-
-// load policies inside the tarball provided by source_path into the default store
-pub(crate) fn load(source_path: &str) -> Result<()> {
-    let default_store = Store::default();
-    let destination_path = default_store.root;
+use policy_evaluator::policy_fetcher::{
+    policy::Policy,
+    store::{PolicyPath, Store},
+};
+use prettytable::{Table, format, row};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fs::{self, File},
+    io::Read,
+    path::{Component, Path, PathBuf},
+};
+use tar::{Archive, EntryType};
+use tracing::warn;
+
+use crate::{
+    save::{BUNDLE_MANIFEST, BundleEntry, BundleManifest, signatures_path},
+    signatures::BundledSignatures,
+};
+
+pub(crate) enum OutputType {
+    Text,
+    Json,
+}
+
+impl TryFrom<Option<&str>> for OutputType {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Option<&str>) -> Result<Self, Self::Error> {
+        match value {
+            Some("text") | None => Ok(Self::Text),
+            Some("json") => Ok(Self::Json),
+            Some(unknown) => Err(anyhow!("Invalid output format '{}'", unknown)),
+        }
+    }
+}
+
+/// A policy that has been added to the store
+#[derive(Debug, Serialize)]
+pub(crate) struct LoadedPolicy {
+    pub uri: String,
+    pub digest: String,
+    pub local_path: PathBuf,
+    /// The signatures of the policy have been loaded too
+    pub signatures: bool,
+}
+
+/// A file the archive must hold, according to its bundle manifest
+struct ExpectedFile {
+    /// Position of the policy inside of the bundle manifest
+    policy: usize,
+    digest: String,
+    /// The file holds the signatures of the policy, instead of its Wasm module
+    signatures: bool,
+}
+
+// load policies inside the tarball provided by source_path into the policy store.
+// The whole archive is checked against its bundle manifest before the store
+// is changed. Archives created by older releases of kwctl have no bundle
+// manifest, their entries are loaded as long as they are policies.
+pub(crate) fn load(source_path: &str) -> Result<Vec<LoadedPolicy>> {
+    let store = crate::store::open();
+    let entries = read_entries(source_path)?;
+    match entries.first() {
+        Some((path, _)) if path == Path::new(BUNDLE_MANIFEST) => load_bundle(&store, entries),
+        Some(_) => {
+            warn!(
+                archive = source_path,
+                "the archive has no bundle manifest, the digests of its policies cannot be verified"
+            );
+            load_legacy(&store, entries)
+        }
+        None => Err(anyhow!("{} is empty", source_path)),
+    }
+}
+
+/// Reads the regular files of the archive, with their path. Paths escaping
+/// the destination directory and other kinds of entries are rejected
+fn read_entries(source_path: &str) -> Result<Vec<(PathBuf, Vec<u8>)>> {
     let tar_gz =
         File::open(source_path).map_err(|e| anyhow!("cannot open file {}: {}", source_path, e))?;
     let tar = GzDecoder::new(tar_gz);
     let mut archive = Archive::new(tar);
-    archive
-        .unpack(destination_path)
-        .map_err(|e| anyhow!("cannot unpack file {}: {}", source_path, e))?;
 
+    let mut entries = Vec::new();
+    for entry in archive
+        .entries()
+        .map_err(|e| anyhow!("cannot unpack file {}: {}", source_path, e))?
+    {
+        let mut entry = entry.map_err(|e| anyhow!("cannot unpack file {}: {}", source_path, e))?;
+        let entry_path = entry
+            .path()
+            .map_err(|e| anyhow!("cannot unpack file {}: {}", source_path, e))?
+            .to_path_buf();
+        let entry_type = entry.header().entry_type();
+        if entry_type == EntryType::Directory {
+            continue;
+        }
+        if entry_type != EntryType::Regular {
+            return Err(anyhow!(
+                "archive entry {:?} is not a regular file",
+                entry_path
+            ));
+        }
+        let entry_path = safe_entry_path(&entry_path)?;
+
+        let mut contents = Vec::new();
+        entry
+            .read_to_end(&mut contents)
+            .map_err(|e| anyhow!("cannot read archive entry {:?}: {}", entry_path, e))?;
+        entries.push((entry_path, contents));
+    }
+    Ok(entries)
+}
+
+/// Loads an archive starting with a bundle manifest
+fn load_bundle(store: &Store, entries: Vec<(PathBuf, Vec<u8>)>) -> Result<Vec<LoadedPolicy>> {
+    let mut entries = entries.into_iter();
+    let (_, manifest) = entries
+        .next()
+        .expect("the bundle manifest is the first entry");
+    let manifest: BundleManifest = serde_json::from_slice(&manifest)
+        .map_err(|e| anyhow!("cannot parse bundle manifest: {}", e))?;
+    let policies = manifest.policies;
+    let mut expected_files = expected_files(store, &policies)?;
+
+    let mut modules: HashMap<usize, Vec<u8>> = HashMap::new();
+    let mut signatures: HashMap<usize, BundledSignatures> = HashMap::new();
+    for (entry_path, contents) in entries {
+        let file = expected_files
+            .remove(&entry_path)
+            .ok_or_else(|| anyhow!("unexpected archive entry {:?}", entry_path))?;
+        let uri = &policies[file.policy].uri;
+        if format!("{:x}", Sha256::digest(&contents)) != file.digest {
+            return Err(anyhow!(
+                "{} of policy {} does not match the digest recorded in the bundle manifest",
+                if file.signatures {
+                    "signatures"
+                } else {
+                    "module"
+                },
+                uri
+            ));
+        }
+        if file.signatures {
+            let policy_signatures = serde_json::from_slice(&contents)
+                .map_err(|e| anyhow!("cannot parse signatures of policy {}: {}", uri, e))?;
+            signatures.insert(file.policy, policy_signatures);
+        } else {
+            modules.insert(file.policy, contents);
+        }
+    }
+
+    if let Some(file) = expected_files.values().next() {
+        return Err(anyhow!(
+            "policy {} is listed in the bundle manifest, but it is not part of the archive",
+            policies[file.policy].uri
+        ));
+    }
+
+    let mut loaded = Vec::new();
+    for (index, entry) in policies.into_iter().enumerate() {
+        let contents = modules
+            .remove(&index)
+            .ok_or_else(|| anyhow!("policy {} is not part of the archive", entry.uri))?;
+        let policy = write_policy(store, entry.uri, &entry.path, &contents)?;
+        let policy_signatures = signatures.remove(&index);
+        match &policy_signatures {
+            Some(policy_signatures) => {
+                crate::store::save_signatures(store, &policy.uri, policy_signatures)?
+            }
+            None => crate::store::remove_signatures(store, &policy.uri)?,
+        }
+        loaded.push(LoadedPolicy {
+            uri: policy.uri,
+            digest: entry.digest,
+            local_path: policy.local_path,
+            signatures: policy_signatures.is_some(),
+        });
+    }
+
+    Ok(loaded)
+}
+
+/// Loads an archive without bundle manifest: every entry must be a policy,
+/// stored at the path the policy store uses for it
+fn load_legacy(store: &Store, entries: Vec<(PathBuf, Vec<u8>)>) -> Result<Vec<LoadedPolicy>> {
+    let mut policies = Vec::new();
+    for (entry_path, contents) in entries {
+        let uri = legacy_policy_uri(&entry_path)
+            .ok_or_else(|| anyhow!("unexpected archive entry {:?}", entry_path))?;
+        let expected_path = store
+            .policy_path(&uri, PolicyPath::PrefixAndFilename)
+            .map_err(|e| anyhow!("cannot find path for policy {}: {}", uri, e))?;
+        if expected_path != entry_path {
+            return Err(anyhow!("unexpected archive entry {:?}", entry_path));
+        }
+        policies.push((uri, entry_path, contents));
+    }
+
+    let mut loaded = Vec::new();
+    for (uri, path, contents) in policies {
+        let policy = write_policy(store, uri, &path, &contents)?;
+        crate::store::remove_signatures(store, &policy.uri)?;
+        loaded.push(LoadedPolicy {
+            uri: policy.uri,
+            digest: format!("{:x}", Sha256::digest(&contents)),
+            local_path: policy.local_path,
+            signatures: false,
+        });
+    }
+    Ok(loaded)
+}
+
+/// URI of the policy stored at the given path of the store: its first
+/// component is the scheme of the URI
+fn legacy_policy_uri(path: &Path) -> Option<String> {
+    let mut components = path.iter().map(|component| component.to_str());
+    let scheme = components.next()??;
+    if !["registry", "https", "http"].contains(&scheme) {
+        return None;
+    }
+    let rest = components.collect::<Option<Vec<&str>>>()?;
+    if rest.is_empty() {
+        return None;
+    }
+    Some(format!("{}://{}", scheme, rest.join("/")))
+}
+
+/// Writes the module of a policy to the store, recording its digest so that
+/// the store can be verified later
+fn write_policy(store: &Store, uri: String, path: &Path, contents: &[u8]) -> Result<Policy> {
+    let local_path = store.root.join(path);
+    if let Some(parent) = local_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| anyhow!("cannot create directory {:?}: {}", parent, e))?;
+    }
+    fs::write(&local_path, contents)
+        .map_err(|e| anyhow!("cannot write policy {} to the store: {}", uri, e))?;
+
+    let policy = Policy { uri, local_path };
+    crate::store::record_policy(store, &policy, None)?;
+    Ok(policy)
+}
+
+pub(crate) fn print_loaded(loaded: &[LoadedPolicy], output: OutputType) -> Result<()> {
+    match output {
+        OutputType::Json => {
+            println!("{}", serde_json::to_string(loaded)?);
+        }
+        OutputType::Text => {
+            if loaded.is_empty() {
+                return Ok(());
+            }
+            let mut table = Table::new();
+            table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+            table.set_titles(row!["Policy", "SHA-256", "Signatures"]);
+            for policy in loaded {
+                let signatures = if policy.signatures { "yes" } else { "no" };
+                table.add_row(row![policy.uri, policy.digest, signatures]);
+            }
+            table.printstd();
+        }
+    }
     Ok(())
 }
+
+/// Lists the files the archive must hold, indexed by path. The path of each
+/// module must be the one the policy store uses for the policy
+fn expected_files(
+    store: &Store,
+    policies: &[BundleEntry],
+) -> Result<HashMap<PathBuf, ExpectedFile>> {
+    let mut files = HashMap::new();
+    for (index, entry) in policies.iter().enumerate() {
+        let expected_path = store
+            .policy_path(&entry.uri, PolicyPath::PrefixAndFilename)
+            .map_err(|e| anyhow!("cannot find path for policy {}: {}", entry.uri, e))?;
+        if safe_entry_path(&entry.path)? != expected_path {
+            return Err(anyhow!(
+                "unexpected path {:?} for policy {} in the bundle manifest",
+                entry.path,
+                entry.uri
+            ));
+        }
+
+        let mut entry_files = vec![(
+            expected_path.clone(),
+            ExpectedFile {
+                policy: index,
+                digest: entry.digest.clone(),
+                signatures: false,
+            },
+        )];
+        if let Some(signatures) = &entry.signatures {
+            let expected_signatures_path = signatures_path(&expected_path);
+            if safe_entry_path(&signatures.path)? != expected_signatures_path {
+                return Err(anyhow!(
+                    "unexpected path {:?} for the signatures of policy {} in the bundle manifest",
+                    signatures.path,
+                    entry.uri
+                ));
+            }
+            entry_files.push((
+                expected_signatures_path,
+                ExpectedFile {
+                    policy: index,
+                    digest: signatures.digest.clone(),
+                    signatures: true,
+                },
+            ));
+        }
+
+        for (path, file) in entry_files {
+            if files.insert(path, file).is_some() {
+                return Err(anyhow!("duplicated entries in the bundle manifest"));
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Ensures the path of an archive entry stays inside of the destination
+/// directory, rejecting absolute paths and parent directory references
+fn safe_entry_path(path: &Path) -> Result<PathBuf> {
+    let mut safe_path = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => safe_path.push(name),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(anyhow!("unsafe path {:?} inside of the archive", path));
+            }
+        }
+    }
+    if safe_path.as_os_str().is_empty() {
+        return Err(anyhow!("empty path inside of the archive"));
+    }
+    Ok(safe_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::relative("registry/ghcr.io/policy:v1", Some("registry/ghcr.io/policy:v1"))]
+    #[case::current_dir("./registry/ghcr.io/policy:v1", Some("registry/ghcr.io/policy:v1"))]
+    #[case::absolute("/etc/passwd", None)]
+    #[case::parent_dir("registry/../../../etc/passwd", None)]
+    #[case::empty(".", None)]
+    fn test_safe_entry_path(#[case] path: &str, #[case] expected: Option<&str>) {
+        assert_eq!(
+            safe_entry_path(Path::new(path)).ok(),
+            expected.map(PathBuf::from)
+        );
+    }
+
+    #[rstest]
+    #[case::registry(
+        "registry/ghcr.io/kubewarden/tests/pod-privileged:v0.2.5",
+        Some("registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5")
+    )]
+    #[case::https(
+        "https/example.com/policy.wasm",
+        Some("https://example.com/policy.wasm")
+    )]
+    #[case::bundle_manifest("bundle-manifest.json", None)]
+    #[case::unknown_scheme("file/tmp/policy.wasm", None)]
+    #[case::scheme_only("registry", None)]
+    fn test_legacy_policy_uri(#[case] path: &str, #[case] expected: Option<&str>) {
+        assert_eq!(legacy_policy_uri(Path::new(path)).as_deref(), expected);
+    }
+}
@@ -0,0 +1,242 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+use policy_evaluator::policy_fetcher::{PullDestination, sources::Sources};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::config::policy_definition::{PolicyDefinition, normalize_uri};
+
+/// Suffix appended to the name of a YAML file to obtain the name of its lock file
+const LOCK_FILE_SUFFIX: &str = ".lock";
+
+/// Pins the modules referenced by a YAML file holding Kubewarden policies.
+/// Registry modules are pinned to the digest of their OCI manifest, while
+/// https:// modules are pinned to the SHA-256 digest of the Wasm module
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct LockFile {
+    /// Map of the module URIs, as written inside of the YAML file, to the
+    /// digest of their manifest
+    pub policies: BTreeMap<String, String>,
+    /// Map of the https:// module URIs to the SHA-256 digest of the Wasm module
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub checksums: BTreeMap<String, String>,
+}
+
+impl LockFile {
+    /// Path of the lock file of the given YAML file: `policies.yaml` is
+    /// locked by `policies.yaml.lock`
+    pub(crate) fn path_for(yaml_path: &Path) -> PathBuf {
+        let mut path = yaml_path.as_os_str().to_owned();
+        path.push(LOCK_FILE_SUFFIX);
+        PathBuf::from(path)
+    }
+
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let file = fs::File::open(path).map_err(|e| {
+            anyhow!(
+                "cannot open lock file {:?}: {}. Create it using `kwctl lock`",
+                path,
+                e
+            )
+        })?;
+        serde_yaml::from_reader(file)
+            .map_err(|e| anyhow!("cannot parse lock file {:?}: {}", path, e))
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> Result<()> {
+        let contents = serde_yaml::to_string(self)
+            .map_err(|e| anyhow!("cannot serialize lock file: {}", e))?;
+        fs::write(path, contents).map_err(|e| anyhow!("cannot write lock file {:?}: {}", path, e))
+    }
+
+    /// Resolves the digest of every registry module referenced by the given
+    /// policies. Modules coming from other sources cannot be locked
+    pub(crate) async fn resolve(
+        policy_definitions: &[PolicyDefinition],
+        sources: Option<&Sources>,
+    ) -> Result<Self> {
+        let uris: BTreeSet<String> = policy_definitions
+            .iter()
+            .flat_map(|policy_definition| policy_definition.uris())
+            .collect();
+
+        let mut policies = BTreeMap::new();
+        let mut checksums = BTreeMap::new();
+        for uri in uris {
+            let normalized_uri = normalize_uri(&uri);
+            if normalized_uri.starts_with("https://") {
+                let checksum = module_checksum(&uri, sources).await?;
+                checksums.insert(uri, checksum);
+                continue;
+            }
+            if !normalized_uri.starts_with("registry://") {
+                warn!(
+                    policy = uri.as_str(),
+                    "only registry and https policies can be locked, skipping"
+                );
+                continue;
+            }
+            let digest = crate::retry::manifest_digest(&normalized_uri, sources)
+                .await
+                .map_err(|e| anyhow!("cannot resolve digest of policy {}: {}", uri, e))?;
+            policies.insert(uri, digest);
+        }
+        Ok(Self {
+            policies,
+            checksums,
+        })
+    }
+
+    /// SHA-256 digest the Wasm module of an https:// policy must have
+    pub(crate) fn checksum(&self, uri: &str) -> Option<&String> {
+        self.checksums.get(uri)
+    }
+
+    /// Ensures the manifest of a registry module still has the digest recorded
+    /// inside of the lock file.
+    ///
+    /// Returns the locked digest of registry modules: they must be pulled by
+    /// it, the tag could be moved right after this check
+    pub(crate) async fn ensure_locked(
+        &self,
+        uri: &str,
+        sources: Option<&Sources>,
+    ) -> Result<Option<&str>> {
+        let normalized_uri = normalize_uri(uri);
+        if normalized_uri.starts_with("https://") {
+            // the module is checked once it has been downloaded
+            return self.checksum(uri).map(|_| None).ok_or_else(|| {
+                anyhow!(
+                    "policy {} is not part of the lock file. Update it using `kwctl lock`",
+                    uri
+                )
+            });
+        }
+        if !normalized_uri.starts_with("registry://") {
+            return Ok(None);
+        }
+        let locked_digest = self.policies.get(uri).ok_or_else(|| {
+            anyhow!(
+                "policy {} is not part of the lock file. Update it using `kwctl lock`",
+                uri
+            )
+        })?;
+        let digest = crate::retry::manifest_digest(&normalized_uri, sources)
+            .await
+            .map_err(|e| anyhow!("cannot resolve digest of policy {}: {}", uri, e))?;
+        if &digest != locked_digest {
+            return Err(anyhow!(
+                "policy {} resolves to {}, while the lock file expects {}",
+                uri,
+                digest,
+                locked_digest
+            ));
+        }
+        Ok(Some(locked_digest))
+    }
+}
+
+/// Downloads the Wasm module of an https:// policy and computes its digest
+async fn module_checksum(uri: &str, sources: Option<&Sources>) -> Result<String> {
+    let download_dir =
+        tempfile::tempdir().map_err(|e| anyhow!("cannot create temporary directory: {}", e))?;
+    let policy = crate::pull::pull(
+        uri,
+        sources,
+        PullDestination::LocalFile(download_dir.path().join("policy.wasm")),
+    )
+    .await?;
+    let module =
+        fs::read(&policy.local_path).map_err(|e| anyhow!("cannot read policy {}: {}", uri, e))?;
+    Ok(format!("{:x}", Sha256::digest(&module)))
+}
+
+/// Writes the lock file of the given YAML file, returning its path
+pub(crate) async fn lock(
+    yaml_path: &str,
+    output: Option<&str>,
+    sources: Option<&Sources>,
+) -> Result<PathBuf> {
+    let policy_definitions = PolicyDefinition::from_yaml_file(yaml_path)?;
+    let lock_file = LockFile::resolve(&policy_definitions, sources).await?;
+
+    let path = output
+        .map(PathBuf::from)
+        .unwrap_or_else(|| LockFile::path_for(Path::new(yaml_path)));
+    lock_file.save(&path)?;
+    info!(path = ?path, policies = lock_file.policies.len(), "lock file written");
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_for() {
+        assert_eq!(
+            LockFile::path_for(Path::new("manifests/policies.yaml")),
+            PathBuf::from("manifests/policies.yaml.lock")
+        );
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("policies.yaml.lock");
+        let lock_file = LockFile {
+            policies: BTreeMap::from([(
+                "registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5".to_string(),
+                "sha256:0d7a3d0ce1bb8ab43b4e3ac9ee0e3ae1d55c3d3ed2fbbf2ec3c5a0af76c9aacf"
+                    .to_string(),
+            )]),
+            ..Default::default()
+        };
+        lock_file.save(&path).unwrap();
+        assert_eq!(LockFile::load(&path).unwrap(), lock_file);
+    }
+
+    #[tokio::test]
+    async fn test_ensure_locked_rejects_unknown_policy() {
+        let lock_file = LockFile::default();
+        let error = lock_file
+            .ensure_locked(
+                "registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5",
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("is not part of the lock file"));
+    }
+
+    #[tokio::test]
+    async fn test_ensure_locked_https_policy() {
+        let uri = "https://example.com/policy.wasm";
+        let mut lock_file = LockFile::default();
+        assert!(lock_file.ensure_locked(uri, None).await.is_err());
+
+        lock_file.checksums.insert(
+            uri.to_string(),
+            "0d7a3d0ce1bb8ab43b4e3ac9ee0e3ae1d55c3d3ed2fbbf2ec3c5a0af76c9aacf".to_string(),
+        );
+        assert_eq!(lock_file.ensure_locked(uri, None).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_ensure_locked_ignores_local_policies() {
+        let lock_file = LockFile::default();
+        assert_eq!(
+            lock_file
+                .ensure_locked("file:///tmp/policy.wasm", None)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+}
@@ -6,7 +6,7 @@ use anyhow::{Result, anyhow};
 use clap::ArgMatches;
 use itertools::Itertools;
 use lazy_static::lazy_static;
-use policy_evaluator::policy_fetcher::{PullDestination, registry::Registry, store::DEFAULT_ROOT};
+use policy_evaluator::policy_fetcher::{PullDestination, store::DEFAULT_ROOT};
 use rustls::crypto::aws_lc_rs::default_provider;
 use tracing::{debug, info};
 use tracing_subscriber::{
@@ -17,8 +17,13 @@ use tracing_subscriber::{
 
 use crate::{
     config::{
+        kubernetes::KubeClientSettings,
+        policy_definition::{normalize_uri, referenced_uris_from_files},
         sources::remote_server_options,
-        verification::{build_sigstore_trust_root, build_verification_options},
+        verification::{
+            build_sigstore_trust_root, build_sigstore_trust_root_from_config,
+            build_verification_options,
+        },
     },
     load::load,
     save::save,
@@ -26,22 +31,37 @@ use crate::{
 };
 
 mod annotate;
+mod attestations;
 mod backend;
 mod callback_handler;
 mod cli;
 mod command;
 mod completions;
+mod concurrency;
 mod config;
+mod identity;
 mod info;
 mod inspect;
 mod load;
+mod lock;
+mod mirror;
+mod oci_layout;
+mod outdated;
+mod pin;
 mod policies;
+mod prune;
 mod pull;
 mod push;
+mod retry;
 mod rm;
 mod save;
 mod scaffold;
+mod sign;
+mod signatures;
+mod store;
+mod transparency_log;
 mod utils;
+mod verification_report;
 mod verify;
 
 pub(crate) const KWCTL_VERIFICATION_CONFIG: &str = "verification-config.yml";
@@ -110,12 +130,42 @@ async fn main() -> Result<()> {
         )
         .init();
 
+    if let Some(store_path) = matches.get_one::<String>("store-path") {
+        store::set_root(PathBuf::from(store_path))?;
+    }
+    if let Some(retries) = matches.get_one::<u32>("retries") {
+        retry::set_max_retries(*retries)?;
+    }
+
     if let Err(e) = default_provider().install_default() {
         tracing::warn!("Failed to install rustls crypto provider: {:?}", e);
     }
 
     match matches.subcommand_name() {
-        Some("policies") => policies::list(),
+        Some("policies") => {
+            if let Some(matches) = matches.subcommand_matches("policies") {
+                let output = policies::OutputType::try_from(
+                    matches.get_one::<String>("output").map(|s| s.as_str()),
+                )?;
+                let filter = policies::PolicyFilter {
+                    registry: matches.get_one::<String>("registry").cloned(),
+                    mutating: matches
+                        .get_one::<bool>("mutating")
+                        .unwrap_or(&false)
+                        .to_owned(),
+                    context_aware: matches
+                        .get_one::<bool>("context-aware")
+                        .unwrap_or(&false)
+                        .to_owned(),
+                    unannotated: matches
+                        .get_one::<bool>("unannotated")
+                        .unwrap_or(&false)
+                        .to_owned(),
+                };
+                policies::list(output, &filter)?;
+            }
+            Ok(())
+        }
         Some("info") => info::info(),
         Some("pull") => {
             if let Some(matches) = matches.subcommand_matches("pull") {
@@ -127,7 +177,8 @@ async fn main() -> Result<()> {
                     Some(destination) => PullDestination::LocalFile(destination),
                     None => PullDestination::MainStore,
                 };
-                pull_command(uri, destination, matches).await?
+                let sha256 = matches.get_one::<String>("sha256").map(|s| s.as_str());
+                pull_command(uri, destination, sha256, matches).await?
             };
             Ok(())
         }
@@ -137,17 +188,70 @@ async fn main() -> Result<()> {
                 let sources = remote_server_options(matches)?;
                 let verification_options = build_verification_options(matches)?
                     .ok_or_else(|| anyhow!("could not retrieve sigstore options"))?;
-                let sigstore_trust_root =
-                    build_sigstore_trust_root(matches.get_one::<PathBuf>("sigstore-trust-config"))
-                        .await?;
-                verify::verify(
-                    uri,
-                    sources.as_ref(),
-                    &verification_options,
-                    sigstore_trust_root.clone(),
-                )
-                .await
-                .map_err(|e| anyhow!("Policy {} cannot be validated\n{:?}", uri, e))?;
+                let offline = matches
+                    .get_one::<bool>("offline")
+                    .unwrap_or(&false)
+                    .to_owned();
+                if let Some(bundle_path) = matches.get_one::<PathBuf>("bundle") {
+                    // the trust root is provided by the user, bundles are
+                    // verified without any network access
+                    let trust_config = matches
+                        .get_one::<PathBuf>("sigstore-trust-config")
+                        .ok_or_else(|| anyhow!("--bundle requires --sigstore-trust-config"))?;
+                    let sigstore_trust_root = build_sigstore_trust_root_from_config(trust_config)?;
+                    verify::verify_bundle(
+                        uri,
+                        bundle_path,
+                        &verification_options,
+                        sigstore_trust_root,
+                    )
+                    .await
+                    .map_err(|e| anyhow!("Policy {} cannot be validated\n{:?}", uri, e))?;
+                } else if matches.get_one::<String>("output").map(|s| s.as_str()) == Some("json") {
+                    // Sigstore's TUF repository cannot be reached offline
+                    let sigstore_trust_root =
+                        match matches.get_one::<PathBuf>("sigstore-trust-config") {
+                            Some(path) => build_sigstore_trust_root(Some(path)).await?,
+                            None if offline => None,
+                            None => build_sigstore_trust_root(None).await?,
+                        };
+                    let report = verify::verify_with_report(
+                        uri,
+                        sources.as_ref(),
+                        &verification_options,
+                        sigstore_trust_root,
+                        offline,
+                    )
+                    .await?;
+                    serde_json::to_writer_pretty(std::io::stdout(), &report)?;
+                    println!();
+                    if !report.verified {
+                        return Err(anyhow!("Policy {} cannot be validated", uri));
+                    }
+                } else if offline {
+                    // Sigstore's TUF repository cannot be reached, only a
+                    // trust root provided by the user can be used
+                    let sigstore_trust_root =
+                        match matches.get_one::<PathBuf>("sigstore-trust-config") {
+                            Some(path) => build_sigstore_trust_root(Some(path)).await?,
+                            None => None,
+                        };
+                    verify::verify_offline(uri, &verification_options, sigstore_trust_root)
+                        .map_err(|e| anyhow!("Policy {} cannot be validated\n{:?}", uri, e))?;
+                } else {
+                    let sigstore_trust_root = build_sigstore_trust_root(
+                        matches.get_one::<PathBuf>("sigstore-trust-config"),
+                    )
+                    .await?;
+                    verify::verify(
+                        uri,
+                        sources.as_ref(),
+                        &verification_options,
+                        sigstore_trust_root.clone(),
+                    )
+                    .await
+                    .map_err(|e| anyhow!("Policy {} cannot be validated\n{:?}", uri, e))?;
+                }
             };
             Ok(())
         }
@@ -198,6 +302,123 @@ async fn main() -> Result<()> {
             }
             Ok(())
         }
+        Some("prune") => {
+            if let Some(matches) = matches.subcommand_matches("prune") {
+                let referenced_uris = match matches.get_many::<String>("not-referenced-by") {
+                    Some(files) => Some(referenced_uris_from_files(
+                        &files.cloned().collect::<Vec<String>>(),
+                    )?),
+                    None => None,
+                };
+                let criteria = prune::PruneCriteria {
+                    referenced_uris,
+                    older_than: matches
+                        .get_one::<u64>("older-than")
+                        .map(|days| std::time::Duration::from_secs(days * 24 * 60 * 60)),
+                    keep_latest: matches.get_one::<usize>("keep-latest").copied(),
+                };
+                let dry_run = matches
+                    .get_one::<bool>("dry-run")
+                    .unwrap_or(&false)
+                    .to_owned();
+                prune::prune(&criteria, dry_run)?;
+            }
+            Ok(())
+        }
+        Some("lock") => {
+            if let Some(matches) = matches.subcommand_matches("lock") {
+                let yaml_file = matches.get_one::<String>("yaml_file").unwrap();
+                let output = matches.get_one::<String>("output").map(|s| s.as_str());
+                let sources = remote_server_options(matches)?;
+                let path = lock::lock(yaml_file, output, sources.as_ref()).await?;
+                println!("Lock file written to {}", path.display());
+            }
+            Ok(())
+        }
+        Some("outdated") => {
+            if let Some(matches) = matches.subcommand_matches("outdated") {
+                let yaml_files: Vec<String> = matches
+                    .get_many::<String>("yaml_files")
+                    .map(|files| files.cloned().collect())
+                    .unwrap_or_default();
+                let update = if matches
+                    .get_one::<bool>("update")
+                    .unwrap_or(&false)
+                    .to_owned()
+                {
+                    Some(outdated::UpdateLevel::try_from(
+                        matches.get_one::<String>("update-level").unwrap().as_str(),
+                    )?)
+                } else {
+                    None
+                };
+                let sources = remote_server_options(matches)?;
+                outdated::outdated(&yaml_files, sources.as_ref(), update).await?;
+            }
+            Ok(())
+        }
+        Some("pin") => {
+            if let Some(matches) = matches.subcommand_matches("pin") {
+                let dry_run = matches
+                    .get_one::<bool>("dry-run")
+                    .unwrap_or(&false)
+                    .to_owned();
+                let sources = remote_server_options(matches)?;
+                for yaml_file in matches.get_many::<String>("yaml_files").unwrap() {
+                    for module in pin::pin(yaml_file, sources.as_ref(), dry_run).await? {
+                        if dry_run {
+                            println!("{yaml_file}: would pin {} to {}", module.uri, module.pinned);
+                        } else {
+                            println!("{yaml_file}: pinned {} to {}", module.uri, module.pinned);
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+        Some("mirror") => {
+            if let Some(matches) = matches.subcommand_matches("mirror") {
+                let list_files: Vec<String> = matches
+                    .get_many::<String>("policies-file")
+                    .map(|files| files.cloned().collect())
+                    .unwrap_or_default();
+                let crd_files: Vec<String> = matches
+                    .get_many::<String>("crd")
+                    .map(|files| files.cloned().collect())
+                    .unwrap_or_default();
+                let mut uris = mirror::policies_from_files(&list_files, &crd_files)?;
+                if let Some(policies) = matches.get_many::<String>("uris") {
+                    uris.extend(policies.map(|uri| normalize_uri(uri)));
+                }
+                let destination = matches.get_one::<String>("destination").unwrap();
+                let include_signatures = matches
+                    .get_one::<bool>("include-signatures")
+                    .unwrap_or(&false)
+                    .to_owned();
+                let dry_run = matches
+                    .get_one::<bool>("dry-run")
+                    .unwrap_or(&false)
+                    .to_owned();
+                let sources = remote_server_options(matches)?;
+                mirror::mirror(
+                    &uris,
+                    destination,
+                    sources.as_ref(),
+                    include_signatures,
+                    dry_run,
+                )
+                .await?;
+            }
+            Ok(())
+        }
+        Some("store") => {
+            if let Some(matches) = matches.subcommand_matches("store")
+                && matches.subcommand_matches("verify").is_some()
+            {
+                store::verify()?;
+            }
+            Ok(())
+        }
         Some("run") => {
             let run_arg = matches
                 .subcommand_matches("run")
@@ -326,7 +547,15 @@ async fn main() -> Result<()> {
                     None
                 };
 
-                scaffold::admission_request(operation, object_path, old_object_path).await?;
+                let kube_client_settings = KubeClientSettings::from_matches(matches);
+
+                scaffold::admission_request(
+                    operation,
+                    object_path,
+                    old_object_path,
+                    kube_client_settings,
+                )
+                .await?;
             };
 
             Ok(())
@@ -341,25 +570,103 @@ async fn main() -> Result<()> {
             if let Some(matches) = matches.subcommand_matches("digest") {
                 let uri = matches.get_one::<String>("uri").unwrap();
                 let sources = remote_server_options(matches)?;
-                let registry = Registry::new();
-                let digest = registry.manifest_digest(uri, sources.as_ref()).await?;
+                let digest = retry::manifest_digest(uri, sources.as_ref()).await?;
                 println!("{uri}@{digest}");
             }
             Ok(())
         }
+        Some("sign") => {
+            if let Some(matches) = matches.subcommand_matches("sign") {
+                let uri = matches.get_one::<String>("uri").unwrap();
+                let key_path = matches.get_one::<String>("key").unwrap();
+                let annotations = sign::parse_annotations(
+                    matches
+                        .get_many::<String>("annotation")
+                        .into_iter()
+                        .flatten(),
+                )?;
+                let password = env::var(sign::KEY_PASSWORD_ENV_VAR).unwrap_or_default();
+                let sources = remote_server_options(matches)?;
+                let digest = sign::sign(
+                    uri,
+                    sources.as_ref(),
+                    &PathBuf::from(key_path),
+                    &password,
+                    annotations,
+                )
+                .await?;
+                println!("Policy successfully signed: {uri}@{digest}");
+            }
+            Ok(())
+        }
+        Some("generate-key-pair") => {
+            if let Some(matches) = matches.subcommand_matches("generate-key-pair") {
+                let prefix = matches.get_one::<String>("output-key-prefix").unwrap();
+                let password = env::var(sign::KEY_PASSWORD_ENV_VAR).unwrap_or_default();
+                if password.is_empty() {
+                    tracing::warn!(
+                        "{} is not set, the private key is not protected by a password",
+                        sign::KEY_PASSWORD_ENV_VAR
+                    );
+                }
+                let (private_key_path, public_key_path) =
+                    sign::generate_key_pair(prefix, &password)?;
+                println!("Private key written to {}", private_key_path.display());
+                println!("Public key written to {}", public_key_path.display());
+            }
+            Ok(())
+        }
         Some("save") => {
             if let Some(matches) = matches.subcommand_matches("save") {
                 let policies = matches.get_many::<String>("policies").unwrap();
                 let output = matches.get_one::<String>("output").unwrap();
+                let format = save::BundleFormat::try_from(
+                    matches.get_one::<String>("format").map(|s| s.as_str()),
+                )?;
+                let include_signatures = matches
+                    .get_one::<bool>("include-signatures")
+                    .unwrap_or(&false)
+                    .to_owned();
+                let sources = remote_server_options(matches)?;
 
-                save(policies.collect_vec(), output)?;
+                match format {
+                    save::BundleFormat::TarGz => {
+                        save(
+                            policies.collect_vec(),
+                            output,
+                            include_signatures,
+                            sources.as_ref(),
+                        )
+                        .await?
+                    }
+                    save::BundleFormat::OciLayout => {
+                        if include_signatures {
+                            return Err(anyhow!(
+                                "signatures can be included only inside of tar.gz bundles"
+                            ));
+                        }
+                        oci_layout::save(policies.collect_vec(), output, sources.as_ref()).await?
+                    }
+                }
             }
             Ok(())
         }
         Some("load") => {
             if let Some(matches) = matches.subcommand_matches("load") {
                 let input = matches.get_one::<String>("input").unwrap();
-                load(input)?;
+                let format = save::BundleFormat::try_from(
+                    matches.get_one::<String>("format").map(|s| s.as_str()),
+                )?;
+
+                let output = load::OutputType::try_from(
+                    matches.get_one::<String>("output").map(|s| s.as_str()),
+                )?;
+
+                let loaded = match format {
+                    save::BundleFormat::TarGz => load(input)?,
+                    save::BundleFormat::OciLayout => oci_layout::load(input)?,
+                };
+                load::print_loaded(&loaded, output)?;
             }
             Ok(())
         }
@@ -391,7 +698,7 @@ async fn pull_if_needed(uri_or_sha_prefix: &str, matches: &ArgMatches) -> Result
                 "cannot find policy with uri: {}, trying to pull it from remote registry",
                 uri
             );
-            pull_command(&uri, PullDestination::MainStore, matches).await
+            pull_command(&uri, PullDestination::MainStore, None, matches).await
         }
         Err(e) => Err(anyhow!("{}", e)),
         Ok(_path) => Ok(()),
@@ -402,6 +709,7 @@ async fn pull_if_needed(uri_or_sha_prefix: &str, matches: &ArgMatches) -> Result
 async fn pull_command(
     uri: &String,
     destination: PullDestination,
+    expected_sha256: Option<&str>,
     matches: &ArgMatches,
 ) -> Result<()> {
     let sources = remote_server_options(matches)?;
@@ -420,7 +728,32 @@ async fn pull_command(
         );
     }
 
-    let policy = pull::pull(uri, sources.as_ref(), destination).await?;
+    // the policy is pulled by the digest of the verified manifest, even if
+    // its tag has been moved in the meantime
+    let policy = match (expected_sha256, verified_manifest_digest.as_deref()) {
+        (Some(sha256), manifest_digest) => {
+            pull::pull_checked(
+                uri,
+                manifest_digest,
+                sources.as_ref(),
+                destination,
+                sha256,
+                pull::spinner(),
+            )
+            .await?
+        }
+        (None, Some(manifest_digest)) => {
+            pull::pull_by_digest(
+                uri,
+                manifest_digest,
+                sources.as_ref(),
+                destination,
+                pull::spinner(),
+            )
+            .await?
+        }
+        (None, None) => pull::pull(uri, sources.as_ref(), destination).await?,
+    };
 
     if verification_options.is_some() {
         let sigstore_trust_root =
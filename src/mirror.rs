@@ -0,0 +1,274 @@
+use std::{collections::BTreeSet, fs, str::FromStr};
+
+use anyhow::{Result, anyhow};
+use indicatif::ProgressBar;
+use policy_evaluator::policy_fetcher::{
+    PullDestination,
+    oci_client::{Reference, manifest::OciManifest},
+    registry::Registry,
+    sources::Sources,
+};
+use tracing::{debug, warn};
+
+use crate::config::policy_definition::{normalize_uri, referenced_uris_from_files};
+
+/// Collects the URIs of the policies to mirror, reading them from the given
+/// list files and from the Kubewarden Custom Resources defined inside of the
+/// given YAML files.
+///
+/// List files hold one URI per line, empty lines and lines starting with `#`
+/// are ignored.
+pub(crate) fn policies_from_files(
+    list_files: &[String],
+    crd_files: &[String],
+) -> Result<BTreeSet<String>> {
+    let mut uris = BTreeSet::new();
+    for file in list_files {
+        let contents =
+            fs::read_to_string(file).map_err(|e| anyhow!("cannot read file {}: {}", file, e))?;
+        uris.extend(
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(normalize_uri),
+        );
+    }
+    uris.extend(referenced_uris_from_files(crd_files)?);
+    Ok(uris)
+}
+
+/// Copies the given policies to the destination registry, keeping their
+/// repository and tag. Each policy is pulled by the digest of its manifest
+/// and pushed again together with its annotations, like `kwctl pull` and
+/// `kwctl push` do.
+///
+/// When `include_signatures` is set, the cosign signatures of each policy
+/// are copied too, making the failure to find them an error. The signatures
+/// are valid only when the mirrored manifest has the same digest of the
+/// source one, mirroring fails otherwise.
+pub(crate) async fn mirror(
+    uris: &BTreeSet<String>,
+    destination: &str,
+    sources: Option<&Sources>,
+    include_signatures: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let destination = destination
+        .trim_start_matches("registry://")
+        .trim_end_matches('/');
+    if destination.is_empty() {
+        return Err(anyhow!("the destination registry cannot be empty"));
+    }
+
+    for uri in uris {
+        let Some(image_name) = uri.strip_prefix("registry://") else {
+            warn!(
+                policy = uri.as_str(),
+                "only registry policies can be mirrored, skipping"
+            );
+            continue;
+        };
+        let source = Reference::from_str(image_name)
+            .map_err(|e| anyhow!("invalid policy reference {}: {}", uri, e))?;
+        let target = destination_reference(&source, destination)?;
+        let target_uri = format!("registry://{}", target.whole());
+
+        if dry_run {
+            println!("Would mirror {} to {}", uri, target_uri);
+            continue;
+        }
+
+        debug!(
+            policy = uri.as_str(),
+            destination = target_uri.as_str(),
+            "mirroring policy"
+        );
+        let (source_digest, digest) = copy_policy(uri, &target_uri, sources)
+            .await
+            .map_err(|e| anyhow!("cannot mirror policy {}: {}", uri, e))?;
+        if digest != source_digest {
+            if include_signatures {
+                return Err(anyhow!(
+                    "cannot mirror signatures of policy {}: the mirrored manifest {} does not match the signed one {}",
+                    uri,
+                    digest,
+                    source_digest
+                ));
+            }
+            warn!(
+                policy = uri.as_str(),
+                source_digest = source_digest.as_str(),
+                digest = digest.as_str(),
+                "the mirrored policy has a different manifest digest"
+            );
+        }
+
+        if include_signatures {
+            copy_signatures(uri, &source_digest, &target, sources)
+                .await
+                .map_err(|e| anyhow!("cannot mirror signatures of policy {}: {}", uri, e))?;
+        }
+
+        println!(
+            "Mirrored {} to {}@{}{}",
+            uri,
+            target_uri,
+            digest,
+            if include_signatures {
+                ", together with its signatures"
+            } else {
+                ""
+            }
+        );
+    }
+    Ok(())
+}
+
+/// Reference of the mirrored policy: the repository path, the tag and the
+/// digest of the source are kept, while the registry is replaced by the
+/// destination. The destination can include a repository prefix, like
+/// `harbor.local/kubewarden`
+fn destination_reference(source: &Reference, destination: &str) -> Result<Reference> {
+    let mut reference = format!("{}/{}", destination, source.repository());
+    if let Some(tag) = source.tag() {
+        reference.push_str(&format!(":{tag}"));
+    }
+    if let Some(digest) = source.digest() {
+        reference.push_str(&format!("@{digest}"));
+    }
+    Reference::from_str(&reference)
+        .map_err(|e| anyhow!("invalid destination reference {}: {}", reference, e))
+}
+
+/// Pulls the policy by the digest of its manifest and pushes it to
+/// `target_uri`, keeping the annotations of the manifest.
+///
+/// Returns the digests of the source and of the mirrored manifests
+async fn copy_policy(
+    uri: &str,
+    target_uri: &str,
+    sources: Option<&Sources>,
+) -> Result<(String, String)> {
+    let source_digest = crate::retry::manifest_digest(uri, sources).await?;
+    let pinned_uri = crate::pull::pinned_uri(uri, &source_digest)?;
+    let pinned_uri = pinned_uri.as_str();
+    let manifest = crate::retry::with_retries(&format!("fetch manifest of {uri}"), || async move {
+        Registry::new()
+            .manifest(pinned_uri, sources)
+            .await
+            .map_err(anyhow::Error::new)
+    })
+    .await?;
+    let OciManifest::Image(manifest) = manifest else {
+        return Err(anyhow!("{} is not a policy", uri));
+    };
+
+    let download_dir =
+        tempfile::tempdir().map_err(|e| anyhow!("cannot create temporary directory: {}", e))?;
+    let download_path = download_dir.path().join("policy.wasm");
+    crate::pull::pull_by_digest(
+        uri,
+        &source_digest,
+        sources,
+        PullDestination::LocalFile(download_path.clone()),
+        ProgressBar::hidden(),
+    )
+    .await?;
+    let module = fs::read(&download_path)
+        .map_err(|e| anyhow!("cannot read {}: {}", download_path.display(), e))?;
+
+    let module = &module;
+    let annotations = manifest.annotations;
+    let pushed = crate::retry::with_retries(&format!("push policy to {target_uri}"), || {
+        let annotations = annotations.clone();
+        async move {
+            Registry::new()
+                .push(module, target_uri, sources, annotations)
+                .await
+                .map_err(anyhow::Error::new)
+        }
+    })
+    .await?;
+    let digest = pushed
+        .rsplit_once('@')
+        .map(|(_, digest)| digest.to_string())
+        .ok_or_else(|| anyhow!("cannot find the digest of the pushed policy {}", pushed))?;
+    Ok((source_digest, digest))
+}
+
+/// Copies the cosign signatures of the policy manifest with the given digest
+/// next to the mirrored policy. The signature layers are copied as they are,
+/// keeping certificates and transparency log entries.
+async fn copy_signatures(
+    uri: &str,
+    digest: &str,
+    target: &Reference,
+    sources: Option<&Sources>,
+) -> Result<()> {
+    let signatures = crate::signatures::fetch(uri, sources).await?;
+    if signatures.image_digest() != digest {
+        return Err(anyhow!(
+            "the signatures have been fetched for manifest {}, not for the mirrored one {}",
+            signatures.image_digest(),
+            digest
+        ));
+    }
+    let layers = crate::signatures::image_layers(&signatures)?;
+    crate::signatures::push(target, digest, sources, layers).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::tag(
+        "ghcr.io/kubewarden/policies/pod-privileged:v0.2.5",
+        "harbor.local",
+        "harbor.local/kubewarden/policies/pod-privileged:v0.2.5"
+    )]
+    #[case::prefix(
+        "ghcr.io/kubewarden/policies/pod-privileged:v0.2.5",
+        "harbor.local/mirror",
+        "harbor.local/mirror/kubewarden/policies/pod-privileged:v0.2.5"
+    )]
+    #[case::digest(
+        "ghcr.io/kubewarden/policies/pod-privileged@sha256:0d7a3d0ce1bb8ab43b4e3ac9ee0e3ae1d55c3d3ed2fbbf2ec3c5a0af76c9aacf",
+        "localhost:5000",
+        "localhost:5000/kubewarden/policies/pod-privileged@sha256:0d7a3d0ce1bb8ab43b4e3ac9ee0e3ae1d55c3d3ed2fbbf2ec3c5a0af76c9aacf"
+    )]
+    fn test_destination_reference(
+        #[case] source: &str,
+        #[case] destination: &str,
+        #[case] expected: &str,
+    ) {
+        let source = Reference::from_str(source).unwrap();
+        assert_eq!(
+            destination_reference(&source, destination).unwrap().whole(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_policies_from_list_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let list = dir.path().join("policies.txt");
+        fs::write(
+            &list,
+            "# approved policies\nghcr.io/kubewarden/policies/pod-privileged:v0.2.5\n\n  registry://ghcr.io/kubewarden/policies/safe-labels:v0.1.7  \n",
+        )
+        .unwrap();
+
+        let uris = policies_from_files(&[list.to_string_lossy().to_string()], &[]).unwrap();
+        assert_eq!(
+            uris.into_iter().collect::<Vec<_>>(),
+            vec![
+                "registry://ghcr.io/kubewarden/policies/pod-privileged:v0.2.5",
+                "registry://ghcr.io/kubewarden/policies/safe-labels:v0.1.7",
+            ]
+        );
+    }
+}
@@ -0,0 +1,676 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Result, anyhow};
+use policy_evaluator::{
+    policy_fetcher::{
+        PullDestination,
+        oci_client::{
+            self, Reference,
+            annotations::ORG_OPENCONTAINERS_IMAGE_REF_NAME,
+            manifest::{
+                OCI_IMAGE_MEDIA_TYPE, OciDescriptor, OciImageManifest, WASM_CONFIG_MEDIA_TYPE,
+                WASM_LAYER_MEDIA_TYPE,
+            },
+        },
+        policy::Policy,
+        registry::Registry,
+        sigstore::registry::ClientConfig,
+        sources::Sources,
+        store::{PolicyPath, Store},
+    },
+    policy_metadata::Metadata,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{load::LoadedPolicy, store::PolicyRecord};
+
+/// Scheme of the URIs referencing a policy inside of an OCI image layout
+pub(crate) const SCHEME: &str = "oci-layout://";
+
+const OCI_LAYOUT_FILE: &str = "oci-layout";
+const OCI_LAYOUT_VERSION: &str = "1.0.0";
+const INDEX_FILE: &str = "index.json";
+const OCI_IMAGE_INDEX_MEDIA_TYPE: &str = "application/vnd.oci.image.index.v1+json";
+
+/// Annotation of the index entries holding the URI of the policy, as known by
+/// the policy store
+const KUBEWARDEN_ANNOTATION_POLICY_URI: &str = "io.kubewarden.policy.uri";
+
+/// The same configuration pushed by `kwctl push`
+const WASM_CONFIG: &[u8] = b"{}";
+
+/// Contents of the `oci-layout` file
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OciLayout {
+    image_layout_version: String,
+}
+
+/// Contents of the `index.json` file
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Index {
+    schema_version: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    media_type: Option<String>,
+    manifests: Vec<OciDescriptor>,
+}
+
+/// Writes the given policies as an OCI image layout inside of the `output`
+/// directory. Policies must be inside the policy store.
+///
+/// Registry policies keep the manifest and the config they have on the
+/// registry, byte for byte: their digest does not change, hence their cosign
+/// signatures still match. The manifest of the other policies is built the
+/// same way `kwctl push` does, using the annotations of the policy metadata.
+pub(crate) async fn save(
+    policies: Vec<&String>,
+    output: &str,
+    sources: Option<&Sources>,
+) -> Result<()> {
+    let root = PathBuf::from(output);
+    if root.join(INDEX_FILE).exists() {
+        return Err(anyhow!(
+            "{} already contains an OCI image layout",
+            root.display()
+        ));
+    }
+    fs::create_dir_all(blobs_dir(&root))
+        .map_err(|e| anyhow!("cannot create directory {}: {}", root.display(), e))?;
+
+    let layout = OciLayout {
+        image_layout_version: OCI_LAYOUT_VERSION.to_string(),
+    };
+    write_json(&root.join(OCI_LAYOUT_FILE), &layout)?;
+
+    let mut manifests = Vec::new();
+    for policy in policies {
+        let uri = crate::utils::map_path_to_uri(policy.as_str())?;
+        let wasm_path = crate::utils::wasm_path(&uri)
+            .map_err(|e| anyhow!("cannot find policy {}: {}", policy, e))?;
+        let wasm = fs::read(&wasm_path)
+            .map_err(|e| anyhow!("cannot open policy file {}: {}", policy, e))?;
+
+        let mut descriptor = match uri.strip_prefix("registry://") {
+            Some(image_name) => {
+                copy_registry_manifest(&root, &uri, image_name, &wasm, sources).await?
+            }
+            None => build_manifest(&root, &wasm_path, &wasm)
+                .map_err(|e| anyhow!("cannot save policy {}: {}", policy, e))?,
+        };
+        let mut annotations =
+            BTreeMap::from([(KUBEWARDEN_ANNOTATION_POLICY_URI.to_string(), uri.clone())]);
+        if let Some(reference) = uri.strip_prefix("registry://") {
+            annotations.insert(
+                ORG_OPENCONTAINERS_IMAGE_REF_NAME.to_string(),
+                reference.to_string(),
+            );
+        }
+        descriptor.annotations = Some(annotations);
+        manifests.push(descriptor);
+    }
+
+    let index = Index {
+        schema_version: 2,
+        media_type: Some(OCI_IMAGE_INDEX_MEDIA_TYPE.to_string()),
+        manifests,
+    };
+    write_json(&root.join(INDEX_FILE), &index)
+}
+
+/// Writes the manifest of a policy that does not come from a registry, built
+/// like `kwctl push` does
+fn build_manifest(root: &Path, wasm_path: &Path, wasm: &[u8]) -> Result<OciDescriptor> {
+    let annotations = Metadata::from_path(wasm_path)?
+        .and_then(|metadata| metadata.annotations)
+        .map(crate::push::build_oci_annotations);
+    let manifest = OciImageManifest {
+        schema_version: 2,
+        media_type: Some(OCI_IMAGE_MEDIA_TYPE.to_string()),
+        config: write_blob(root, WASM_CONFIG_MEDIA_TYPE, WASM_CONFIG)?,
+        layers: vec![write_blob(root, WASM_LAYER_MEDIA_TYPE, wasm)?],
+        annotations,
+        ..Default::default()
+    };
+    let manifest =
+        serde_json::to_vec(&manifest).map_err(|e| anyhow!("cannot serialize manifest: {}", e))?;
+    write_blob(root, OCI_IMAGE_MEDIA_TYPE, &manifest)
+}
+
+/// Copies the manifest of a registry policy, and the blobs it references,
+/// without changing them. The manifest is the one the policy has been pulled
+/// by, when it has been recorded. The Wasm module of the store must be the
+/// one referenced by the manifest.
+async fn copy_registry_manifest(
+    root: &Path,
+    uri: &str,
+    image_name: &str,
+    wasm: &[u8],
+    sources: Option<&Sources>,
+) -> Result<OciDescriptor> {
+    let image = Reference::from_str(image_name)
+        .map_err(|e| anyhow!("invalid policy reference {}: {}", uri, e))?;
+    let recorded_digest = PolicyRecord::load(&crate::store::open(), uri)?
+        .and_then(|record| record.provenance)
+        .and_then(|provenance| provenance.manifest_digest);
+    let reference = match recorded_digest {
+        Some(digest) => Reference::with_digest(
+            image.registry().to_string(),
+            image.repository().to_string(),
+            digest,
+        ),
+        None => image,
+    };
+
+    let auth = Registry::auth(image_name);
+    let client_config: ClientConfig = sources.cloned().unwrap_or_default().into();
+    let client = oci_client::Client::new(client_config.into());
+    let (client, reference, auth) = (&client, &reference, &auth);
+    let (raw_manifest, digest) =
+        crate::retry::with_retries(&format!("fetch manifest of {uri}"), || async move {
+            client
+                .pull_manifest_raw(reference, auth, &[OCI_IMAGE_MEDIA_TYPE])
+                .await
+                .map_err(|e| anyhow!("cannot fetch manifest of policy {}: {}", uri, e))
+        })
+        .await?;
+    let manifest: OciImageManifest = serde_json::from_slice(&raw_manifest)
+        .map_err(|e| anyhow!("cannot parse manifest of policy {}: {}", uri, e))?;
+
+    for descriptor in std::iter::once(&manifest.config).chain(manifest.layers.iter()) {
+        let data = if descriptor.media_type == WASM_LAYER_MEDIA_TYPE {
+            if sha256_digest(wasm) != descriptor.digest {
+                return Err(anyhow!(
+                    "policy {} inside of the store does not match manifest {}, pull it again",
+                    uri,
+                    digest
+                ));
+            }
+            wasm.to_vec()
+        } else {
+            crate::retry::with_retries(
+                &format!("fetch blob {}", descriptor.digest),
+                || async move {
+                    let mut data: Vec<u8> = Vec::new();
+                    client
+                        .pull_blob(reference, descriptor, &mut data)
+                        .await
+                        .map_err(|e| anyhow!("cannot fetch blob {}: {}", descriptor.digest, e))?;
+                    Ok(data)
+                },
+            )
+            .await?
+        };
+        let written = write_blob(root, &descriptor.media_type, &data)?;
+        if written.digest != descriptor.digest {
+            return Err(anyhow!(
+                "blob {} of policy {} does not match its digest",
+                descriptor.digest,
+                uri
+            ));
+        }
+    }
+
+    let media_type = manifest
+        .media_type
+        .as_deref()
+        .unwrap_or(OCI_IMAGE_MEDIA_TYPE);
+    let descriptor = write_blob(root, media_type, &raw_manifest)?;
+    if descriptor.digest != digest {
+        return Err(anyhow!(
+            "manifest of policy {} does not match its digest {}",
+            uri,
+            digest
+        ));
+    }
+    Ok(descriptor)
+}
+
+/// Loads the policies of the OCI image layout found inside of the `input`
+/// directory into the policy store
+pub(crate) fn load(input: &str) -> Result<Vec<LoadedPolicy>> {
+    let root = PathBuf::from(input);
+    let index = read_index(&root)?;
+
+    let store = crate::store::open();
+    let mut loaded = Vec::new();
+    for entry in index.manifests {
+        let uri = policy_uri(&entry)?;
+
+        let layer = wasm_layer(&root, &entry, &uri)?;
+        let wasm = read_blob(&root, &layer.digest)?;
+        let digest = layer
+            .digest
+            .strip_prefix("sha256:")
+            .unwrap_or(&layer.digest)
+            .to_string();
+
+        let local_path = store
+            .policy_full_path(&uri, PolicyPath::PrefixAndFilename)
+            .map_err(|e| anyhow!("cannot find path for policy {}: {}", uri, e))?;
+        if let Some(parent) = local_path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| anyhow!("cannot create directory {:?}: {}", parent, e))?;
+        }
+        fs::write(&local_path, wasm)
+            .map_err(|e| anyhow!("cannot write policy {} to the store: {}", uri, e))?;
+
+        let policy = Policy { uri, local_path };
+        crate::store::record_policy(&store, &policy, None)?;
+        loaded.push(LoadedPolicy {
+            uri: policy.uri,
+            digest,
+            local_path: policy.local_path,
+            signatures: false,
+        });
+    }
+
+    Ok(loaded)
+}
+
+/// Reference to a policy stored inside of an OCI image layout directory:
+/// `oci-layout://<path>`, `oci-layout://<path>:<tag>` or
+/// `oci-layout://<path>@sha256:<digest>`. The tag can be omitted when the
+/// layout holds a single policy
+#[derive(Debug, PartialEq)]
+pub(crate) struct LayoutUri {
+    pub root: PathBuf,
+    reference: Option<LayoutReference>,
+}
+
+#[derive(Debug, PartialEq)]
+enum LayoutReference {
+    Tag(String),
+    Digest(String),
+}
+
+impl LayoutUri {
+    pub(crate) fn parse(uri: &str) -> Result<Self> {
+        let rest = uri
+            .strip_prefix(SCHEME)
+            .ok_or_else(|| anyhow!("{} is not an {} URI", uri, SCHEME))?;
+        let (root, reference) = if let Some((root, digest)) = rest.rsplit_once('@') {
+            (root, Some(LayoutReference::Digest(digest.to_string())))
+        } else {
+            match rest.rsplit_once(':') {
+                Some((root, tag)) if !root.is_empty() && !tag.contains('/') => {
+                    (root, Some(LayoutReference::Tag(tag.to_string())))
+                }
+                _ => (rest, None),
+            }
+        };
+        if root.is_empty() {
+            return Err(anyhow!("{} does not contain the path of the layout", uri));
+        }
+        Ok(Self {
+            root: PathBuf::from(root),
+            reference,
+        })
+    }
+
+    /// The same reference, with the path of the layout made absolute
+    pub(crate) fn canonicalize(self) -> Result<Self> {
+        let root = self.root.canonicalize().map_err(|e| {
+            anyhow!(
+                "cannot find OCI image layout {}: {}",
+                self.root.display(),
+                e
+            )
+        })?;
+        Ok(Self { root, ..self })
+    }
+
+    /// Finds the entry of the index matching the tag or the digest. Tags are
+    /// compared with the reference name of the manifests, which can be either
+    /// a plain tag or a whole image reference
+    fn find<'a>(&self, index: &'a Index) -> Result<&'a OciDescriptor> {
+        let matches: Vec<&OciDescriptor> = index
+            .manifests
+            .iter()
+            .filter(|entry| match &self.reference {
+                None => true,
+                Some(LayoutReference::Digest(digest)) => &entry.digest == digest,
+                Some(LayoutReference::Tag(tag)) => entry
+                    .annotations
+                    .as_ref()
+                    .and_then(|a| a.get(ORG_OPENCONTAINERS_IMAGE_REF_NAME))
+                    .is_some_and(|name| name == tag || name.ends_with(&format!(":{tag}"))),
+            })
+            .collect();
+        match matches.as_slice() {
+            [entry] => Ok(entry),
+            [] => Err(anyhow!("cannot find policy {} inside of the layout", self)),
+            _ => Err(anyhow!(
+                "{} matches {} policies inside of the layout, use a tag or a digest to choose one",
+                self,
+                matches.len()
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for LayoutUri {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{SCHEME}{}", self.root.display())?;
+        match &self.reference {
+            Some(LayoutReference::Tag(tag)) => write!(f, ":{tag}"),
+            Some(LayoutReference::Digest(digest)) => write!(f, "@{digest}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Path of the Wasm module referenced by an `oci-layout://` URI. The blob is
+/// used in place, after making sure its contents match the digest
+pub(crate) fn wasm_path(uri: &str) -> Result<PathBuf> {
+    let layout_uri = LayoutUri::parse(uri)?;
+    let index = read_index(&layout_uri.root)?;
+    let entry = layout_uri.find(&index)?;
+    let layer = wasm_layer(&layout_uri.root, entry, uri)?;
+    read_blob(&layout_uri.root, &layer.digest)?;
+    blob_path(&layout_uri.root, &layer.digest)
+}
+
+/// Copies the Wasm module referenced by an `oci-layout://` URI to the given
+/// destination. Inside of the policy store, the policy is saved under the
+/// reference recorded by the layout, like `kwctl load` does. The references
+/// that do not match the one used to find the entry are refused
+pub(crate) fn pull(uri: &str, destination: PullDestination) -> Result<Policy> {
+    let layout_uri = LayoutUri::parse(uri)?;
+    let index = read_index(&layout_uri.root)?;
+    let entry = layout_uri.find(&index)?;
+    let layer = wasm_layer(&layout_uri.root, entry, uri)?;
+    let wasm = read_blob(&layout_uri.root, &layer.digest)?;
+
+    let (policy_uri, local_path) = match destination {
+        PullDestination::LocalFile(path) => (uri.to_string(), path),
+        destination => {
+            let store = match destination {
+                PullDestination::Store(root) => Store::new(&root),
+                _ => crate::store::open(),
+            };
+            let policy_uri = policy_uri(entry)?;
+            let local_path = store
+                .policy_full_path(&policy_uri, PolicyPath::PrefixAndFilename)
+                .map_err(|e| anyhow!("cannot find path for policy {}: {}", policy_uri, e))?;
+            (policy_uri, local_path)
+        }
+    };
+    if let Some(parent) = local_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| anyhow!("cannot create directory {:?}: {}", parent, e))?;
+    }
+    fs::write(&local_path, wasm)
+        .map_err(|e| anyhow!("cannot write policy {} to {:?}: {}", uri, local_path, e))?;
+
+    Ok(Policy {
+        uri: policy_uri,
+        local_path,
+    })
+}
+
+fn read_index(root: &Path) -> Result<Index> {
+    let layout: OciLayout = read_json(&root.join(OCI_LAYOUT_FILE))?;
+    if layout.image_layout_version != OCI_LAYOUT_VERSION {
+        return Err(anyhow!(
+            "unsupported OCI image layout version {}",
+            layout.image_layout_version
+        ));
+    }
+    read_json(&root.join(INDEX_FILE))
+}
+
+/// Descriptor of the Wasm layer of the manifest referenced by `entry`
+fn wasm_layer(root: &Path, entry: &OciDescriptor, uri: &str) -> Result<OciDescriptor> {
+    let manifest: OciImageManifest = serde_json::from_slice(&read_blob(root, &entry.digest)?)
+        .map_err(|e| anyhow!("cannot parse manifest of policy {}: {}", uri, e))?;
+    manifest
+        .layers
+        .into_iter()
+        .find(|layer| layer.media_type == WASM_LAYER_MEDIA_TYPE)
+        .ok_or_else(|| anyhow!("policy {} does not have a Wasm layer", uri))
+}
+
+/// URI of the policy described by an entry of the index. Layouts not created
+/// by kwctl only have the reference name of the image.
+///
+/// The URI recorded by kwctl is refused when it does not match the reference
+/// name: an entry must not be able to claim the identity of another policy
+/// of the store, while being found by a different reference
+fn policy_uri(entry: &OciDescriptor) -> Result<String> {
+    let annotations = entry.annotations.as_ref();
+    let reference_uri = annotations
+        .and_then(|a| a.get(ORG_OPENCONTAINERS_IMAGE_REF_NAME))
+        .filter(|reference| reference.contains('/'))
+        .map(|reference| format!("registry://{reference}"));
+    let uri = annotations.and_then(|a| a.get(KUBEWARDEN_ANNOTATION_POLICY_URI));
+    match (uri, reference_uri) {
+        (Some(uri), Some(reference_uri)) if *uri != reference_uri => Err(anyhow!(
+            "policy {} of manifest {} does not match its reference {}",
+            uri,
+            entry.digest,
+            reference_uri
+        )),
+        (Some(uri), None) if uri.starts_with("registry://") => Err(anyhow!(
+            "policy {} of manifest {} does not have a reference name",
+            uri,
+            entry.digest
+        )),
+        (Some(uri), _) => Ok(uri.to_owned()),
+        (None, Some(reference_uri)) => Ok(reference_uri),
+        (None, None) => Err(anyhow!(
+            "cannot find the reference of manifest {}",
+            entry.digest
+        )),
+    }
+}
+
+fn blobs_dir(root: &Path) -> PathBuf {
+    root.join("blobs").join("sha256")
+}
+
+/// Path of the blob with the given digest. Only well formed SHA-256 digests
+/// are accepted, this prevents escaping the blobs directory
+fn blob_path(root: &Path, digest: &str) -> Result<PathBuf> {
+    let hex = digest
+        .strip_prefix("sha256:")
+        .filter(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| anyhow!("invalid digest {}", digest))?;
+    Ok(blobs_dir(root).join(hex))
+}
+
+fn sha256_digest(data: &[u8]) -> String {
+    format!("sha256:{:x}", Sha256::digest(data))
+}
+
+fn write_blob(root: &Path, media_type: &str, data: &[u8]) -> Result<OciDescriptor> {
+    let digest = sha256_digest(data);
+    let path = blob_path(root, &digest)?;
+    fs::write(&path, data).map_err(|e| anyhow!("cannot write blob {}: {}", digest, e))?;
+
+    Ok(OciDescriptor {
+        media_type: media_type.to_string(),
+        digest,
+        size: data.len() as i64,
+        ..Default::default()
+    })
+}
+
+/// Reads a blob, making sure its contents match the digest
+fn read_blob(root: &Path, digest: &str) -> Result<Vec<u8>> {
+    let path = blob_path(root, digest)?;
+    let data = fs::read(&path).map_err(|e| anyhow!("cannot read blob {}: {}", digest, e))?;
+    if sha256_digest(&data) != digest {
+        return Err(anyhow!("blob {} does not match its digest", digest));
+    }
+    Ok(data)
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let data = serde_json::to_vec(value)
+        .map_err(|e| anyhow!("cannot serialize {}: {}", path.display(), e))?;
+    fs::write(path, data).map_err(|e| anyhow!("cannot write {}: {}", path.display(), e))
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<T> {
+    let data = fs::read(path).map_err(|e| anyhow!("cannot read {}: {}", path.display(), e))?;
+    serde_json::from_slice(&data).map_err(|e| anyhow!("cannot parse {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::valid(
+        "sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef",
+        true
+    )]
+    #[case::other_algorithm(
+        "sha512:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef",
+        false
+    )]
+    #[case::too_short("sha256:0123456789abcdef", false)]
+    #[case::path_traversal(
+        "sha256:../../../../../../../../../../../../../../../../../etc/passwd",
+        false
+    )]
+    fn test_blob_path(#[case] digest: &str, #[case] valid: bool) {
+        let root = Path::new("/layout");
+        assert_eq!(blob_path(root, digest).is_ok(), valid);
+    }
+
+    #[test]
+    fn test_blob_round_trip() {
+        let tempdir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(blobs_dir(tempdir.path())).unwrap();
+
+        let descriptor = write_blob(tempdir.path(), WASM_LAYER_MEDIA_TYPE, b"\0asm").unwrap();
+        assert_eq!(descriptor.size, 4);
+        assert_eq!(
+            read_blob(tempdir.path(), &descriptor.digest).unwrap(),
+            b"\0asm"
+        );
+
+        // tamper with the blob
+        fs::write(
+            blob_path(tempdir.path(), &descriptor.digest).unwrap(),
+            b"????",
+        )
+        .unwrap();
+        assert!(read_blob(tempdir.path(), &descriptor.digest).is_err());
+    }
+
+    #[rstest]
+    #[case::tag(
+        "oci-layout://./policies:v1.0.0",
+        "./policies",
+        Some(LayoutReference::Tag("v1.0.0".to_string()))
+    )]
+    #[case::absolute_path(
+        "oci-layout:///srv/policies:latest",
+        "/srv/policies",
+        Some(LayoutReference::Tag("latest".to_string()))
+    )]
+    #[case::digest(
+        "oci-layout://policies@sha256:0123",
+        "policies",
+        Some(LayoutReference::Digest("sha256:0123".to_string()))
+    )]
+    #[case::no_reference("oci-layout://policies", "policies", None)]
+    #[case::colon_inside_of_path("oci-layout://a:b/policies", "a:b/policies", None)]
+    fn test_parse_layout_uri(
+        #[case] uri: &str,
+        #[case] root: &str,
+        #[case] reference: Option<LayoutReference>,
+    ) {
+        let layout_uri = LayoutUri::parse(uri).unwrap();
+        assert_eq!(
+            layout_uri,
+            LayoutUri {
+                root: PathBuf::from(root),
+                reference,
+            }
+        );
+        assert_eq!(layout_uri.to_string(), uri);
+    }
+
+    fn index_entry(digest: &str, ref_name: &str) -> OciDescriptor {
+        OciDescriptor {
+            digest: digest.to_string(),
+            annotations: Some(BTreeMap::from([(
+                ORG_OPENCONTAINERS_IMAGE_REF_NAME.to_string(),
+                ref_name.to_string(),
+            )])),
+            ..Default::default()
+        }
+    }
+
+    #[rstest]
+    #[case::plain_tag("oci-layout://layout:v1.0.0", Some("sha256:aaa"))]
+    #[case::image_reference("oci-layout://layout:v2.0.0", Some("sha256:bbb"))]
+    #[case::digest("oci-layout://layout@sha256:bbb", Some("sha256:bbb"))]
+    #[case::unknown_tag("oci-layout://layout:v3.0.0", None)]
+    #[case::ambiguous("oci-layout://layout", None)]
+    fn test_find_layout_entry(#[case] uri: &str, #[case] expected: Option<&str>) {
+        let index = Index {
+            schema_version: 2,
+            media_type: None,
+            manifests: vec![
+                index_entry("sha256:aaa", "v1.0.0"),
+                index_entry("sha256:bbb", "ghcr.io/kubewarden/policies/psp:v2.0.0"),
+            ],
+        };
+        let found = LayoutUri::parse(uri).unwrap().find(&index).ok();
+        assert_eq!(found.map(|entry| entry.digest.as_str()), expected);
+    }
+
+    #[rstest]
+    #[case::kubewarden_uri(
+        &[(KUBEWARDEN_ANNOTATION_POLICY_URI, "https://example.com/policy.wasm")],
+        Some("https://example.com/policy.wasm")
+    )]
+    #[case::reference_name(
+        &[(ORG_OPENCONTAINERS_IMAGE_REF_NAME, "ghcr.io/kubewarden/policies/psp:v1.0.0")],
+        Some("registry://ghcr.io/kubewarden/policies/psp:v1.0.0")
+    )]
+    #[case::matching_references(
+        &[
+            (KUBEWARDEN_ANNOTATION_POLICY_URI, "registry://ghcr.io/kubewarden/policies/psp:v1.0.0"),
+            (ORG_OPENCONTAINERS_IMAGE_REF_NAME, "ghcr.io/kubewarden/policies/psp:v1.0.0"),
+        ],
+        Some("registry://ghcr.io/kubewarden/policies/psp:v1.0.0")
+    )]
+    #[case::mismatching_references(
+        &[
+            (KUBEWARDEN_ANNOTATION_POLICY_URI, "registry://ghcr.io/kubewarden/policies/psp:v1.0.0"),
+            (ORG_OPENCONTAINERS_IMAGE_REF_NAME, "ghcr.io/evil/psp:v1.0.0"),
+        ],
+        None
+    )]
+    #[case::registry_uri_without_reference(
+        &[(KUBEWARDEN_ANNOTATION_POLICY_URI, "registry://ghcr.io/kubewarden/policies/psp:v1.0.0")],
+        None
+    )]
+    #[case::tag_only(&[(ORG_OPENCONTAINERS_IMAGE_REF_NAME, "v1.0.0")], None)]
+    #[case::no_annotations(&[], None)]
+    fn test_policy_uri(#[case] annotations: &[(&str, &str)], #[case] expected: Option<&str>) {
+        let entry = OciDescriptor {
+            annotations: Some(
+                annotations
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+        assert_eq!(policy_uri(&entry).ok().as_deref(), expected);
+    }
+}
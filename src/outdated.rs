@@ -0,0 +1,351 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    fs,
+    str::FromStr,
+};
+
+use anyhow::{Result, anyhow};
+use policy_evaluator::policy_fetcher::{
+    oci_client::{self, Reference},
+    registry::Registry,
+    sigstore::registry::ClientConfig,
+    sources::Sources,
+};
+use prettytable::{Table, format, row};
+use semver::Version;
+use tracing::debug;
+
+use crate::{
+    config::policy_definition::{PolicyDefinition, normalize_uri},
+    pin::rewrite_modules,
+};
+
+/// The newest release `--update` moves the policies to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum UpdateLevel {
+    /// Latest release sharing the major and minor versions
+    Patch,
+    /// Latest release sharing the major version
+    Minor,
+    /// Latest release
+    Major,
+}
+
+impl TryFrom<&str> for UpdateLevel {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "patch" => Ok(Self::Patch),
+            "minor" => Ok(Self::Minor),
+            "major" => Ok(Self::Major),
+            unknown => Err(anyhow!("Invalid update level '{}'", unknown)),
+        }
+    }
+}
+
+/// The releases of a policy that are newer than the one being used. Only
+/// tags that are valid semantic versions, with an optional `v` prefix, are
+/// taken into account. Pre-releases are ignored
+#[derive(Debug, Default, PartialEq)]
+struct NewerReleases {
+    patch: Option<String>,
+    minor: Option<String>,
+    major: Option<String>,
+}
+
+impl NewerReleases {
+    fn is_empty(&self) -> bool {
+        self.patch.is_none() && self.minor.is_none() && self.major.is_none()
+    }
+
+    fn get(&self, level: UpdateLevel) -> Option<&String> {
+        match level {
+            UpdateLevel::Patch => self.patch.as_ref(),
+            UpdateLevel::Minor => self.minor.as_ref(),
+            UpdateLevel::Major => self.major.as_ref(),
+        }
+    }
+}
+
+/// A policy with newer releases
+struct OutdatedPolicy {
+    uri: String,
+    current: String,
+    newer: NewerReleases,
+}
+
+/// Reports the policies having newer releases. The policies are taken from
+/// the Kubewarden Custom Resources defined inside of the given YAML files or,
+/// when no file is given, from the policy store.
+///
+/// When `update` is set, the modules referenced by the YAML files are moved
+/// to the newest release of the given level.
+pub(crate) async fn outdated(
+    yaml_files: &[String],
+    sources: Option<&Sources>,
+    update: Option<UpdateLevel>,
+) -> Result<()> {
+    let uris = if yaml_files.is_empty() {
+        crate::store::open()
+            .list()
+            .map_err(anyhow::Error::new)?
+            .into_iter()
+            .map(|policy| policy.uri)
+            .collect::<BTreeSet<String>>()
+    } else {
+        let mut uris = BTreeSet::new();
+        for yaml_file in yaml_files {
+            for policy_definition in PolicyDefinition::from_yaml_file(yaml_file)? {
+                uris.extend(policy_definition.uris());
+            }
+        }
+        uris
+    };
+
+    let client_config: ClientConfig = sources.cloned().unwrap_or_default().into();
+    let client = oci_client::Client::new(client_config.into());
+
+    let mut outdated = Vec::new();
+    for uri in uris {
+        let normalized_uri = normalize_uri(&uri);
+        let Some(image) = normalized_uri.strip_prefix("registry://") else {
+            continue;
+        };
+        let reference = Reference::from_str(image)
+            .map_err(|e| anyhow!("invalid policy reference {}: {}", uri, e))?;
+        let Some(tag) = reference.tag() else {
+            continue;
+        };
+        if parse_version(tag).is_none() {
+            debug!(
+                policy = uri.as_str(),
+                "tag is not a semantic version, skipping"
+            );
+            continue;
+        }
+
+        let tags = client
+            .list_tags(&reference, &Registry::auth(image), None, None)
+            .await
+            .map_err(|e| anyhow!("cannot list tags of policy {}: {}", uri, e))?
+            .tags;
+        if let Some(newer) = newer_releases(tag, &tags)
+            && !newer.is_empty()
+        {
+            outdated.push(OutdatedPolicy {
+                current: tag.to_string(),
+                uri,
+                newer,
+            });
+        }
+    }
+
+    print_outdated(&outdated);
+
+    if let Some(level) = update {
+        let updated = updated_uris(&outdated, level, sources).await?;
+        for yaml_file in yaml_files {
+            update_yaml_file(yaml_file, &updated)?;
+        }
+    }
+    Ok(())
+}
+
+fn print_outdated(outdated: &[OutdatedPolicy]) {
+    if outdated.is_empty() {
+        println!("All the policies are up to date");
+        return;
+    }
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(row!["Policy", "Current", "Patch", "Minor", "Major"]);
+    let release = |release: &Option<String>| release.clone().unwrap_or_else(|| "-".to_string());
+    for policy in outdated {
+        table.add_row(row![
+            policy.uri,
+            policy.current,
+            release(&policy.newer.patch),
+            release(&policy.newer.minor),
+            release(&policy.newer.major),
+        ]);
+    }
+    table.printstd();
+}
+
+/// Computes the new URI of each outdated policy. The digest of pinned
+/// references is resolved again, to match the new tag
+async fn updated_uris(
+    outdated: &[OutdatedPolicy],
+    level: UpdateLevel,
+    sources: Option<&Sources>,
+) -> Result<BTreeMap<String, String>> {
+    let mut updated = BTreeMap::new();
+    for policy in outdated {
+        let Some(new_tag) = policy.newer.get(level) else {
+            continue;
+        };
+        let (repository, digest) = split_reference(&policy.uri, &policy.current)?;
+        let new_uri = if digest.is_some() {
+            let tagged_uri = format!("{repository}:{new_tag}");
+            let new_digest = crate::retry::manifest_digest(&normalize_uri(&tagged_uri), sources)
+                .await
+                .map_err(|e| anyhow!("cannot resolve digest of policy {}: {}", tagged_uri, e))?;
+            format!("{tagged_uri}@{new_digest}")
+        } else {
+            format!("{repository}:{new_tag}")
+        };
+        updated.insert(policy.uri.clone(), new_uri);
+    }
+    Ok(updated)
+}
+
+fn update_yaml_file(yaml_file: &str, updated: &BTreeMap<String, String>) -> Result<()> {
+    let contents = fs::read_to_string(yaml_file)
+        .map_err(|e| anyhow!("cannot read file {}: {}", yaml_file, e))?;
+    let (rewritten, replaced) = rewrite_modules(&contents, updated);
+    if replaced.is_empty() {
+        return Ok(());
+    }
+    fs::write(yaml_file, rewritten)
+        .map_err(|e| anyhow!("cannot write file {}: {}", yaml_file, e))?;
+    for uri in &replaced {
+        println!("{yaml_file}: updated {} to {}", uri, updated[uri]);
+    }
+    Ok(())
+}
+
+/// Splits the URI of a policy into the part preceding the tag and the
+/// optional digest
+fn split_reference<'a>(uri: &'a str, tag: &str) -> Result<(&'a str, Option<&'a str>)> {
+    let (tagged, digest) = match uri.split_once('@') {
+        Some((tagged, digest)) => (tagged, Some(digest)),
+        None => (uri, None),
+    };
+    let repository = tagged
+        .strip_suffix(tag)
+        .and_then(|repository| repository.strip_suffix(':'))
+        .ok_or_else(|| anyhow!("cannot find tag {} inside of {}", tag, uri))?;
+    Ok((repository, digest))
+}
+
+fn parse_version(tag: &str) -> Option<Version> {
+    Version::parse(tag.strip_prefix('v').unwrap_or(tag))
+        .ok()
+        .filter(|version| version.pre.is_empty())
+}
+
+/// Finds the newest releases among the given tags. Returns `None` when the
+/// current tag is not a semantic version
+fn newer_releases(current: &str, tags: &[String]) -> Option<NewerReleases> {
+    let current = parse_version(current)?;
+    let mut patch: Option<(Version, &String)> = None;
+    let mut minor: Option<(Version, &String)> = None;
+    let mut major: Option<(Version, &String)> = None;
+
+    for tag in tags {
+        let Some(version) = parse_version(tag) else {
+            continue;
+        };
+        if version <= current {
+            continue;
+        }
+        if version.major == current.major && version.minor == current.minor {
+            keep_newest(&mut patch, &version, tag);
+        }
+        if version.major == current.major {
+            keep_newest(&mut minor, &version, tag);
+        }
+        keep_newest(&mut major, &version, tag);
+    }
+
+    let tag = |release: Option<(Version, &String)>| release.map(|(_, tag)| tag.clone());
+    Some(NewerReleases {
+        patch: tag(patch),
+        minor: tag(minor),
+        major: tag(major),
+    })
+}
+
+fn keep_newest<'a>(newest: &mut Option<(Version, &'a String)>, version: &Version, tag: &'a String) {
+    if newest.as_ref().is_none_or(|(newest, _)| version > newest) {
+        *newest = Some((version.clone(), tag));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn tags() -> Vec<String> {
+        [
+            "v0.1.2",
+            "v0.1.3",
+            "v0.1.10",
+            "v0.2.0",
+            "v0.2.1",
+            "v1.0.0-rc1",
+            "v1.0.0",
+            "latest",
+            "sha256-abc.sig",
+        ]
+        .iter()
+        .map(|tag| tag.to_string())
+        .collect()
+    }
+
+    #[rstest]
+    #[case::outdated("v0.1.2", Some("v0.1.10"), Some("v0.2.1"), Some("v1.0.0"))]
+    #[case::latest_patch("v0.1.10", None, Some("v0.2.1"), Some("v1.0.0"))]
+    #[case::latest_minor("v0.2.1", None, None, Some("v1.0.0"))]
+    #[case::up_to_date("v1.0.0", None, None, None)]
+    fn test_newer_releases(
+        #[case] current: &str,
+        #[case] patch: Option<&str>,
+        #[case] minor: Option<&str>,
+        #[case] major: Option<&str>,
+    ) {
+        assert_eq!(
+            newer_releases(current, &tags()),
+            Some(NewerReleases {
+                patch: patch.map(String::from),
+                minor: minor.map(String::from),
+                major: major.map(String::from),
+            })
+        );
+    }
+
+    #[test]
+    fn test_newer_releases_not_semver() {
+        assert_eq!(newer_releases("latest", &tags()), None);
+    }
+
+    #[rstest]
+    #[case::tag(
+        "registry://ghcr.io/kubewarden/tests/pod-privileged:v0.1.9",
+        "registry://ghcr.io/kubewarden/tests/pod-privileged",
+        None
+    )]
+    #[case::digest(
+        "ghcr.io/kubewarden/tests/pod-privileged:v0.1.9@sha256:abc",
+        "ghcr.io/kubewarden/tests/pod-privileged",
+        Some("sha256:abc")
+    )]
+    #[case::registry_port(
+        "registry://localhost:5000/pod-privileged:v0.1.9",
+        "registry://localhost:5000/pod-privileged",
+        None
+    )]
+    fn test_split_reference(
+        #[case] uri: &str,
+        #[case] repository: &str,
+        #[case] digest: Option<&str>,
+    ) {
+        assert_eq!(
+            split_reference(uri, "v0.1.9").unwrap(),
+            (repository, digest)
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+};
+
+use anyhow::{Result, anyhow};
+use lazy_static::lazy_static;
+use policy_evaluator::policy_fetcher::sources::Sources;
+use regex::{Captures, Regex};
+use tracing::warn;
+
+use crate::config::policy_definition::{PolicyDefinition, normalize_uri};
+
+lazy_static! {
+    /// Matches the `module` field of policies and policy group members, in
+    /// block style YAML
+    static ref MODULE_FIELD: Regex =
+        Regex::new(r#"(?m)^([ \t]*(?:-[ \t]+)?module:[ \t]*)(["']?)([^\s"'#]+)(["']?)"#).unwrap();
+}
+
+/// A module reference replaced by its immutable counterpart
+pub(crate) struct PinnedModule {
+    pub uri: String,
+    pub pinned: String,
+}
+
+/// Rewrites the modules referenced by the Kubewarden policies defined inside
+/// of the given YAML file, turning them into `host/repo:tag@sha256:...`
+/// references. Only the values of the `module` fields are changed, comments
+/// and the order of the documents are preserved.
+///
+/// Modules that are already pinned, or that do not come from a registry, are
+/// left untouched.
+pub(crate) async fn pin(
+    yaml_path: &str,
+    sources: Option<&Sources>,
+    dry_run: bool,
+) -> Result<Vec<PinnedModule>> {
+    let uris: BTreeSet<String> = PolicyDefinition::from_yaml_file(yaml_path)?
+        .iter()
+        .flat_map(|policy_definition| policy_definition.uris())
+        .collect();
+
+    let mut pinned = BTreeMap::new();
+    for uri in uris {
+        let normalized_uri = normalize_uri(&uri);
+        let Some(image) = normalized_uri.strip_prefix("registry://") else {
+            continue;
+        };
+        if image.contains('@') {
+            continue;
+        }
+        let digest = crate::retry::manifest_digest(&normalized_uri, sources)
+            .await
+            .map_err(|e| anyhow!("cannot resolve digest of policy {}: {}", uri, e))?;
+        pinned.insert(uri, format!("{image}@{digest}"));
+    }
+
+    let contents = fs::read_to_string(yaml_path)
+        .map_err(|e| anyhow!("cannot read file {}: {}", yaml_path, e))?;
+    let (rewritten, replaced) = rewrite_modules(&contents, &pinned);
+    for uri in pinned.keys().filter(|uri| !replaced.contains(*uri)) {
+        warn!(
+            policy = uri.as_str(),
+            file = yaml_path,
+            "cannot find the module inside of the file, it has to be pinned by hand"
+        );
+    }
+
+    if !dry_run && rewritten != contents {
+        fs::write(yaml_path, rewritten)
+            .map_err(|e| anyhow!("cannot write file {}: {}", yaml_path, e))?;
+    }
+
+    Ok(pinned
+        .into_iter()
+        .filter(|(uri, _)| replaced.contains(uri))
+        .map(|(uri, pinned)| PinnedModule { uri, pinned })
+        .collect())
+}
+
+/// Replaces the values of the `module` fields found inside of `pinned`.
+/// Returns the new contents and the modules that have been replaced
+pub(crate) fn rewrite_modules(
+    contents: &str,
+    pinned: &BTreeMap<String, String>,
+) -> (String, BTreeSet<String>) {
+    let mut replaced = BTreeSet::new();
+    let rewritten = MODULE_FIELD.replace_all(contents, |caps: &Captures| {
+        let (field, open_quote, uri, close_quote) = (&caps[1], &caps[2], &caps[3], &caps[4]);
+        match pinned.get(uri) {
+            Some(pinned_uri) if open_quote == close_quote => {
+                replaced.insert(uri.to_string());
+                format!("{field}{open_quote}{pinned_uri}{close_quote}")
+            }
+            _ => caps[0].to_string(),
+        }
+    });
+    (rewritten.into_owned(), replaced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGEST: &str = "sha256:0d7a3d0ce1bb8ab43b4e3ac9ee0e3ae1d55c3d3ed2fbbf2ec3c5a0af76c9aacf";
+
+    fn pinned() -> BTreeMap<String, String> {
+        BTreeMap::from([
+            (
+                "registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5".to_string(),
+                format!("ghcr.io/kubewarden/tests/pod-privileged:v0.2.5@{DIGEST}"),
+            ),
+            (
+                "ghcr.io/kubewarden/tests/safe-labels:v0.1.13".to_string(),
+                format!("ghcr.io/kubewarden/tests/safe-labels:v0.1.13@{DIGEST}"),
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_rewrite_single_policy() {
+        let contents = r#"# approved policies
+apiVersion: policies.kubewarden.io/v1
+kind: ClusterAdmissionPolicy
+metadata:
+  name: pod-privileged
+spec:
+  module: registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5 # keep me
+  settings: {}
+---
+apiVersion: policies.kubewarden.io/v1
+kind: ClusterAdmissionPolicy
+metadata:
+  name: other
+spec:
+  module: "registry://ghcr.io/kubewarden/tests/other:v1.0.0"
+"#;
+        let (rewritten, replaced) = rewrite_modules(contents, &pinned());
+        assert_eq!(
+            rewritten,
+            contents.replace(
+                "registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5",
+                &format!("ghcr.io/kubewarden/tests/pod-privileged:v0.2.5@{DIGEST}")
+            )
+        );
+        assert!(rewritten.contains("# keep me"));
+        assert_eq!(
+            replaced,
+            BTreeSet::from([
+                "registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_rewrite_group_members() {
+        let contents = r#"apiVersion: policies.kubewarden.io/v1
+kind: ClusterAdmissionPolicyGroup
+metadata:
+  name: group
+spec:
+  policies:
+    privileged:
+      module: 'registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5'
+    labels:
+      # no scheme
+      module: ghcr.io/kubewarden/tests/safe-labels:v0.1.13
+  expression: "privileged() && labels()"
+"#;
+        let (rewritten, replaced) = rewrite_modules(contents, &pinned());
+        assert!(rewritten.contains(&format!(
+            "      module: 'ghcr.io/kubewarden/tests/pod-privileged:v0.2.5@{DIGEST}'\n"
+        )));
+        assert!(rewritten.contains(&format!(
+            "      module: ghcr.io/kubewarden/tests/safe-labels:v0.1.13@{DIGEST}\n"
+        )));
+        assert!(rewritten.contains("      # no scheme\n"));
+        assert_eq!(replaced.len(), 2);
+    }
+
+    #[test]
+    fn test_rewrite_mismatched_quotes() {
+        let contents =
+            "spec:\n  module: \"registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5'\n";
+        let (rewritten, replaced) = rewrite_modules(contents, &pinned());
+        assert_eq!(rewritten, contents);
+        assert!(replaced.is_empty());
+    }
+}
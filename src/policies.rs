@@ -1,12 +1,153 @@
+use std::convert::TryFrom;
+
 use anyhow::{Result, anyhow};
 use policy_evaluator::{
+    constants::{KUBEWARDEN_ANNOTATION_POLICY_TITLE, KUBEWARDEN_ANNOTATION_POLICY_VERSION},
+    policy_evaluator::PolicyExecutionMode,
     policy_fetcher::{policy::Policy, store::Store},
     policy_metadata::Metadata as PolicyMetadata,
 };
 use prettytable::{Table, format, row};
+use serde::Serialize;
+
+use crate::store::{PolicyRecord, Provenance};
+
+pub(crate) enum OutputType {
+    Pretty,
+    Json,
+    Yaml,
+}
+
+impl TryFrom<Option<&str>> for OutputType {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Option<&str>) -> Result<Self, Self::Error> {
+        match value {
+            Some("json") => Ok(Self::Json),
+            Some("yaml") => Ok(Self::Yaml),
+            None => Ok(Self::Pretty),
+            Some(unknown) => Err(anyhow!("Invalid output format '{}'", unknown)),
+        }
+    }
+}
+
+/// Restricts the policies being listed. All the conditions must be satisfied
+#[derive(Default)]
+pub(crate) struct PolicyFilter {
+    /// Only the policies pulled from this registry
+    pub registry: Option<String>,
+    /// Only the mutating policies
+    pub mutating: bool,
+    /// Only the context aware policies
+    pub context_aware: bool,
+    /// Only the policies without metadata
+    pub unannotated: bool,
+}
+
+impl PolicyFilter {
+    fn matches(&self, entry: &PolicyEntry) -> bool {
+        if let Some(registry) = &self.registry
+            && registry_of(&entry.uri) != Some(registry.as_str())
+        {
+            return false;
+        }
+        if self.mutating && entry.mutating != Some(true) {
+            return false;
+        }
+        if self.context_aware && entry.context_aware != Some(true) {
+            return false;
+        }
+        if self.unannotated && entry.annotated {
+            return false;
+        }
+
+        true
+    }
+}
+
+/// The details of a policy inside of the store
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PolicyEntry {
+    uri: String,
+    /// SHA-256 digest of the Wasm module
+    digest: String,
+    /// Size of the Wasm module, in bytes
+    size: u64,
+    annotated: bool,
+    /// Unknown when the policy is not annotated
+    mutating: Option<bool>,
+    context_aware: Option<bool>,
+    execution_mode: Option<PolicyExecutionMode>,
+    title: Option<String>,
+    version: Option<String>,
+    /// Not available for the policies pulled by older versions of kwctl
+    provenance: Option<Provenance>,
+}
+
+impl PolicyEntry {
+    fn from_policy(store: &Store, policy: &Policy) -> Result<PolicyEntry> {
+        let metadata = PolicyMetadata::from_path(&policy.local_path)
+            .map_err(|e| anyhow!("error processing metadata of policy {}: {:?}", policy, e))?;
+        let policy_filesystem_metadata = std::fs::metadata(&policy.local_path)?;
+
+        let annotation = |name: &str| {
+            metadata
+                .as_ref()
+                .and_then(|metadata| metadata.annotations.as_ref())
+                .and_then(|annotations| annotations.get(name))
+                .cloned()
+        };
+
+        Ok(PolicyEntry {
+            uri: policy.uri.clone(),
+            digest: policy.digest()?,
+            size: policy_filesystem_metadata.len(),
+            annotated: metadata.is_some(),
+            mutating: metadata.as_ref().map(|metadata| metadata.mutating),
+            context_aware: metadata
+                .as_ref()
+                .map(|metadata| !metadata.context_aware_resources.is_empty()),
+            execution_mode: metadata.as_ref().map(|metadata| metadata.execution_mode),
+            title: annotation(KUBEWARDEN_ANNOTATION_POLICY_TITLE),
+            version: annotation(KUBEWARDEN_ANNOTATION_POLICY_VERSION),
+            provenance: PolicyRecord::load(store, &policy.uri)?
+                .and_then(|record| record.provenance),
+        })
+    }
+}
+
+/// Returns the registry of policies pulled via `registry://`
+fn registry_of(uri: &str) -> Option<&str> {
+    uri.strip_prefix("registry://")
+        .and_then(|reference| reference.split('/').next())
+}
+
+pub(crate) fn list(output: OutputType, filter: &PolicyFilter) -> Result<()> {
+    let store = crate::store::open();
+    let mut entries = Vec::new();
+    for policy in store.list().map_err(anyhow::Error::new)? {
+        let entry = PolicyEntry::from_policy(&store, &policy)?;
+        if filter.matches(&entry) {
+            entries.push(entry);
+        }
+    }
+
+    match output {
+        OutputType::Pretty => print_table(&entries),
+        OutputType::Json => {
+            println!("{}", serde_json::to_string_pretty(&entries)?);
+            Ok(())
+        }
+        OutputType::Yaml => {
+            print!("{}", serde_yaml::to_string(&entries)?);
+            Ok(())
+        }
+    }
+}
 
-pub(crate) fn list() -> Result<()> {
-    if policy_list()?.is_empty() {
+fn print_table(entries: &[PolicyEntry]) -> Result<()> {
+    if entries.is_empty() {
         return Ok(());
     }
     let mut table = Table::new();
@@ -15,48 +156,106 @@ pub(crate) fn list() -> Result<()> {
         "Policy",
         "Mutating",
         "Context aware",
+        "Verified",
         "SHA-256",
         "Size"
     ]);
-    for policy in policy_list()? {
-        let (mutating, context_aware) = if let Some(policy_metadata) =
-            PolicyMetadata::from_path(&policy.local_path)
-                .map_err(|e| anyhow!("error processing metadata of policy {}: {:?}", policy, e))?
-        {
-            let mutating = if policy_metadata.mutating {
-                "yes"
-            } else {
-                "no"
-            };
-
-            let context_aware = if policy_metadata.context_aware_resources.is_empty() {
-                "no"
-            } else {
-                "yes"
-            };
-
-            (mutating, context_aware)
+    for entry in entries {
+        let mutating = match entry.mutating {
+            Some(true) => "yes",
+            Some(false) => "no",
+            None => "unknown",
+        };
+        let context_aware = if entry.context_aware == Some(true) {
+            "yes"
         } else {
-            ("unknown", "no")
+            "no"
         };
 
-        let mut sha256sum = policy.digest()?;
-        sha256sum.truncate(12);
+        let verified = match &entry.provenance {
+            Some(provenance) if provenance.verified => "yes",
+            Some(_) => "no",
+            None => "unknown",
+        };
 
-        let policy_filesystem_metadata = std::fs::metadata(&policy.local_path)?;
+        let mut sha256sum = entry.digest.clone();
+        sha256sum.truncate(12);
 
         table.add_row(row![
-            format!("{policy}"),
+            entry.uri,
             mutating,
             context_aware,
+            verified,
             sha256sum,
-            humansize::format_size(policy_filesystem_metadata.len(), humansize::DECIMAL),
+            humansize::format_size(entry.size, humansize::DECIMAL),
         ]);
     }
     table.printstd();
     Ok(())
 }
 
-fn policy_list() -> Result<Vec<Policy>> {
-    Store::default().list().map_err(anyhow::Error::new)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn entry(uri: &str, mutating: Option<bool>, context_aware: Option<bool>) -> PolicyEntry {
+        PolicyEntry {
+            uri: uri.to_string(),
+            digest: "1234".to_string(),
+            size: 42,
+            annotated: mutating.is_some(),
+            mutating,
+            context_aware,
+            execution_mode: None,
+            title: None,
+            version: None,
+            provenance: None,
+        }
+    }
+
+    #[rstest]
+    #[case::registry("registry://ghcr.io/kubewarden/policies/psp:v1", Some("ghcr.io"))]
+    #[case::registry_with_port("registry://localhost:5000/psp:v1", Some("localhost:5000"))]
+    #[case::https("https://example.com/policy.wasm", None)]
+    fn test_registry_of(#[case] uri: &str, #[case] expected: Option<&str>) {
+        assert_eq!(registry_of(uri), expected);
+    }
+
+    #[rstest]
+    #[case::no_filter(PolicyFilter::default(), vec![true, true, true])]
+    #[case::registry(
+        PolicyFilter { registry: Some("ghcr.io".to_string()), ..Default::default() },
+        vec![true, true, false]
+    )]
+    #[case::mutating(
+        PolicyFilter { mutating: true, ..Default::default() },
+        vec![true, false, false]
+    )]
+    #[case::context_aware(
+        PolicyFilter { context_aware: true, ..Default::default() },
+        vec![false, true, false]
+    )]
+    #[case::unannotated(
+        PolicyFilter { unannotated: true, ..Default::default() },
+        vec![false, false, true]
+    )]
+    fn test_policy_filter(#[case] filter: PolicyFilter, #[case] expected: Vec<bool>) {
+        let entries = [
+            entry(
+                "registry://ghcr.io/kubewarden/mutating:v1",
+                Some(true),
+                Some(false),
+            ),
+            entry(
+                "registry://ghcr.io/kubewarden/context-aware:v1",
+                Some(false),
+                Some(true),
+            ),
+            entry("https://example.com/policy.wasm", None, None),
+        ];
+
+        let matches: Vec<bool> = entries.iter().map(|e| filter.matches(e)).collect();
+        assert_eq!(matches, expected);
+    }
 }
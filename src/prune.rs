@@ -0,0 +1,281 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashSet},
+    fs::File,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Result, anyhow};
+use policy_evaluator::policy_fetcher::policy::Policy;
+use tracing::{debug, warn};
+
+use crate::rm::remove_policy;
+
+/// Selects the policies to be removed from the store. A policy is removed
+/// only when it satisfies all the criteria that have been provided.
+#[derive(Default)]
+pub(crate) struct PruneCriteria {
+    /// Remove the policies that are not referenced by any of these URIs
+    pub referenced_uris: Option<HashSet<String>>,
+    /// Remove the policies that have not been used for longer than this
+    pub older_than: Option<Duration>,
+    /// Remove all but the latest `keep_latest` tags of each repository
+    pub keep_latest: Option<usize>,
+}
+
+/// A policy inside of the store, with the time it was last used
+struct StoredPolicy {
+    uri: String,
+    last_used: SystemTime,
+}
+
+pub(crate) fn prune(criteria: &PruneCriteria, dry_run: bool) -> Result<()> {
+    let store = crate::store::open();
+    let policies = store
+        .list()
+        .map_err(anyhow::Error::new)?
+        .iter()
+        .map(stored_policy)
+        .collect::<Result<Vec<StoredPolicy>>>()?;
+
+    for uri in select_policies(&policies, criteria, SystemTime::now()) {
+        if dry_run {
+            println!("Would remove {uri}");
+        } else {
+            remove_policy(&store, uri)?;
+            println!("Removed {uri}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Records the policy stored at the given path has just been used.
+///
+/// The last usage is tracked via the modification time of the file,
+/// that is used later to prune the old policies.
+pub(crate) fn mark_as_used(policy_path: &Path) {
+    if !policy_path.starts_with(&crate::store::open().root) {
+        // not a policy inside of the store, leave it alone
+        return;
+    }
+
+    if let Err(e) = File::options()
+        .write(true)
+        .open(policy_path)
+        .and_then(|file| file.set_modified(SystemTime::now()))
+    {
+        warn!(path = ?policy_path, error = %e, "cannot record policy usage");
+    }
+}
+
+fn stored_policy(policy: &Policy) -> Result<StoredPolicy> {
+    let last_used = std::fs::metadata(&policy.local_path)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|e| anyhow!("cannot read metadata of policy {}: {}", policy, e))?;
+
+    Ok(StoredPolicy {
+        uri: policy.uri.clone(),
+        last_used,
+    })
+}
+
+/// Returns the URIs of the policies matching all the criteria
+fn select_policies<'a>(
+    policies: &'a [StoredPolicy],
+    criteria: &PruneCriteria,
+    now: SystemTime,
+) -> Vec<&'a str> {
+    let superseded = criteria
+        .keep_latest
+        .map(|keep_latest| superseded_policies(policies, keep_latest));
+
+    policies
+        .iter()
+        .filter(|policy| {
+            if let Some(referenced_uris) = &criteria.referenced_uris
+                && referenced_uris.contains(&policy.uri)
+            {
+                return false;
+            }
+            if let Some(older_than) = criteria.older_than {
+                let age = now
+                    .duration_since(policy.last_used)
+                    .unwrap_or(Duration::ZERO);
+                if age <= older_than {
+                    return false;
+                }
+            }
+            if let Some(superseded) = &superseded
+                && !superseded.contains(policy.uri.as_str())
+            {
+                return false;
+            }
+            true
+        })
+        .inspect(|policy| debug!(uri = policy.uri.as_str(), "policy selected for pruning"))
+        .map(|policy| policy.uri.as_str())
+        .collect()
+}
+
+/// Returns the URIs of the policies that are not among the latest
+/// `keep_latest` tags of their repository. Only `registry://` policies
+/// have tags, the other ones are never superseded.
+fn superseded_policies(policies: &[StoredPolicy], keep_latest: usize) -> HashSet<&str> {
+    let mut repositories: BTreeMap<&str, Vec<(&str, &StoredPolicy)>> = BTreeMap::new();
+    for policy in policies {
+        if let Some((repository, tag)) = split_tag(&policy.uri) {
+            repositories
+                .entry(repository)
+                .or_default()
+                .push((tag, policy));
+        }
+    }
+
+    repositories
+        .into_values()
+        .flat_map(|mut tags| {
+            // newest first
+            tags.sort_by(|(tag_a, policy_a), (tag_b, policy_b)| {
+                compare_tags(tag_b, tag_a).then_with(|| policy_b.last_used.cmp(&policy_a.last_used))
+            });
+            tags.into_iter()
+                .skip(keep_latest)
+                .map(|(_, policy)| policy.uri.as_str())
+        })
+        .collect()
+}
+
+/// Splits the URI of a `registry://` policy into its repository and tag
+fn split_tag(uri: &str) -> Option<(&str, &str)> {
+    let reference = uri.strip_prefix("registry://")?;
+    let scheme_len = uri.len() - reference.len();
+
+    let separator = match reference.find('@') {
+        Some(digest_start) => digest_start,
+        None => {
+            let name_start = reference.rfind('/').map(|idx| idx + 1).unwrap_or(0);
+            reference[name_start..].rfind(':')? + name_start
+        }
+    };
+
+    Some((&uri[..scheme_len + separator], &reference[separator + 1..]))
+}
+
+/// Tags that are valid semantic versions are compared as such, and are
+/// considered newer than the other tags
+fn compare_tags(a: &str, b: &str) -> Ordering {
+    let parse = |tag: &str| semver::Version::parse(tag.trim_start_matches('v')).ok();
+    match (parse(a), parse(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Greater,
+        (None, Some(_)) => Ordering::Less,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn policies(now: SystemTime) -> Vec<StoredPolicy> {
+        [
+            ("registry://ghcr.io/kubewarden/policies/psp:v1.0.0", 40),
+            ("registry://ghcr.io/kubewarden/policies/psp:v1.2.0", 20),
+            ("registry://ghcr.io/kubewarden/policies/psp:v1.10.0", 1),
+            (
+                "registry://ghcr.io/kubewarden/policies/safe-labels:v0.1.0",
+                50,
+            ),
+            ("https://example.com/policy.wasm", 100),
+        ]
+        .into_iter()
+        .map(|(uri, days)| StoredPolicy {
+            uri: uri.to_string(),
+            last_used: now - DAY * days,
+        })
+        .collect()
+    }
+
+    #[rstest]
+    #[case::tag(
+        "registry://ghcr.io/kubewarden/policies/psp:v1.0.0",
+        Some(("registry://ghcr.io/kubewarden/policies/psp", "v1.0.0"))
+    )]
+    #[case::registry_with_port(
+        "registry://localhost:5000/psp:latest",
+        Some(("registry://localhost:5000/psp", "latest"))
+    )]
+    #[case::digest(
+        "registry://ghcr.io/psp@sha256:1234",
+        Some(("registry://ghcr.io/psp", "sha256:1234"))
+    )]
+    #[case::no_tag("registry://localhost:5000/psp", None)]
+    #[case::https("https://example.com/policy.wasm", None)]
+    fn test_split_tag(#[case] uri: &str, #[case] expected: Option<(&str, &str)>) {
+        assert_eq!(split_tag(uri), expected);
+    }
+
+    #[test]
+    fn select_older_than() {
+        let now = SystemTime::now();
+        let policies = policies(now);
+        let criteria = PruneCriteria {
+            older_than: Some(DAY * 30),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            select_policies(&policies, &criteria, now),
+            vec![
+                "registry://ghcr.io/kubewarden/policies/psp:v1.0.0",
+                "registry://ghcr.io/kubewarden/policies/safe-labels:v0.1.0",
+                "https://example.com/policy.wasm",
+            ]
+        );
+    }
+
+    #[test]
+    fn select_keep_latest_uses_semver_ordering() {
+        let now = SystemTime::now();
+        let policies = policies(now);
+        let criteria = PruneCriteria {
+            keep_latest: Some(1),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            select_policies(&policies, &criteria, now),
+            vec![
+                "registry://ghcr.io/kubewarden/policies/psp:v1.0.0",
+                "registry://ghcr.io/kubewarden/policies/psp:v1.2.0",
+            ]
+        );
+    }
+
+    #[test]
+    fn select_not_referenced_and_older_than() {
+        let now = SystemTime::now();
+        let policies = policies(now);
+        let criteria = PruneCriteria {
+            referenced_uris: Some(HashSet::from([
+                "registry://ghcr.io/kubewarden/policies/safe-labels:v0.1.0".to_string(),
+            ])),
+            older_than: Some(DAY * 10),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            select_policies(&policies, &criteria, now),
+            vec![
+                "registry://ghcr.io/kubewarden/policies/psp:v1.0.0",
+                "registry://ghcr.io/kubewarden/policies/psp:v1.2.0",
+                "https://example.com/policy.wasm",
+            ]
+        );
+    }
+}
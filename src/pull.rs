@@ -1,28 +1,90 @@
-use std::time::Duration;
+use std::{fs, str::FromStr, time::Duration};
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use indicatif::{ProgressBar, ProgressStyle};
 use policy_evaluator::policy_fetcher::{
-    PullDestination, fetch_policy, policy::Policy, sources::Sources,
+    PullDestination, fetch_policy,
+    oci_client::Reference,
+    policy::Policy,
+    sources::Sources,
+    store::{PolicyPath, Store},
 };
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+use tracing::warn;
+use url::Url;
+
+use crate::{config::policy_definition::normalize_uri, store::Provenance};
 
 pub(crate) async fn pull(
     uri: &str,
     sources: Option<&Sources>,
     destination: PullDestination,
 ) -> Result<Policy> {
-    let pb = ProgressBar::new_spinner();
-    pb.set_style(
-        ProgressStyle::default_spinner()
-            .template("{spinner:.green} {msg}")
-            .expect("cannot set spinner template"),
-    );
+    pull_with_progress(uri, sources, destination, spinner()).await
+}
+
+/// Pulls the policy reporting the progress on the given bar. Used when many
+/// policies are pulled at the same time
+pub(crate) async fn pull_with_progress(
+    uri: &str,
+    sources: Option<&Sources>,
+    destination: PullDestination,
+    pb: ProgressBar,
+) -> Result<Policy> {
+    pull_pinned(uri, None, sources, destination, pb)
+        .await
+        .map(|(policy, _)| policy)
+}
+
+/// Pulls the registry policy whose OCI manifest has the given digest, even
+/// when its tag now references something else. The policy is stored under
+/// `uri`, like the policies pulled by tag
+pub(crate) async fn pull_by_digest(
+    uri: &str,
+    manifest_digest: &str,
+    sources: Option<&Sources>,
+    destination: PullDestination,
+    pb: ProgressBar,
+) -> Result<Policy> {
+    pull_pinned(uri, Some(manifest_digest), sources, destination, pb)
+        .await
+        .map(|(policy, _)| policy)
+}
+
+/// Registry policies are always fetched by the digest of their manifest,
+/// resolved beforehand when not provided. The module that is stored is the
+/// one described by the digest being returned, even if the tag is moved in
+/// the meantime.
+///
+/// Returns the policy and the digest of the manifest it has been pulled by
+async fn pull_pinned(
+    uri: &str,
+    manifest_digest: Option<&str>,
+    sources: Option<&Sources>,
+    destination: PullDestination,
+    pb: ProgressBar,
+) -> Result<(Policy, Option<String>)> {
     pb.set_message(format!("Pulling policy from {}", uri));
     pb.enable_steady_tick(Duration::from_millis(100));
 
-    let result = fetch_policy(uri, destination, sources)
-        .await
-        .map_err(anyhow::Error::new);
+    let to_main_store = matches!(destination, PullDestination::MainStore);
+    let destination = match destination {
+        PullDestination::MainStore => crate::store::pull_destination(),
+        destination => destination,
+    };
+
+    let result = fetch(uri, manifest_digest, sources, destination).await;
+
+    if to_main_store && let Ok((policy, manifest_digest)) = &result {
+        let store = crate::store::open();
+        if policy.local_path.starts_with(&store.root) {
+            let provenance = provenance(uri, sources, manifest_digest.clone());
+            if let Err(e) = crate::store::record_policy(&store, policy, Some(provenance)) {
+                warn!(error = %e, "cannot record the provenance of the policy");
+            }
+        }
+    }
 
     match &result {
         Ok(_) => pb.finish_with_message(format!("Successfully pulled policy from {}", uri)),
@@ -31,3 +93,257 @@ pub(crate) async fn pull(
 
     result
 }
+
+async fn fetch(
+    uri: &str,
+    manifest_digest: Option<&str>,
+    sources: Option<&Sources>,
+    destination: PullDestination,
+) -> Result<(Policy, Option<String>)> {
+    // local policies cannot be affected by transient failures
+    if uri.starts_with(crate::oci_layout::SCHEME) {
+        return crate::oci_layout::pull(uri, destination).map(|policy| (policy, None));
+    }
+    if uri.starts_with("file://") {
+        return fetch_policy(uri, destination, sources)
+            .await
+            .map(|policy| (policy, None))
+            .map_err(anyhow::Error::new);
+    }
+
+    let normalized_uri = normalize_uri(uri);
+    if !normalized_uri.starts_with("registry://") {
+        let destination = &destination;
+        let policy = crate::retry::with_retries(&format!("pull policy {uri}"), || async move {
+            fetch_policy(uri, destination.clone(), sources)
+                .await
+                .map_err(anyhow::Error::new)
+        })
+        .await?;
+        return Ok((policy, None));
+    }
+
+    let manifest_digest = match manifest_digest {
+        Some(digest) => digest.to_string(),
+        None => crate::retry::manifest_digest(&normalized_uri, sources)
+            .await
+            .map_err(|e| anyhow!("cannot resolve digest of policy {}: {}", uri, e))?,
+    };
+    let pinned_uri = pinned_uri(&normalized_uri, &manifest_digest)?;
+    // the policy is stored under its own URI, not under the pinned one
+    let local_path = match destination {
+        PullDestination::LocalFile(path) => path,
+        PullDestination::Store(root) => Store::new(&root)
+            .policy_full_path(&normalized_uri, PolicyPath::PrefixAndFilename)
+            .map_err(|e| anyhow!("cannot find path for policy {}: {}", uri, e))?,
+        PullDestination::MainStore => crate::store::open()
+            .policy_full_path(&normalized_uri, PolicyPath::PrefixAndFilename)
+            .map_err(|e| anyhow!("cannot find path for policy {}: {}", uri, e))?,
+    };
+    if let Some(parent) = local_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| anyhow!("cannot create directory {:?}: {}", parent, e))?;
+    }
+
+    let local_path = &local_path;
+    let pinned_uri = pinned_uri.as_str();
+    crate::retry::with_retries(&format!("pull policy {uri}"), || async move {
+        fetch_policy(
+            pinned_uri,
+            PullDestination::LocalFile(local_path.clone()),
+            sources,
+        )
+        .await
+        .map_err(anyhow::Error::new)
+    })
+    .await?;
+
+    let policy = Policy {
+        uri: uri.to_string(),
+        local_path: local_path.clone(),
+    };
+    Ok((policy, Some(manifest_digest)))
+}
+
+/// URI of the registry policy referenced by the digest of its manifest
+pub(crate) fn pinned_uri(uri: &str, manifest_digest: &str) -> Result<String> {
+    let image = uri.strip_prefix("registry://").unwrap_or(uri);
+    let reference = Reference::from_str(image)
+        .map_err(|e| anyhow!("invalid policy reference {}: {}", uri, e))?;
+    let pinned = Reference::with_digest(
+        reference.registry().to_string(),
+        reference.repository().to_string(),
+        manifest_digest.to_string(),
+    );
+    Ok(format!("registry://{}", pinned.whole()))
+}
+
+/// Pulls the policy, making sure the Wasm module has the expected SHA-256
+/// digest. The module is downloaded to a temporary directory first, nothing
+/// is written to the destination when the digest does not match.
+/// Registry policies are pulled by `manifest_digest` when provided, like
+/// `pull_by_digest` does.
+pub(crate) async fn pull_checked(
+    uri: &str,
+    manifest_digest: Option<&str>,
+    sources: Option<&Sources>,
+    destination: PullDestination,
+    expected_sha256: &str,
+    pb: ProgressBar,
+) -> Result<Policy> {
+    let expected_sha256 = parse_sha256(expected_sha256)?;
+    let download_dir =
+        tempfile::tempdir().map_err(|e| anyhow!("cannot create temporary directory: {}", e))?;
+    let download_path = download_dir.path().join("policy.wasm");
+    let (downloaded, manifest_digest) = pull_pinned(
+        uri,
+        manifest_digest,
+        sources,
+        PullDestination::LocalFile(download_path.clone()),
+        pb,
+    )
+    .await?;
+
+    let module = fs::read(&downloaded.local_path)
+        .map_err(|e| anyhow!("cannot read policy {}: {}", uri, e))?;
+    let digest = format!("{:x}", Sha256::digest(&module));
+    if digest != expected_sha256 {
+        return Err(anyhow!(
+            "policy {} has SHA-256 digest {}, while {} was expected. The policy has not been stored",
+            uri,
+            digest,
+            expected_sha256
+        ));
+    }
+
+    // the policy and its record are kept inside of the store being pulled to
+    let normalized_uri = normalize_uri(uri);
+    let store_path = |store: &Store| {
+        store
+            .policy_full_path(&normalized_uri, PolicyPath::PrefixAndFilename)
+            .map_err(|e| anyhow!("cannot find path for policy {}: {}", uri, e))
+    };
+    let (local_path, store) = match destination {
+        PullDestination::LocalFile(path) => (path, None),
+        PullDestination::Store(root) => {
+            let store = Store::new(&root);
+            (store_path(&store)?, Some(store))
+        }
+        PullDestination::MainStore => {
+            let store = crate::store::open();
+            (store_path(&store)?, Some(store))
+        }
+    };
+    if let Some(parent) = local_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| anyhow!("cannot create directory {:?}: {}", parent, e))?;
+    }
+    fs::write(&local_path, module)
+        .map_err(|e| anyhow!("cannot write policy {} to {:?}: {}", uri, local_path, e))?;
+
+    let policy = Policy {
+        uri: normalized_uri.clone(),
+        local_path,
+    };
+    if let Some(store) = store {
+        let provenance = provenance(uri, sources, manifest_digest);
+        if let Err(e) = crate::store::record_policy(&store, &policy, Some(provenance)) {
+            warn!(error = %e, "cannot record the provenance of the policy");
+        }
+    }
+    Ok(policy)
+}
+
+/// Parses a SHA-256 digest, with an optional `sha256:` prefix, into its
+/// lowercase hex form
+pub(crate) fn parse_sha256(digest: &str) -> Result<String> {
+    let hex = digest.strip_prefix("sha256:").unwrap_or(digest);
+    if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!("invalid SHA-256 digest '{}'", digest));
+    }
+    Ok(hex.to_ascii_lowercase())
+}
+
+pub(crate) fn spinner() -> ProgressBar {
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} {msg}")
+            .expect("cannot set spinner template"),
+    );
+    pb
+}
+
+/// Describes how the policy has just been fetched from `uri`. Registry
+/// policies have been fetched by the given manifest digest
+fn provenance(uri: &str, sources: Option<&Sources>, manifest_digest: Option<String>) -> Provenance {
+    let host = Url::parse(uri).ok().and_then(|url| {
+        url.host_str().map(|host| match url.port() {
+            Some(port) => format!("{host}:{port}"),
+            None => host.to_string(),
+        })
+    });
+    let (insecure_source, custom_certificate_authorities) = match (sources, host) {
+        (Some(sources), Some(host)) => (
+            sources.is_insecure_source(&host),
+            sources.source_authority(&host).is_some(),
+        ),
+        _ => (false, false),
+    };
+
+    Provenance {
+        pulled_at: OffsetDateTime::now_utc(),
+        manifest_digest,
+        insecure_source,
+        custom_certificate_authorities,
+        verified: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    const DIGEST: &str = "0d7a3d0ce1bb8ab43b4e3ac9ee0e3ae1d55c3d3ed2fbbf2ec3c5a0af76c9aacf";
+
+    #[rstest]
+    #[case::hex(DIGEST, true)]
+    #[case::prefixed(
+        "sha256:0d7a3d0ce1bb8ab43b4e3ac9ee0e3ae1d55c3d3ed2fbbf2ec3c5a0af76c9aacf",
+        true
+    )]
+    #[case::uppercase(
+        "0D7A3D0CE1BB8AB43B4E3AC9EE0E3AE1D55C3D3ED2FBBF2EC3C5A0AF76C9AACF",
+        true
+    )]
+    #[case::short("0d7a3d0c", false)]
+    #[case::not_hex(
+        "zz7a3d0ce1bb8ab43b4e3ac9ee0e3ae1d55c3d3ed2fbbf2ec3c5a0af76c9aacf",
+        false
+    )]
+    fn test_parse_sha256(#[case] digest: &str, #[case] valid: bool) {
+        match parse_sha256(digest) {
+            Ok(hex) => {
+                assert!(valid);
+                assert_eq!(hex, DIGEST);
+            }
+            Err(_) => assert!(!valid),
+        }
+    }
+
+    #[rstest]
+    #[case::tag("registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5")]
+    #[case::digest(
+        "registry://ghcr.io/kubewarden/tests/pod-privileged@sha256:0000000000000000000000000000000000000000000000000000000000000000"
+    )]
+    #[case::tag_and_digest(
+        "registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5@sha256:0000000000000000000000000000000000000000000000000000000000000000"
+    )]
+    fn test_pinned_uri(#[case] uri: &str) {
+        assert_eq!(
+            pinned_uri(uri, &format!("sha256:{DIGEST}")).unwrap(),
+            format!("registry://ghcr.io/kubewarden/tests/pod-privileged@sha256:{DIGEST}")
+        );
+    }
+}
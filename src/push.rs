@@ -39,10 +39,17 @@ pub(crate) async fn push(
     let annotations = metadata.and_then(|meta| meta.annotations.map(build_oci_annotations));
 
     let policy = fs::read(&wasm_path).map_err(|e| anyhow!("Cannot open policy file: {:?}", e))?;
-    Registry::new()
-        .push(&policy, uri, sources, annotations)
-        .await
-        .map_err(anyhow::Error::new)
+    crate::retry::with_retries(&format!("push policy to {uri}"), || {
+        let annotations = annotations.clone();
+        let policy = &policy;
+        async move {
+            Registry::new()
+                .push(policy, uri, sources, annotations)
+                .await
+                .map_err(anyhow::Error::new)
+        }
+    })
+    .await
 }
 
 fn can_be_force_pushed_without_metadata(
@@ -58,7 +65,9 @@ fn can_be_force_pushed_without_metadata(
 
 /// Augment the annotations with the `org.opencontainers.image.source`
 /// annotation, if the `io.kubewarden.policy.source` annotation is present.
-fn build_oci_annotations(annotations: BTreeMap<String, String>) -> BTreeMap<String, String> {
+pub(crate) fn build_oci_annotations(
+    annotations: BTreeMap<String, String>,
+) -> BTreeMap<String, String> {
     // filter all the multi-line annotations, they are not supported by the OCI spec
     let mut annotations: BTreeMap<String, String> = annotations
         .iter()
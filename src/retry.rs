@@ -0,0 +1,298 @@
+use std::{
+    collections::hash_map::RandomState,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    io,
+    sync::OnceLock,
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow};
+use policy_evaluator::policy_fetcher::{
+    oci_client::errors::OciDistributionError, registry::Registry, sources::Sources,
+};
+use reqwest::StatusCode;
+use tracing::{info, warn};
+
+/// Number of retries used when the user does not choose one
+pub(crate) const DEFAULT_RETRIES: u32 = 3;
+
+/// Delay before the first retry, doubled at every attempt
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Upper bound of the delay between two attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Messages of the transient failures the fetchers report only as text
+const TRANSIENT_ERROR_MESSAGES: &[&str] = &[
+    "HTTP status server error",
+    "429 Too Many Requests",
+    "TOOMANYREQUESTS",
+];
+
+/// Kinds of I/O errors caused by the network. The other ones, like a missing
+/// file or a full disk, would make every attempt fail
+const TRANSIENT_IO_ERRORS: &[io::ErrorKind] = &[
+    io::ErrorKind::ConnectionReset,
+    io::ErrorKind::ConnectionAborted,
+    io::ErrorKind::ConnectionRefused,
+    io::ErrorKind::TimedOut,
+    io::ErrorKind::UnexpectedEof,
+    io::ErrorKind::BrokenPipe,
+];
+
+/// Number of retries chosen by the user, `None` when the default has to be used
+static MAX_RETRIES: OnceLock<u32> = OnceLock::new();
+
+/// Sets how many times the network operations are retried. Must be invoked
+/// before any network operation is started.
+pub(crate) fn set_max_retries(retries: u32) -> Result<()> {
+    MAX_RETRIES
+        .set(retries)
+        .map_err(|_| anyhow!("the number of retries has already been set"))
+}
+
+fn max_retries() -> u32 {
+    MAX_RETRIES.get().copied().unwrap_or(DEFAULT_RETRIES)
+}
+
+/// Runs `operation`, retrying it with exponential backoff and jitter when it
+/// fails with a transient error. `description` names the operation inside of
+/// the messages reporting the failures.
+pub(crate) async fn with_retries<T, F, Fut>(description: &str, operation: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    retry(description, max_retries(), INITIAL_BACKOFF, operation).await
+}
+
+/// Resolves the manifest digest of a registry policy, retrying on failures
+pub(crate) async fn manifest_digest(uri: &str, sources: Option<&Sources>) -> Result<String> {
+    with_retries(&format!("resolve digest of {uri}"), || async move {
+        Registry::new()
+            .manifest_digest(uri, sources)
+            .await
+            .map_err(anyhow::Error::new)
+    })
+    .await
+}
+
+async fn retry<T, F, Fut>(
+    description: &str,
+    retries: u32,
+    initial_backoff: Duration,
+    mut operation: F,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt = 0;
+    loop {
+        match operation().await {
+            Ok(result) => {
+                if attempt > 0 {
+                    info!(
+                        operation = description,
+                        retries = attempt,
+                        "operation succeeded after retrying"
+                    );
+                }
+                return Ok(result);
+            }
+            Err(e) if attempt >= retries || !is_transient(&e) => {
+                if attempt == 0 {
+                    return Err(e);
+                }
+                return Err(anyhow!(
+                    "cannot {} after {} attempts, last error: {}",
+                    description,
+                    attempt + 1,
+                    e
+                ));
+            }
+            Err(e) => {
+                let delay = backoff(initial_backoff, attempt);
+                attempt += 1;
+                warn!(
+                    operation = description,
+                    attempt,
+                    retries,
+                    error = %e,
+                    "operation failed, retrying in {:?}",
+                    delay
+                );
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+/// Whether the failure is worth retrying: server errors, rate limiting and
+/// connection problems are transient, while a missing policy or wrong
+/// credentials would make every attempt fail
+fn is_transient(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            return e.is_connect() || e.is_timeout() || e.status().is_some_and(is_transient_status);
+        }
+        if let Some(OciDistributionError::ServerError { code, .. }) = cause.downcast_ref() {
+            return StatusCode::from_u16(*code).is_ok_and(is_transient_status);
+        }
+        if let Some(e) = cause.downcast_ref::<io::Error>() {
+            return TRANSIENT_IO_ERRORS.contains(&e.kind());
+        }
+        let message = cause.to_string();
+        TRANSIENT_ERROR_MESSAGES
+            .iter()
+            .any(|transient| message.contains(transient))
+    })
+}
+
+fn is_transient_status(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Exponential backoff with jitter: the delay is picked randomly between half
+/// and the whole of `initial_backoff * 2^attempt`, capped to `MAX_BACKOFF`
+fn backoff(initial_backoff: Duration, attempt: u32) -> Duration {
+    let delay = initial_backoff
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_BACKOFF);
+    let jitter = RandomState::new().build_hasher().finish() % 1_000;
+    delay / 2 + (delay / 2).mul_f64(jitter as f64 / 1_000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    const BAD_GATEWAY: &str =
+        "HTTP status server error (502 Bad Gateway) for url (http://127.0.0.1/policy.wasm)";
+
+    async fn flaky(calls: &AtomicU32, failures: u32) -> Result<u32> {
+        let call = calls.fetch_add(1, Ordering::SeqCst);
+        if call < failures {
+            Err(anyhow!(BAD_GATEWAY))
+        } else {
+            Ok(call)
+        }
+    }
+
+    async fn missing(calls: &AtomicU32) -> Result<u32> {
+        calls.fetch_add(1, Ordering::SeqCst);
+        Err(anyhow!("manifest unknown"))
+    }
+
+    async fn missing_file(calls: &AtomicU32) -> Result<u32> {
+        calls.fetch_add(1, Ordering::SeqCst);
+        Err(io::Error::from(io::ErrorKind::NotFound).into())
+    }
+
+    #[tokio::test]
+    async fn test_retry_recovers_from_transient_failures() {
+        let calls = AtomicU32::new(0);
+        let result = retry("pull policy", 3, Duration::from_millis(1), || {
+            flaky(&calls, 2)
+        })
+        .await;
+        assert_eq!(result.unwrap(), 2);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_reports_last_error() {
+        let calls = AtomicU32::new(0);
+        let error = retry("pull policy", 2, Duration::from_millis(1), || {
+            flaky(&calls, 5)
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("cannot pull policy after 3 attempts, last error: {BAD_GATEWAY}")
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_disabled() {
+        let calls = AtomicU32::new(0);
+        let error = retry("pull policy", 0, Duration::from_millis(1), || {
+            flaky(&calls, 1)
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(error.to_string(), BAD_GATEWAY);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_skips_permanent_failures() {
+        let calls = AtomicU32::new(0);
+        let error = retry("pull policy", 3, Duration::from_millis(1), || {
+            missing(&calls)
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(error.to_string(), "manifest unknown");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_skips_local_io_failures() {
+        let calls = AtomicU32::new(0);
+        let error = retry("pull policy", 3, Duration::from_millis(1), || {
+            missing_file(&calls)
+        })
+        .await
+        .unwrap_err();
+        assert!(error.is::<io::Error>());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[rstest]
+    #[case::server_error(anyhow!(BAD_GATEWAY), true)]
+    #[case::rate_limited(anyhow!("HTTP status client error (429 Too Many Requests) for url (http://127.0.0.1)"), true)]
+    #[case::connection_reset(
+        anyhow::Error::new(io::Error::from(io::ErrorKind::ConnectionReset)),
+        true
+    )]
+    #[case::file_not_found(anyhow::Error::new(io::Error::from(io::ErrorKind::NotFound)), false)]
+    #[case::permission_denied(
+        anyhow::Error::new(io::Error::from(io::ErrorKind::PermissionDenied)),
+        false
+    )]
+    #[case::registry_unavailable(
+        anyhow::Error::new(OciDistributionError::ServerError {
+            code: 503,
+            url: "https://ghcr.io".to_string(),
+            message: "unavailable".to_string(),
+        }),
+        true
+    )]
+    #[case::registry_not_found(
+        anyhow::Error::new(OciDistributionError::ServerError {
+            code: 404,
+            url: "https://ghcr.io".to_string(),
+            message: "not found".to_string(),
+        }),
+        false
+    )]
+    #[case::not_found(anyhow!("HTTP status client error (404 Not Found) for url (http://127.0.0.1)"), false)]
+    fn test_is_transient(#[case] error: anyhow::Error, #[case] transient: bool) {
+        assert_eq!(is_transient(&error), transient);
+    }
+
+    #[rstest]
+    #[case(0, Duration::from_millis(250), Duration::from_millis(500))]
+    #[case(2, Duration::from_secs(1), Duration::from_secs(2))]
+    #[case(20, MAX_BACKOFF / 2, MAX_BACKOFF)]
+    fn test_backoff(#[case] attempt: u32, #[case] min: Duration, #[case] max: Duration) {
+        let delay = backoff(INITIAL_BACKOFF, attempt);
+        assert!(delay >= min && delay <= max, "unexpected delay {delay:?}");
+    }
+}
@@ -7,15 +7,23 @@ use crate::utils::LookupError;
 pub(crate) fn rm(uri_or_sha_prefix: &str) -> Result<()> {
     let uri = crate::utils::get_uri(&uri_or_sha_prefix.to_string())?;
 
-    let store = Store::default();
+    let store = crate::store::open();
 
     if store.get_policy_by_uri(&uri)?.is_none() {
         return Err(anyhow!(LookupError::PolicyMissing(uri)));
     }
 
-    let policy_path = store.policy_full_path(&uri, PolicyPath::PrefixAndFilename)?;
+    remove_policy(&store, &uri)
+}
+
+/// Deletes the policy identified by `uri` from the store, together with
+/// the directories that are left empty
+pub(crate) fn remove_policy(store: &Store, uri: &str) -> Result<()> {
+    let policy_path = store.policy_full_path(uri, PolicyPath::PrefixAndFilename)?;
     std::fs::remove_file(&policy_path)
         .map_err(|err| anyhow!("could not delete policy {}: {}", uri, err))?;
+    crate::store::PolicyRecord::remove(store, uri)?;
+    crate::store::remove_signatures(store, uri)?;
 
     // Given a policy in the store, try to cleanup all intermediate
     // directories up to the store root, from the innermost to the
@@ -1,30 +1,172 @@
 use anyhow::{Result, anyhow};
 use flate2::Compression;
 use flate2::write::GzEncoder;
-use policy_evaluator::policy_fetcher::store::{PolicyPath, Store};
-use std::fs::File;
+use policy_evaluator::policy_fetcher::{sources::Sources, store::PolicyPath};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    convert::TryFrom,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use tracing::warn;
+
+use crate::store::PolicyRecord;
+
+/// How the policies are bundled together
+pub(crate) enum BundleFormat {
+    /// A tar.gz file holding the Wasm modules, laid out like the policy store
+    TarGz,
+    /// An OCI image layout directory, holding the manifest of each policy
+    OciLayout,
+}
+
+impl TryFrom<Option<&str>> for BundleFormat {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Option<&str>) -> Result<Self, Self::Error> {
+        match value {
+            Some("tar.gz") | None => Ok(Self::TarGz),
+            Some("oci-layout") => Ok(Self::OciLayout),
+            Some(unknown) => Err(anyhow!("Invalid bundle format '{}'", unknown)),
+        }
+    }
+}
+
+/// Name of the archive entry listing the policies of the bundle
+pub(crate) const BUNDLE_MANIFEST: &str = "bundle-manifest.json";
+
+/// Directory of the archive holding the signatures of the policies
+const SIGNATURES_DIR: &str = "signatures";
+
+/// Index of the policies saved inside of a tarball. It is always the first
+/// entry of the archive
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct BundleManifest {
+    pub policies: Vec<BundleEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct BundleEntry {
+    pub uri: String,
+    /// Path of the Wasm module inside of the archive
+    pub path: PathBuf,
+    /// SHA-256 digest of the Wasm module
+    pub digest: String,
+    /// The cosign signatures of the policy, when they have been included
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signatures: Option<BundleFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct BundleFile {
+    /// Path of the file inside of the archive
+    pub path: PathBuf,
+    /// SHA-256 digest of the file
+    pub digest: String,
+}
+
+/// Path inside of the archive of the signatures of the policy stored at `policy_path`
+pub(crate) fn signatures_path(policy_path: &Path) -> PathBuf {
+    let mut path = Path::new(SIGNATURES_DIR).join(policy_path).into_os_string();
+    path.push(".json");
+    PathBuf::from(path)
+}
 
 // saves all policies in a tarball with the name provided as output.
-// policies must be inside the default store.
-pub(crate) fn save(policies: Vec<&String>, output: &str) -> Result<()> {
-    let tar_gz =
-        File::create(output).map_err(|e| anyhow!("cannot create file {}: {}", output, e))?;
-    let enc = GzEncoder::new(tar_gz, Compression::default());
-    let mut tar = tar::Builder::new(enc);
+// policies must be inside the policy store. When `include_signatures` is set,
+// the cosign signatures of the registry policies are fetched and saved too.
+pub(crate) async fn save(
+    policies: Vec<&String>,
+    output: &str,
+    include_signatures: bool,
+    sources: Option<&Sources>,
+) -> Result<()> {
+    let store = crate::store::open();
 
+    let mut entries = Vec::new();
+    let mut files = Vec::new();
     for policy in policies {
-        let store = Store::default();
         let uri = crate::utils::map_path_to_uri(policy.as_str())?;
         let wasm_path = crate::utils::wasm_path(&uri)
             .map_err(|e| anyhow!("cannot find policy {}: {}", policy, e))?;
-        let mut file = File::open(wasm_path)
+        let module = fs::read(wasm_path)
             .map_err(|e| anyhow!("cannot open policy file {}: {}", policy, e))?;
         let policy_path = store
             .policy_path(&uri, PolicyPath::PrefixAndFilename)
             .map_err(|e| anyhow!("cannot find path for policy {}: {}", policy, e))?;
-        tar.append_file(policy_path, &mut file)
-            .map_err(|e| anyhow!("cannot append policy {} to tar file: {}", policy, e))?;
+
+        let signatures = if include_signatures && uri.starts_with("registry://") {
+            // the tag may have moved since the pull, the signatures are the
+            // ones of the manifest the stored module comes from
+            let manifest_digest = PolicyRecord::load(&store, &uri)?
+                .and_then(|record| record.provenance)
+                .and_then(|provenance| provenance.manifest_digest)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "cannot fetch signatures of policy {}: the manifest it has been pulled from is unknown, pull it again",
+                        policy
+                    )
+                })?;
+            let pinned_uri = crate::pull::pinned_uri(&uri, &manifest_digest)?;
+            let signatures = crate::signatures::fetch(&pinned_uri, sources)
+                .await
+                .map_err(|e| anyhow!("cannot fetch signatures of policy {}: {}", policy, e))?;
+            let signatures = serde_json::to_vec(&signatures)
+                .map_err(|e| anyhow!("cannot serialize signatures of {}: {}", policy, e))?;
+            let file = BundleFile {
+                path: signatures_path(&policy_path),
+                digest: format!("{:x}", Sha256::digest(&signatures)),
+            };
+            files.push((file.path.clone(), signatures));
+            Some(file)
+        } else {
+            if include_signatures {
+                warn!(
+                    policy = uri.as_str(),
+                    "signatures are available only for registry policies"
+                );
+            }
+            None
+        };
+
+        entries.push(BundleEntry {
+            uri,
+            path: policy_path.clone(),
+            digest: format!("{:x}", Sha256::digest(&module)),
+            signatures,
+        });
+        files.push((policy_path, module));
+    }
+
+    let manifest = BundleManifest { policies: entries };
+    let manifest = serde_json::to_vec_pretty(&manifest)
+        .map_err(|e| anyhow!("cannot serialize bundle manifest: {}", e))?;
+
+    let tar_gz =
+        File::create(output).map_err(|e| anyhow!("cannot create file {}: {}", output, e))?;
+    let enc = GzEncoder::new(tar_gz, Compression::default());
+    let mut tar = tar::Builder::new(enc);
+
+    append_data(&mut tar, Path::new(BUNDLE_MANIFEST), &manifest)
+        .map_err(|e| anyhow!("cannot append bundle manifest to tar file: {}", e))?;
+    for (path, data) in files {
+        append_data(&mut tar, &path, &data)
+            .map_err(|e| anyhow!("cannot append {:?} to tar file: {}", path, e))?;
     }
+    tar.into_inner()
+        .and_then(|enc| enc.finish())
+        .map_err(|e| anyhow!("cannot write file {}: {}", output, e))?;
 
     Ok(())
 }
+
+fn append_data<W: Write>(tar: &mut tar::Builder<W>, path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    tar.append_data(&mut header, path, data)
+}
@@ -22,6 +22,8 @@ use policy_evaluator::kube;
 use serde::{Deserialize, Serialize};
 use tracing::{debug, info, warn};
 
+use crate::config::kubernetes::KubeClientSettings;
+
 lazy_static! {
     pub static ref DEFAULT_ROOT: ProjectDirs =
         ProjectDirs::from("io.kubewarden", "", "kubewarden").unwrap();
@@ -245,8 +247,8 @@ impl ApiResourceCatalog {
 // The scaffold command must be snappy, we don't want it to get stuck
 // waiting for the connection to Kubernetes to be established.
 // Because of that we set a connection timeout of 1 second.
-async fn build_kube_client() -> Result<kube::Client> {
-    let mut config = kube::Config::infer().await?;
+async fn build_kube_client(settings: KubeClientSettings) -> Result<kube::Client> {
+    let mut config = settings.kube_config().await?;
     config.connect_timeout = Some(std::time::Duration::from_secs(1));
     let client = kube::Client::try_from(config)?;
     Ok(client)
@@ -256,12 +258,18 @@ pub(crate) async fn admission_request(
     operation: Operation,
     object: Option<PathBuf>,
     old_object: Option<PathBuf>,
+    kube_client_settings: KubeClientSettings,
 ) -> Result<()> {
     validate_params(&operation, object.as_ref(), old_object.as_ref())?;
 
     let output = match operation {
         Operation::Create => {
-            scaffold_create(&RESOURCE_CATALOG_FILE, build_kube_client, &object.unwrap()).await?
+            scaffold_create(
+                &RESOURCE_CATALOG_FILE,
+                move || build_kube_client(kube_client_settings),
+                &object.unwrap(),
+            )
+            .await?
         }
         Operation::Update => todo!(),
         Operation::Delete => todo!(),
@@ -0,0 +1,205 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Result, anyhow};
+use policy_evaluator::policy_fetcher::{
+    oci_client::Reference,
+    registry::Registry,
+    sigstore::{
+        cosign::{
+            ClientBuilder, CosignCapabilities,
+            constraint::{AnnotationMarker, Constraint, PrivateKeySigner},
+            signature_layers::SignatureLayer,
+        },
+        crypto::{
+            SigningScheme,
+            signing_key::{SigStoreKeyPair, SigStoreSigner},
+        },
+        registry::{ClientConfig, oci_reference::OciReference},
+    },
+    sources::Sources,
+};
+use tracing::{debug, info};
+
+/// Environment variable holding the password of the private key, the same
+/// one read by cosign
+pub(crate) const KEY_PASSWORD_ENV_VAR: &str = "COSIGN_PASSWORD";
+
+/// Signs the manifest of a policy hosted on a registry with a private key,
+/// then pushes the cosign signature next to the policy. The signatures the
+/// policy already has are kept.
+///
+/// Returns the digest of the signed manifest.
+pub(crate) async fn sign(
+    uri: &str,
+    sources: Option<&Sources>,
+    key_path: &Path,
+    password: &str,
+    annotations: Option<HashMap<String, String>>,
+) -> Result<String> {
+    let image_name = uri
+        .strip_prefix("registry://")
+        .ok_or_else(|| anyhow!("only registry:// policies can be signed"))?;
+    let key = fs::read(key_path)
+        .map_err(|e| anyhow!("cannot read private key {}: {}", key_path.display(), e))?;
+    let signer = load_signer(&key, password)
+        .map_err(|e| anyhow!("cannot load private key {}: {}", key_path.display(), e))?;
+
+    let auth = crate::signatures::cosign_auth(&Registry::auth(image_name));
+    let client_config: ClientConfig = sources.cloned().unwrap_or_default().into();
+    let mut cosign_client = ClientBuilder::default()
+        .with_oci_client_config(client_config)
+        .build()?;
+    let image = OciReference::from_str(image_name)?;
+    let (_, image_digest) = cosign_client
+        .triangulate(&image, &auth)
+        .await
+        .map_err(|e| anyhow!("cannot resolve digest of {}: {}", uri, e))?;
+    debug!(policy = uri, digest = image_digest, "signing policy");
+
+    let mut signature_layer = SignatureLayer::new_unsigned(&image, &image_digest)?;
+    // the annotations are part of the signed payload, they must be added first
+    if let Some(annotations) = annotations {
+        AnnotationMarker { annotations }.add_constraint(&mut signature_layer)?;
+    }
+    PrivateKeySigner::new_with_signer(signer).add_constraint(&mut signature_layer)?;
+
+    // all the signatures of a manifest are stored inside of the same image,
+    // the existing ones must be pushed again together with the new one
+    let pinned_uri = crate::pull::pinned_uri(uri, &image_digest)?;
+    let mut layers = match crate::signatures::fetch_if_signed(&pinned_uri, sources).await? {
+        Some(signatures) => crate::signatures::image_layers(&signatures)?,
+        None => Vec::new(),
+    };
+    debug!(
+        policy = uri,
+        signatures = layers.len(),
+        "keeping the existing signatures"
+    );
+    layers.push(crate::signatures::signed_layer(&signature_layer)?);
+
+    let reference = Reference::from_str(image_name)
+        .map_err(|e| anyhow!("invalid policy reference {}: {}", uri, e))?;
+    let signature_image = crate::signatures::push(&reference, &image_digest, sources, layers)
+        .await
+        .map_err(|e| anyhow!("cannot push signature of {}: {}", uri, e))?;
+    info!(policy = uri, signature = %signature_image, "signature pushed");
+    Ok(image_digest)
+}
+
+/// Loads a private key created by `kwctl generate-key-pair` or by
+/// `cosign generate-key-pair`. Unencrypted PEM keys are accepted too.
+fn load_signer(key: &[u8], password: &str) -> Result<SigStoreSigner> {
+    let encrypted = pem::parse(key)
+        .map_err(|e| anyhow!("invalid PEM: {}", e))?
+        .tag()
+        .starts_with("ENCRYPTED");
+    let key_pair = if encrypted {
+        SigStoreKeyPair::from_encrypted_pem(key, password.as_bytes())?
+    } else {
+        SigStoreKeyPair::from_pem(key)?
+    };
+    Ok(key_pair.to_sigstore_signer(&SigningScheme::default())?)
+}
+
+/// Generates a cosign compatible ECDSA P-256 key pair, writing the private
+/// key encrypted with `password` to `<prefix>.key` and the public key to
+/// `<prefix>.pub`.
+///
+/// Returns the paths of the private and of the public key.
+pub(crate) fn generate_key_pair(prefix: &str, password: &str) -> Result<(PathBuf, PathBuf)> {
+    let private_key_path = PathBuf::from(format!("{prefix}.key"));
+    let public_key_path = PathBuf::from(format!("{prefix}.pub"));
+    for path in [&private_key_path, &public_key_path] {
+        if path.exists() {
+            return Err(anyhow!(
+                "{} already exists, refusing to overwrite it",
+                path.display()
+            ));
+        }
+    }
+
+    let key_pair = SigningScheme::default()
+        .create_signer()?
+        .to_sigstore_keypair()?;
+    let private_key = key_pair.private_key_to_encrypted_pem(password.as_bytes())?;
+    let public_key = key_pair.public_key_to_pem()?;
+
+    write_new_file(&private_key_path, private_key.as_bytes(), true)?;
+    write_new_file(&public_key_path, public_key.as_bytes(), false)?;
+    Ok((private_key_path, public_key_path))
+}
+
+fn write_new_file(path: &Path, contents: &[u8], private: bool) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(contents))
+        .map_err(|e| anyhow!("cannot write {}: {}", path.display(), e))
+}
+
+/// Parses the `key=value` annotations added to the signature
+pub(crate) fn parse_annotations<'a>(
+    items: impl IntoIterator<Item = &'a String>,
+) -> Result<Option<HashMap<String, String>>> {
+    let annotations = items
+        .into_iter()
+        .map(|item| {
+            item.split_once('=')
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .ok_or_else(|| anyhow!("annotation {} is not in key=value format", item))
+        })
+        .collect::<Result<HashMap<String, String>>>()?;
+    Ok((!annotations.is_empty()).then_some(annotations))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_annotations() {
+        let items = vec!["env=prod".to_string(), "url=https://x.io/?a=b".to_string()];
+        let annotations = parse_annotations(&items).unwrap().unwrap();
+        assert_eq!(annotations["env"], "prod");
+        assert_eq!(annotations["url"], "https://x.io/?a=b");
+
+        assert!(parse_annotations(&Vec::<String>::new()).unwrap().is_none());
+        assert!(parse_annotations(&vec!["env".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_generate_key_pair() {
+        let tempdir = tempdir().unwrap();
+        let prefix = tempdir.path().join("kwctl");
+        let prefix = prefix.to_str().unwrap();
+
+        let (private_key_path, public_key_path) = generate_key_pair(prefix, "secret").unwrap();
+        let private_key = fs::read(&private_key_path).unwrap();
+        assert!(load_signer(&private_key, "secret").is_ok());
+        assert!(load_signer(&private_key, "wrong").is_err());
+        assert!(
+            fs::read_to_string(&public_key_path)
+                .unwrap()
+                .starts_with("-----BEGIN PUBLIC KEY-----")
+        );
+
+        // existing keys are never overwritten
+        assert!(generate_key_pair(prefix, "secret").is_err());
+    }
+}
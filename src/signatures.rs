@@ -0,0 +1,423 @@
+use std::{collections::BTreeMap, str::FromStr, sync::Arc};
+
+use anyhow::{Result, anyhow};
+use policy_evaluator::policy_fetcher::{
+    oci_client::{
+        self, Reference,
+        client::{Config, ImageLayer},
+        errors::{OciDistributionError, OciErrorCode},
+        manifest::{OCI_IMAGE_MEDIA_TYPE, OciDescriptor, OciImageManifest, WASM_LAYER_MEDIA_TYPE},
+        secrets::RegistryAuth,
+    },
+    policy::Policy,
+    registry::Registry,
+    sigstore::{
+        cosign::{ClientBuilder, CosignCapabilities, signature_layers::SignatureLayer},
+        crypto::{CosignVerificationKey, certificate_pool::CertificatePool},
+        registry::{Auth, ClientConfig, oci_reference::OciReference},
+        simple_signing::SimpleSigning,
+        trust::{TrustRoot, sigstore::SigstoreTrustRoot},
+    },
+    sources::Sources,
+    verify::verify_signatures_against_config,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+use crate::config::verification::VerificationOptions;
+
+/// Media type of the cosign signature layers
+const COSIGN_SIGNATURE_MEDIA_TYPE: &str = "application/vnd.dev.cosign.simplesigning.v1+json";
+
+/// Annotation of the cosign signature layers holding the signature
+const COSIGN_SIGNATURE_ANNOTATION: &str = "dev.cosignproject.cosign/signature";
+
+/// The cosign signatures of a policy, together with the manifest they sign.
+/// This is all what is needed to verify a policy without reaching the registry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BundledSignatures {
+    /// Manifest of the policy image, exactly as served by the registry
+    pub image_manifest: String,
+    /// Manifest of the cosign signature image
+    pub signatures_manifest: String,
+    /// Contents of the layers of the signature image, in the same order of
+    /// the manifest
+    pub layers: Vec<String>,
+}
+
+impl BundledSignatures {
+    /// Digest of the manifest of the policy image, the one being signed
+    pub(crate) fn image_digest(&self) -> String {
+        format!(
+            "sha256:{:x}",
+            Sha256::digest(self.image_manifest.as_bytes())
+        )
+    }
+}
+
+/// Fetches the cosign signatures of a policy hosted on a registry
+pub(crate) async fn fetch(uri: &str, sources: Option<&Sources>) -> Result<BundledSignatures> {
+    fetch_if_signed(uri, sources)
+        .await?
+        .ok_or_else(|| anyhow!("policy {} is not signed", uri))
+}
+
+/// Fetches the cosign signatures of a policy hosted on a registry, returns
+/// `None` when the policy has not been signed yet
+pub(crate) async fn fetch_if_signed(
+    uri: &str,
+    sources: Option<&Sources>,
+) -> Result<Option<BundledSignatures>> {
+    let image_name = uri
+        .strip_prefix("registry://")
+        .ok_or_else(|| anyhow!("signatures can be fetched only for registry:// policies"))?;
+    let registry_auth = Registry::auth(image_name);
+    let auth = cosign_auth(&registry_auth);
+
+    let client_config: ClientConfig = sources.cloned().unwrap_or_default().into();
+    let mut cosign_client = ClientBuilder::default()
+        .with_oci_client_config(client_config.clone())
+        .build()?;
+    let (cosign_image, image_digest) = cosign_client
+        .triangulate(&OciReference::from_str(image_name)?, &auth)
+        .await?;
+
+    let oci_client = oci_client::Client::new(client_config.into());
+    let image = Reference::from_str(image_name)?;
+    let image = Reference::with_digest(
+        image.registry().to_string(),
+        image.repository().to_string(),
+        image_digest.clone(),
+    );
+    let (image_manifest, _) = oci_client
+        .pull_manifest_raw(&image, &registry_auth, &[OCI_IMAGE_MEDIA_TYPE])
+        .await
+        .map_err(|e| anyhow!("cannot fetch manifest of policy {}: {}", uri, e))?;
+
+    let signatures_image = Reference::from_str(&cosign_image.whole())?;
+    let signatures_manifest = match oci_client
+        .pull_manifest_raw(&signatures_image, &registry_auth, &[OCI_IMAGE_MEDIA_TYPE])
+        .await
+    {
+        Ok((signatures_manifest, _)) => signatures_manifest,
+        Err(e) if is_manifest_unknown(&e) => return Ok(None),
+        Err(e) => return Err(anyhow!("cannot fetch signatures of policy {}: {}", uri, e)),
+    };
+    let manifest: OciImageManifest = serde_json::from_slice(&signatures_manifest)
+        .map_err(|e| anyhow!("cannot parse signatures manifest of policy {}: {}", uri, e))?;
+
+    let mut layers = Vec::new();
+    for descriptor in &manifest.layers {
+        let mut data: Vec<u8> = Vec::new();
+        oci_client
+            .pull_blob(&signatures_image, descriptor, &mut data)
+            .await
+            .map_err(|e| anyhow!("cannot fetch signature layer {}: {}", descriptor.digest, e))?;
+        layers.push(
+            String::from_utf8(data)
+                .map_err(|_| anyhow!("signature layer {} is not valid", descriptor.digest))?,
+        );
+    }
+
+    let signatures = BundledSignatures {
+        image_manifest: String::from_utf8(image_manifest.to_vec())
+            .map_err(|_| anyhow!("manifest of policy {} is not valid", uri))?,
+        signatures_manifest: String::from_utf8(signatures_manifest.to_vec())
+            .map_err(|_| anyhow!("signatures manifest of policy {} is not valid", uri))?,
+        layers,
+    };
+    if signatures.image_digest() != image_digest {
+        return Err(anyhow!(
+            "manifest of policy {} does not match digest {}",
+            uri,
+            image_digest
+        ));
+    }
+    Ok(Some(signatures))
+}
+
+/// Whether the registry reported that the manifest does not exist
+fn is_manifest_unknown(error: &OciDistributionError) -> bool {
+    match error {
+        OciDistributionError::ImageManifestNotFoundError(_) => true,
+        OciDistributionError::ServerError { code, .. } => *code == 404,
+        OciDistributionError::RegistryError { envelope, .. } => envelope
+            .errors
+            .iter()
+            .any(|error| matches!(error.code, OciErrorCode::ManifestUnknown)),
+        _ => false,
+    }
+}
+
+/// Simple signing payload of the policy manifest, used to match the
+/// signatures that do not have one against the verification constraints
+pub(crate) fn simple_signing(image_digest: &str) -> Result<SimpleSigning> {
+    serde_json::from_value(serde_json::json!({
+        "critical": {
+            "identity": { "docker-reference": "" },
+            "image": { "docker-manifest-digest": image_digest },
+            "type": "cosign container image signature"
+        },
+        "optional": null
+    }))
+    .map_err(|e| anyhow!("cannot build simple signing payload: {}", e))
+}
+
+/// The registry credentials in the format used by the cosign client
+pub(crate) fn cosign_auth(registry_auth: &RegistryAuth) -> Auth {
+    match registry_auth {
+        RegistryAuth::Anonymous => Auth::Anonymous,
+        RegistryAuth::Basic(username, password) => Auth::Basic(username.clone(), password.clone()),
+        RegistryAuth::Bearer(token) => Auth::Bearer(token.clone()),
+    }
+}
+
+/// Verifies a policy of the store using its bundled signatures, without any
+/// network access. The certificates of keyless signatures can be trusted only
+/// when a Sigstore trust root is provided.
+///
+/// Returns the digest of the verified manifest.
+pub(crate) fn verify_offline(
+    policy: &Policy,
+    signatures: &BundledSignatures,
+    verification_options: &VerificationOptions,
+    sigstore_trust_root: Option<Arc<SigstoreTrustRoot>>,
+) -> Result<String> {
+    // like the online verification, a config without constraints would
+    // accept any signature
+    if !verification_options.has_signature_constraints()
+        && verification_options.identities.is_empty()
+    {
+        return Err(anyhow!(
+            "the verification config does not contain any constraint"
+        ));
+    }
+    let image_digest = signatures.image_digest();
+
+    // Ensure the module inside of the store is the one described by the signed manifest
+    let image_manifest: OciImageManifest = serde_json::from_str(&signatures.image_manifest)
+        .map_err(|e| anyhow!("cannot parse manifest of policy {}: {}", policy.uri, e))?;
+    let module_digest = format!("sha256:{}", policy.digest()?);
+    if !image_manifest
+        .layers
+        .iter()
+        .any(|layer| layer.media_type == WASM_LAYER_MEDIA_TYPE && layer.digest == module_digest)
+    {
+        return Err(anyhow!(
+            "policy {} does not match the signed manifest {}",
+            policy.uri,
+            image_digest
+        ));
+    }
+
+    let trusted_layers = trusted_layers(signatures, &image_digest, sigstore_trust_root)?;
+    if verification_options.has_signature_constraints() {
+        verify_signatures_against_config(&verification_options.config, &trusted_layers)?;
+    }
+    if !verification_options.attestations.is_empty() {
+        return Err(anyhow!(
+            "policy {} requires attestations, they cannot be verified using the bundled signatures",
+            policy.uri
+        ));
+    }
+    crate::identity::verify_identities(&verification_options.identities, &trusted_layers)?;
+
+    info!("Policy successfully verified using the bundled signatures");
+    Ok(image_digest)
+}
+
+/// Signature layers whose integrity has been checked, together with the
+/// certificates of keyless signatures when a trust root is available
+pub(crate) fn trusted_layers(
+    signatures: &BundledSignatures,
+    image_digest: &str,
+    sigstore_trust_root: Option<Arc<SigstoreTrustRoot>>,
+) -> Result<Vec<SignatureLayer>> {
+    let (rekor_pub_keys, fulcio_cert_pool) = match sigstore_trust_root {
+        Some(trust_root) => {
+            let rekor_pub_keys = rekor_pub_keys(&trust_root)?;
+            let fulcio_cert_pool =
+                CertificatePool::from_certificates(trust_root.fulcio_certs()?, [])?;
+            (Some(rekor_pub_keys), Some(fulcio_cert_pool))
+        }
+        None => (None, None),
+    };
+
+    checked_layers(signatures)?
+        .into_iter()
+        .map(|(descriptor, layer)| {
+            SignatureLayer::new(
+                &descriptor,
+                &layer,
+                image_digest,
+                rekor_pub_keys.as_ref(),
+                fulcio_cert_pool.as_ref(),
+            )
+            .map_err(|e| anyhow!("invalid signature layer {}: {}", descriptor.digest, e))
+        })
+        .collect()
+}
+
+/// Layer of the signature image holding a signature created with a private
+/// key, the way cosign stores it
+pub(crate) fn signed_layer(signature_layer: &SignatureLayer) -> Result<ImageLayer> {
+    let signature = signature_layer
+        .signature
+        .clone()
+        .ok_or_else(|| anyhow!("the signature layer has not been signed"))?;
+    Ok(ImageLayer::new(
+        signature_layer.raw_data.clone(),
+        COSIGN_SIGNATURE_MEDIA_TYPE.to_string(),
+        Some(BTreeMap::from([(
+            COSIGN_SIGNATURE_ANNOTATION.to_string(),
+            signature,
+        )])),
+    ))
+}
+
+/// Layers of the signature image, exactly as served by the registry. Used to
+/// copy the signatures without rebuilding them
+pub(crate) fn image_layers(signatures: &BundledSignatures) -> Result<Vec<ImageLayer>> {
+    Ok(checked_layers(signatures)?
+        .into_iter()
+        .map(|(_, layer)| layer)
+        .collect())
+}
+
+/// The layers of the bundled signatures, together with their descriptors,
+/// once ensured they match the signatures manifest
+fn checked_layers(signatures: &BundledSignatures) -> Result<Vec<(OciDescriptor, ImageLayer)>> {
+    let manifest: OciImageManifest = serde_json::from_str(&signatures.signatures_manifest)
+        .map_err(|e| anyhow!("cannot parse signatures manifest: {}", e))?;
+    if manifest.layers.len() != signatures.layers.len() {
+        return Err(anyhow!("bundled signatures do not match their manifest"));
+    }
+
+    manifest
+        .layers
+        .into_iter()
+        .zip(&signatures.layers)
+        .map(|(descriptor, data)| {
+            check_layer(&descriptor, data)?;
+            let layer = ImageLayer::new(
+                data.as_bytes().to_vec(),
+                descriptor.media_type.clone(),
+                descriptor.annotations.clone(),
+            );
+            Ok((descriptor, layer))
+        })
+        .collect()
+}
+
+/// Pushes the cosign signature image of the policy manifest with the given
+/// digest. The image is made of the given layers only: the signatures stored
+/// under the signature tag are replaced, callers willing to keep them must
+/// include their layers.
+///
+/// Returns the reference of the signature image.
+pub(crate) async fn push(
+    image: &Reference,
+    image_digest: &str,
+    sources: Option<&Sources>,
+    layers: Vec<ImageLayer>,
+) -> Result<Reference> {
+    let signatures_image = Reference::with_tag(
+        image.registry().to_string(),
+        image.repository().to_string(),
+        signatures_tag(image_digest),
+    );
+    let registry_auth = Registry::auth(&signatures_image.whole());
+    let client_config: ClientConfig = sources.cloned().unwrap_or_default().into();
+    let oci_client = oci_client::Client::new(client_config.into());
+    let config = Config::oci_v1(b"{}".to_vec(), None);
+
+    let (oci_client, reference, registry_auth, layers) =
+        (&oci_client, &signatures_image, &registry_auth, &layers);
+    crate::retry::with_retries(&format!("push signatures to {reference}"), || {
+        let config = config.clone();
+        async move {
+            oci_client
+                .push(reference, layers, config, registry_auth, None)
+                .await
+                .map_err(anyhow::Error::new)
+        }
+    })
+    .await?;
+    Ok(signatures_image)
+}
+
+/// Tag used by cosign to store the signatures of the image with the given digest
+fn signatures_tag(digest: &str) -> String {
+    format!("{}.sig", digest.replace(':', "-"))
+}
+
+/// Public keys of the Rekor instances of the trust root, by key id
+pub(crate) fn rekor_pub_keys(
+    trust_root: &SigstoreTrustRoot,
+) -> Result<BTreeMap<String, CosignVerificationKey>> {
+    trust_root
+        .rekor_keys()?
+        .into_iter()
+        .map(|(id, key)| Ok((id, CosignVerificationKey::try_from_der(key)?)))
+        .collect()
+}
+
+/// Ensures the contents of the layer match the descriptor
+fn check_layer(descriptor: &OciDescriptor, data: &str) -> Result<()> {
+    if descriptor.media_type != COSIGN_SIGNATURE_MEDIA_TYPE {
+        return Err(anyhow!(
+            "unexpected media type {} of signature layer {}",
+            descriptor.media_type,
+            descriptor.digest
+        ));
+    }
+    if format!("sha256:{:x}", Sha256::digest(data.as_bytes())) != descriptor.digest {
+        return Err(anyhow!(
+            "signature layer {} does not match its digest",
+            descriptor.digest
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    const PAYLOAD: &str = r#"{"critical":{}}"#;
+
+    #[rstest]
+    #[case::valid(COSIGN_SIGNATURE_MEDIA_TYPE, PAYLOAD, true)]
+    #[case::wrong_media_type(WASM_LAYER_MEDIA_TYPE, PAYLOAD, false)]
+    #[case::tampered(COSIGN_SIGNATURE_MEDIA_TYPE, r#"{"critical":{"x":1}}"#, false)]
+    fn test_check_layer(#[case] media_type: &str, #[case] data: &str, #[case] valid: bool) {
+        let descriptor = OciDescriptor {
+            media_type: media_type.to_string(),
+            digest: format!("sha256:{:x}", Sha256::digest(PAYLOAD.as_bytes())),
+            size: PAYLOAD.len() as i64,
+            ..Default::default()
+        };
+        assert_eq!(check_layer(&descriptor, data).is_ok(), valid);
+    }
+
+    #[test]
+    fn test_signatures_tag() {
+        assert_eq!(signatures_tag("sha256:abc"), "sha256-abc.sig");
+    }
+
+    #[test]
+    fn test_image_digest() {
+        let signatures = BundledSignatures {
+            image_manifest: "{}".to_string(),
+            signatures_manifest: "{}".to_string(),
+            layers: vec![],
+        };
+        assert_eq!(
+            signatures.image_digest(),
+            "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+        );
+    }
+}
@@ -0,0 +1,44 @@
+use std::{path::PathBuf, sync::OnceLock};
+
+use anyhow::{Result, anyhow};
+use policy_evaluator::policy_fetcher::{PullDestination, store::Store};
+
+mod records;
+pub(crate) use records::{PolicyRecord, Provenance, record_policy, record_verification};
+
+mod signatures;
+pub(crate) use signatures::{load_signatures, remove_signatures, save_signatures};
+
+mod verify;
+pub(crate) use verify::verify;
+
+/// Location of the policy store chosen by the user, `None` when the
+/// default store has to be used
+static STORE_ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Overrides the location of the policy store used by all the commands.
+/// Must be invoked before any store is opened.
+pub(crate) fn set_root(root: PathBuf) -> Result<()> {
+    if root.as_os_str().is_empty() {
+        return Err(anyhow!("the path of the policy store cannot be empty"));
+    }
+    STORE_ROOT
+        .set(root)
+        .map_err(|_| anyhow!("the location of the policy store has already been set"))
+}
+
+/// Opens the policy store, honouring the location chosen by the user
+pub(crate) fn open() -> Store {
+    match STORE_ROOT.get() {
+        Some(root) => Store::new(root),
+        None => Store::default(),
+    }
+}
+
+/// Destination of the policies pulled into the policy store
+pub(crate) fn pull_destination() -> PullDestination {
+    match STORE_ROOT.get() {
+        Some(root) => PullDestination::Store(root.clone()),
+        None => PullDestination::MainStore,
+    }
+}
@@ -0,0 +1,238 @@
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+use policy_evaluator::policy_fetcher::{
+    policy::Policy,
+    store::{PolicyPath, Store},
+};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+/// Details about a policy, recorded when the policy is added to the store
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PolicyRecord {
+    /// SHA-256 digest of the Wasm module
+    pub digest: String,
+    /// Size of the Wasm module, in bytes
+    pub size: u64,
+    /// Where the policy comes from. Not available for the policies loaded
+    /// from an archive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+}
+
+/// How the policy has been pulled
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Provenance {
+    #[serde(with = "time::serde::rfc3339")]
+    pub pulled_at: OffsetDateTime,
+    /// Digest of the OCI manifest that has been fetched, only for
+    /// policies pulled from a registry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest_digest: Option<String>,
+    /// The policy has been pulled from an insecure source
+    pub insecure_source: bool,
+    /// Custom certificate authorities have been used to connect to the source
+    pub custom_certificate_authorities: bool,
+    /// The sigstore verification of the policy succeeded
+    pub verified: bool,
+}
+
+impl PolicyRecord {
+    /// Builds the record of the given policy, using its current contents
+    pub(crate) fn from_policy(policy: &Policy) -> Result<PolicyRecord> {
+        let digest = policy
+            .digest()
+            .map_err(|e| anyhow!("cannot compute digest of policy {}: {}", policy, e))?;
+        let size = fs::metadata(&policy.local_path)
+            .map_err(|e| anyhow!("cannot read metadata of policy {}: {}", policy, e))?
+            .len();
+
+        Ok(PolicyRecord {
+            digest,
+            size,
+            provenance: None,
+        })
+    }
+
+    /// The policy has been verified and its contents did not change since then
+    pub(crate) fn is_verified(&self, policy: &Policy) -> Result<bool> {
+        let verified = self
+            .provenance
+            .as_ref()
+            .is_some_and(|provenance| provenance.verified);
+        Ok(verified && PolicyRecord::from_policy(policy)?.digest == self.digest)
+    }
+
+    /// Loads the record of the policy, `None` when the policy has not been
+    /// recorded
+    pub(crate) fn load(store: &Store, uri: &str) -> Result<Option<PolicyRecord>> {
+        let path = record_path(store, uri)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let file = File::open(&path)
+            .map_err(|e| anyhow!("cannot open record of policy {}: {}", uri, e))?;
+        serde_json::from_reader(file)
+            .map(Some)
+            .map_err(|e| anyhow!("cannot parse record of policy {}: {}", uri, e))
+    }
+
+    pub(crate) fn save(&self, store: &Store, uri: &str) -> Result<()> {
+        let path = record_path(store, uri)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| anyhow!("cannot create directory {:?}: {}", parent, e))?;
+        }
+
+        let file = File::create(&path)
+            .map_err(|e| anyhow!("cannot create record of policy {}: {}", uri, e))?;
+        serde_json::to_writer_pretty(file, self)
+            .map_err(|e| anyhow!("cannot write record of policy {}: {}", uri, e))
+    }
+
+    /// Deletes the record of the policy, if any
+    pub(crate) fn remove(store: &Store, uri: &str) -> Result<()> {
+        let path = record_path(store, uri)?;
+        if path.exists() {
+            fs::remove_file(&path)
+                .map_err(|e| anyhow!("cannot delete record of policy {}: {}", uri, e))?;
+            remove_empty_dirs(store, &path);
+        }
+        Ok(())
+    }
+}
+
+/// Records the current contents of a policy that has just been added to the store
+pub(crate) fn record_policy(
+    store: &Store,
+    policy: &Policy,
+    provenance: Option<Provenance>,
+) -> Result<()> {
+    let mut record = PolicyRecord::from_policy(policy)?;
+
+    if let Some(mut provenance) = provenance {
+        // The policy has been pulled again: keep the outcome of the previous
+        // verification, as long as the same contents have been fetched
+        if let Some(previous) = PolicyRecord::load(store, &policy.uri)?
+            && previous.digest == record.digest
+            && let Some(previous_provenance) = previous.provenance
+            && previous_provenance.manifest_digest == provenance.manifest_digest
+        {
+            provenance.verified |= previous_provenance.verified;
+        }
+        record.provenance = Some(provenance);
+    }
+
+    record.save(store, &policy.uri)
+}
+
+/// Records the policy has been successfully verified
+pub(crate) fn record_verification(store: &Store, policy: &Policy) -> Result<()> {
+    let mut record = match PolicyRecord::load(store, &policy.uri)? {
+        Some(record) => record,
+        None => PolicyRecord::from_policy(policy)?,
+    };
+    let provenance = record.provenance.get_or_insert_with(|| Provenance {
+        pulled_at: OffsetDateTime::now_utc(),
+        manifest_digest: None,
+        insecure_source: false,
+        custom_certificate_authorities: false,
+        verified: false,
+    });
+    provenance.verified = true;
+
+    record.save(store, &policy.uri)
+}
+
+/// Name of the directory, inside of the store, holding the records.
+/// The store lists only the policies found under the directories named
+/// after their scheme, the records are never mistaken for policies.
+const RECORDS_DIR: &str = ".records";
+
+/// The records are kept inside of the store, mirroring its layout. This way
+/// they follow the policies when the store is copied somewhere else.
+fn records_root(store: &Store) -> PathBuf {
+    store.root.join(RECORDS_DIR)
+}
+
+fn record_path(store: &Store, uri: &str) -> Result<PathBuf> {
+    records_path(store, uri, ".json")
+}
+
+/// Path of a file holding details about the policy, named after the policy
+/// followed by `suffix`
+pub(super) fn records_path(store: &Store, uri: &str, suffix: &str) -> Result<PathBuf> {
+    let policy_path = store
+        .policy_path(uri, PolicyPath::PrefixAndFilename)
+        .map_err(|e| anyhow!("cannot find path for policy {}: {}", uri, e))?;
+
+    let mut path = records_root(store).join(policy_path).into_os_string();
+    path.push(suffix);
+    Ok(PathBuf::from(path))
+}
+
+/// Deletes the directories left empty by the removal of the file at `path`,
+/// from the innermost up to the records directory. Like the cleanup of the
+/// policy directories, errors are ignored: a directory that is not empty is
+/// kept, together with its parents
+pub(super) fn remove_empty_dirs(store: &Store, path: &Path) {
+    let records_root = records_root(store);
+    for dir in path
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| *dir != records_root && dir.starts_with(&records_root))
+    {
+        if fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_save_load_and_remove() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let store = Store::new(&tempdir.path().join("store"));
+        let uri = "registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5";
+
+        assert!(PolicyRecord::load(&store, uri).unwrap().is_none());
+
+        let record = PolicyRecord {
+            digest: "1234".to_string(),
+            size: 42,
+            provenance: Some(Provenance {
+                pulled_at: OffsetDateTime::UNIX_EPOCH,
+                manifest_digest: Some("sha256:5678".to_string()),
+                insecure_source: false,
+                custom_certificate_authorities: false,
+                verified: true,
+            }),
+        };
+        record.save(&store, uri).unwrap();
+        assert_eq!(PolicyRecord::load(&store, uri).unwrap(), Some(record));
+        assert!(
+            record_path(&store, uri)
+                .unwrap()
+                .starts_with(store.root.join(RECORDS_DIR))
+        );
+        assert!(store.list().unwrap().is_empty());
+
+        PolicyRecord::remove(&store, uri).unwrap();
+        assert!(PolicyRecord::load(&store, uri).unwrap().is_none());
+        // the directories of the record are not left behind
+        assert_eq!(
+            fs::read_dir(store.root.join(RECORDS_DIR)).unwrap().count(),
+            0
+        );
+    }
+}
@@ -0,0 +1,52 @@
+use std::fs;
+
+use anyhow::{Result, anyhow};
+use policy_evaluator::policy_fetcher::store::Store;
+
+use super::records::{records_path, remove_empty_dirs};
+use crate::signatures::BundledSignatures;
+
+const SIGNATURES_SUFFIX: &str = ".signatures.json";
+
+/// Keeps the signatures of the policy, loaded from a bundle, next to its record
+pub(crate) fn save_signatures(
+    store: &Store,
+    uri: &str,
+    signatures: &BundledSignatures,
+) -> Result<()> {
+    let path = records_path(store, uri, SIGNATURES_SUFFIX)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| anyhow!("cannot create directory {:?}: {}", parent, e))?;
+    }
+
+    let contents = serde_json::to_vec(signatures)
+        .map_err(|e| anyhow!("cannot serialize signatures of policy {}: {}", uri, e))?;
+    fs::write(&path, contents)
+        .map_err(|e| anyhow!("cannot write signatures of policy {}: {}", uri, e))
+}
+
+/// Loads the bundled signatures of the policy, `None` when the policy has
+/// not been loaded together with its signatures
+pub(crate) fn load_signatures(store: &Store, uri: &str) -> Result<Option<BundledSignatures>> {
+    let path = records_path(store, uri, SIGNATURES_SUFFIX)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let contents =
+        fs::read(&path).map_err(|e| anyhow!("cannot read signatures of policy {}: {}", uri, e))?;
+    serde_json::from_slice(&contents)
+        .map(Some)
+        .map_err(|e| anyhow!("cannot parse signatures of policy {}: {}", uri, e))
+}
+
+pub(crate) fn remove_signatures(store: &Store, uri: &str) -> Result<()> {
+    let path = records_path(store, uri, SIGNATURES_SUFFIX)?;
+    if path.exists() {
+        fs::remove_file(&path)
+            .map_err(|e| anyhow!("cannot delete signatures of policy {}: {}", uri, e))?;
+        remove_empty_dirs(store, &path);
+    }
+    Ok(())
+}
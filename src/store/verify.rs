@@ -0,0 +1,144 @@
+use std::{fmt, fs::File, io::Read, path::Path};
+
+use anyhow::{Result, anyhow};
+use policy_evaluator::policy_fetcher::{policy::Policy, store::Store};
+use prettytable::{Table, format, row};
+
+use super::PolicyRecord;
+
+const WASM_MAGIC: &[u8; 4] = b"\0asm";
+
+/// Outcome of the integrity check of a policy
+#[derive(Debug, PartialEq)]
+enum Status {
+    /// The module matches the record
+    Ok,
+    /// The policy has not been recorded when it was added to the store
+    Unknown,
+    /// The module is smaller than it used to be
+    Truncated,
+    /// The module is not a valid Wasm module anymore
+    Corrupted,
+    /// The module is a valid Wasm module, but its contents changed
+    Tampered,
+}
+
+impl Status {
+    fn is_failure(&self) -> bool {
+        matches!(
+            self,
+            Status::Truncated | Status::Corrupted | Status::Tampered
+        )
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Ok => write!(f, "ok"),
+            Status::Unknown => write!(f, "unknown (no digest recorded)"),
+            Status::Truncated => write!(f, "truncated"),
+            Status::Corrupted => write!(f, "corrupted"),
+            Status::Tampered => write!(f, "tampered"),
+        }
+    }
+}
+
+/// Recomputes the digest of all the policies inside of the store and
+/// compares it with the one recorded when the policy was added to the store
+pub(crate) fn verify() -> Result<()> {
+    let store = super::open();
+    let policies = store.list().map_err(anyhow::Error::new)?;
+    if policies.is_empty() {
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(row!["Policy", "Status"]);
+
+    let mut failures = 0;
+    for policy in policies {
+        let status = check_policy(&store, &policy)?;
+        if status.is_failure() {
+            failures += 1;
+        }
+        table.add_row(row![policy.uri, status]);
+    }
+    table.printstd();
+
+    if failures > 0 {
+        return Err(anyhow!(
+            "{} policies of the store failed the integrity check",
+            failures
+        ));
+    }
+    Ok(())
+}
+
+fn check_policy(store: &Store, policy: &Policy) -> Result<Status> {
+    let Some(record) = PolicyRecord::load(store, &policy.uri)? else {
+        return Ok(Status::Unknown);
+    };
+    let current = PolicyRecord::from_policy(policy)?;
+
+    check_module(&policy.local_path, &current, &record)
+}
+
+fn check_module(path: &Path, current: &PolicyRecord, record: &PolicyRecord) -> Result<Status> {
+    if current.digest == record.digest {
+        return Ok(Status::Ok);
+    }
+    if current.size < record.size {
+        return Ok(Status::Truncated);
+    }
+
+    let mut magic = [0u8; 4];
+    let is_wasm = File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok()
+        && &magic == WASM_MAGIC;
+    if !is_wasm {
+        return Ok(Status::Corrupted);
+    }
+
+    Ok(Status::Tampered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::io::Write;
+
+    #[rstest]
+    #[case::same_digest(b"\0asm\x01\0\0\0", "1234", 8, Status::Ok)]
+    #[case::smaller(b"\0asm", "5678", 8, Status::Truncated)]
+    #[case::not_wasm(b"garbage!", "5678", 8, Status::Corrupted)]
+    #[case::different_wasm(b"\0asm\x01\0\0\x01", "5678", 8, Status::Tampered)]
+    fn test_check_module(
+        #[case] contents: &[u8],
+        #[case] digest: &str,
+        #[case] size: u64,
+        #[case] expected: Status,
+    ) {
+        let mut module = tempfile::NamedTempFile::new().unwrap();
+        module.write_all(contents).unwrap();
+
+        let record = PolicyRecord {
+            digest: "1234".to_string(),
+            size,
+            provenance: None,
+        };
+        let current = PolicyRecord {
+            digest: digest.to_string(),
+            size: contents.len() as u64,
+            provenance: None,
+        };
+
+        assert_eq!(
+            check_module(module.path(), &current, &record).unwrap(),
+            expected
+        );
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Result, anyhow};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use policy_evaluator::policy_fetcher::sigstore::crypto::{CosignVerificationKey, Signature};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sigstore_protobuf_specs::dev::sigstore::rekor::v1::TransparencyLogEntry;
+
+/// Prefix of the signature lines of a checkpoint, see the signed note format
+/// at https://github.com/C2SP/C2SP/blob/main/signed-note.md
+const CHECKPOINT_SIGNATURE_PREFIX: &str = "\u{2014} ";
+
+/// Verifies the Rekor transparency log entry of a Sigstore bundle without any
+/// network access. The signed entry timestamp and the checkpoint of the
+/// inclusion proof must be signed by a Rekor instance of the trust root, and
+/// the entry must be about the artifact with the given SHA-256 digest.
+pub(crate) fn verify_entry(
+    entry: &TransparencyLogEntry,
+    rekor_pub_keys: &BTreeMap<String, CosignVerificationKey>,
+    artifact_digest: &str,
+) -> Result<()> {
+    let log_id = entry
+        .log_id
+        .as_ref()
+        .map(|log_id| to_hex(&log_id.key_id))
+        .ok_or_else(|| anyhow!("the transparency log entry has no log id"))?;
+    let rekor_pub_key = rekor_pub_keys
+        .get(&log_id)
+        .ok_or_else(|| anyhow!("transparency log {} is not part of the trust root", log_id))?;
+
+    verify_inclusion_promise(entry, &log_id, rekor_pub_key)?;
+    verify_inclusion_proof(entry, rekor_pub_key)?;
+    verify_body(&entry.canonicalized_body, artifact_digest)
+}
+
+/// Checks the signed entry timestamp, the promise of Rekor to include the
+/// entry inside of the log
+fn verify_inclusion_promise(
+    entry: &TransparencyLogEntry,
+    log_id: &str,
+    rekor_pub_key: &CosignVerificationKey,
+) -> Result<()> {
+    let promise = entry
+        .inclusion_promise
+        .as_ref()
+        .ok_or_else(|| anyhow!("the transparency log entry has no signed entry timestamp"))?;
+    // Rekor signs the compact JSON with sorted keys, written here in that order
+    let payload = json!({
+        "body": STANDARD.encode(&entry.canonicalized_body),
+        "integratedTime": entry.integrated_time,
+        "logID": log_id,
+        "logIndex": entry.log_index,
+    });
+    rekor_pub_key
+        .verify_signature(
+            Signature::Raw(&promise.signed_entry_timestamp),
+            payload.to_string().as_bytes(),
+        )
+        .map_err(|e| anyhow!("invalid signed entry timestamp: {}", e))
+}
+
+/// Checks the inclusion proof of the entry against the root hash of the log,
+/// then the checkpoint signed by Rekor for that root hash
+fn verify_inclusion_proof(
+    entry: &TransparencyLogEntry,
+    rekor_pub_key: &CosignVerificationKey,
+) -> Result<()> {
+    let proof = entry
+        .inclusion_proof
+        .as_ref()
+        .ok_or_else(|| anyhow!("the transparency log entry has no inclusion proof"))?;
+    let index = u64::try_from(proof.log_index)
+        .map_err(|_| anyhow!("invalid log index {}", proof.log_index))?;
+    let tree_size = u64::try_from(proof.tree_size)
+        .map_err(|_| anyhow!("invalid tree size {}", proof.tree_size))?;
+
+    let root_hash = root_from_inclusion_proof(
+        index,
+        tree_size,
+        leaf_hash(&entry.canonicalized_body),
+        &proof.hashes,
+    )?;
+    if root_hash.as_slice() != proof.root_hash.as_slice() {
+        return Err(anyhow!(
+            "the inclusion proof does not match the root hash of the log"
+        ));
+    }
+
+    let checkpoint = proof
+        .checkpoint
+        .as_ref()
+        .ok_or_else(|| anyhow!("the inclusion proof has no checkpoint"))?;
+    verify_checkpoint(&checkpoint.envelope, tree_size, &root_hash, rekor_pub_key)
+}
+
+/// Checks the checkpoint, a signed note holding the size and the root hash
+/// of the log:
+///
+/// ```text
+/// <origin>
+/// <tree size>
+/// <base64 root hash>
+///
+/// — <name> <base64 key hint and signature>
+/// ```
+fn verify_checkpoint(
+    envelope: &str,
+    tree_size: u64,
+    root_hash: &[u8],
+    rekor_pub_key: &CosignVerificationKey,
+) -> Result<()> {
+    let (note, signatures) = envelope
+        .split_once("\n\n")
+        .ok_or_else(|| anyhow!("the checkpoint is not a signed note"))?;
+    // the signed text includes the newline ending the last line of the note
+    let signed_note = format!("{note}\n");
+    let signed = signatures
+        .lines()
+        .filter_map(|line| line.strip_prefix(CHECKPOINT_SIGNATURE_PREFIX))
+        .filter_map(|line| line.rsplit_once(' '))
+        .filter_map(|(_, signature)| STANDARD.decode(signature).ok())
+        // the first 4 bytes are the hint of the key
+        .filter(|signature| signature.len() > 4)
+        .any(|signature| {
+            rekor_pub_key
+                .verify_signature(Signature::Raw(&signature[4..]), signed_note.as_bytes())
+                .is_ok()
+        });
+    if !signed {
+        return Err(anyhow!(
+            "the checkpoint is not signed by the transparency log"
+        ));
+    }
+
+    let mut lines = note.lines().skip(1);
+    let size = lines.next().and_then(|size| size.parse::<u64>().ok());
+    let hash = lines.next().and_then(|hash| STANDARD.decode(hash).ok());
+    if size != Some(tree_size) || hash.as_deref() != Some(root_hash) {
+        return Err(anyhow!("the checkpoint does not match the inclusion proof"));
+    }
+    Ok(())
+}
+
+/// Ensures the entry records the signature of the artifact being verified
+fn verify_body(canonicalized_body: &[u8], artifact_digest: &str) -> Result<()> {
+    let body: serde_json::Value = serde_json::from_slice(canonicalized_body)
+        .map_err(|e| anyhow!("cannot parse the transparency log entry: {}", e))?;
+    if body["kind"] != "hashedrekord" {
+        return Err(anyhow!(
+            "unsupported transparency log entry of kind {}",
+            body["kind"]
+        ));
+    }
+    let hash = &body["spec"]["data"]["hash"];
+    if hash["algorithm"] != "sha256" || hash["value"] != artifact_digest {
+        return Err(anyhow!(
+            "the transparency log entry does not belong to the policy"
+        ));
+    }
+    Ok(())
+}
+
+/// Root hash of the Merkle tree computed from the inclusion proof of the leaf
+/// with the given index, as described by RFC 9162
+fn root_from_inclusion_proof(
+    index: u64,
+    tree_size: u64,
+    leaf_hash: [u8; 32],
+    proof: &[Vec<u8>],
+) -> Result<[u8; 32]> {
+    if index >= tree_size {
+        return Err(anyhow!(
+            "log index {} is beyond the tree size {}",
+            index,
+            tree_size
+        ));
+    }
+    // the hashes of the inner nodes come first, then the ones on the border
+    // of the tree
+    let inner = (u64::BITS - (index ^ (tree_size - 1)).leading_zeros()) as usize;
+    let border = (index >> inner).count_ones() as usize;
+    if proof.len() != inner + border {
+        return Err(anyhow!(
+            "the inclusion proof has {} hashes instead of {}",
+            proof.len(),
+            inner + border
+        ));
+    }
+
+    let mut hash = leaf_hash;
+    for (level, sibling) in proof.iter().enumerate() {
+        let sibling: &[u8; 32] = sibling
+            .as_slice()
+            .try_into()
+            .map_err(|_| anyhow!("invalid hash inside of the inclusion proof"))?;
+        hash = if level < inner && (index >> level) & 1 == 0 {
+            node_hash(&hash, sibling)
+        } else {
+            node_hash(sibling, &hash)
+        };
+    }
+    Ok(hash)
+}
+
+fn leaf_hash(data: &[u8]) -> [u8; 32] {
+    Sha256::new()
+        .chain_update([0x00])
+        .chain_update(data)
+        .finalize()
+        .into()
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    Sha256::new()
+        .chain_update([0x01])
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    /// Root hash of the tree holding the given leaves, computed as described by RFC 9162
+    fn tree_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+        if leaves.len() == 1 {
+            return leaves[0];
+        }
+        let split = leaves.len().next_power_of_two() / 2;
+        node_hash(&tree_root(&leaves[..split]), &tree_root(&leaves[split..]))
+    }
+
+    /// Inclusion proof of the leaf with the given index, computed as described by RFC 9162
+    fn inclusion_proof(index: usize, leaves: &[[u8; 32]]) -> Vec<Vec<u8>> {
+        if leaves.len() == 1 {
+            return Vec::new();
+        }
+        let split = leaves.len().next_power_of_two() / 2;
+        if index < split {
+            let mut proof = inclusion_proof(index, &leaves[..split]);
+            proof.push(tree_root(&leaves[split..]).to_vec());
+            proof
+        } else {
+            let mut proof = inclusion_proof(index - split, &leaves[split..]);
+            proof.push(tree_root(&leaves[..split]).to_vec());
+            proof
+        }
+    }
+
+    #[rstest]
+    #[case::single_leaf(0, 1)]
+    #[case::first_leaf(0, 7)]
+    #[case::inner_leaf(3, 7)]
+    #[case::last_leaf(6, 7)]
+    #[case::full_tree(5, 8)]
+    fn test_root_from_inclusion_proof(#[case] index: usize, #[case] size: usize) {
+        let leaves: Vec<[u8; 32]> = (0..size)
+            .map(|leaf| leaf_hash(format!("entry {leaf}").as_bytes()))
+            .collect();
+        let proof = inclusion_proof(index, &leaves);
+
+        let root =
+            root_from_inclusion_proof(index as u64, size as u64, leaves[index], &proof).unwrap();
+        assert_eq!(root, tree_root(&leaves));
+
+        // the proof of a leaf cannot be used for another one
+        let other = leaf_hash(b"tampered");
+        assert_ne!(
+            root_from_inclusion_proof(index as u64, size as u64, other, &proof).unwrap(),
+            root
+        );
+        assert!(
+            root_from_inclusion_proof(size as u64, size as u64, leaves[index], &proof).is_err()
+        );
+    }
+
+    #[rstest]
+    #[case::matching("sha256", "abc", true)]
+    #[case::other_artifact("sha256", "def", false)]
+    #[case::other_algorithm("sha512", "abc", false)]
+    fn test_verify_body(#[case] algorithm: &str, #[case] value: &str, #[case] valid: bool) {
+        let body = json!({
+            "apiVersion": "0.0.1",
+            "kind": "hashedrekord",
+            "spec": { "data": { "hash": { "algorithm": algorithm, "value": value } } }
+        });
+        assert_eq!(
+            verify_body(body.to_string().as_bytes(), "abc").is_ok(),
+            valid
+        );
+    }
+}
@@ -22,9 +22,17 @@ pub(crate) enum LookupError {
     UrlToStringConversionError(),
     #[error("{0}")]
     IoError(#[from] std::io::Error),
+    #[error("{0}")]
+    OciLayoutError(#[from] anyhow::Error),
 }
 
 pub(crate) fn map_path_to_uri(uri_or_sha_prefix: &str) -> std::result::Result<String, LookupError> {
+    if uri_or_sha_prefix.starts_with(crate::oci_layout::SCHEME) {
+        // the path of the layout is made absolute, like the ones of file:// URIs
+        let layout_uri = crate::oci_layout::LayoutUri::parse(uri_or_sha_prefix)?.canonicalize()?;
+        return Ok(layout_uri.to_string());
+    }
+
     let uri_has_schema = Regex::new(r"^\w+://").unwrap();
     if uri_has_schema.is_match(uri_or_sha_prefix) {
         return Ok(String::from(uri_or_sha_prefix));
@@ -36,7 +44,7 @@ pub(crate) fn map_path_to_uri(uri_or_sha_prefix: &str) -> std::result::Result<St
 
         Ok(Url::from_file_path(path).unwrap().to_string())
     } else {
-        let store = Store::default();
+        let store = crate::store::open();
         if let Some(policy) = store.get_policy_by_sha_prefix(uri_or_sha_prefix)? {
             Ok(policy.uri.clone())
         } else {
@@ -59,13 +67,16 @@ pub(crate) fn get_wasm_path(uri_or_sha_prefix: &str) -> std::result::Result<Path
 }
 
 pub(crate) fn wasm_path(uri: &str) -> std::result::Result<PathBuf, LookupError> {
+    if uri.starts_with(crate::oci_layout::SCHEME) {
+        return Ok(crate::oci_layout::wasm_path(uri)?);
+    }
     let url = Url::parse(uri)?;
     match url.scheme() {
         "file" => url
             .to_file_path()
             .map_err(|_| LookupError::UrlToStringConversionError()),
         "http" | "https" | "registry" => {
-            let store = Store::default();
+            let store = crate::store::open();
             let policy = store.get_policy_by_uri(uri)?;
 
             if let Some(policy) = policy {
@@ -125,6 +136,22 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_map_path_to_uri_oci_layout() -> Result<()> {
+        let layout_dir = tempfile::tempdir()?;
+        let root = layout_dir.path().canonicalize()?;
+        assert_eq!(
+            map_path_to_uri(&format!(
+                "oci-layout://{}:v1.0.0",
+                layout_dir.path().display()
+            ))?,
+            format!("oci-layout://{}:v1.0.0", root.display()),
+        );
+        assert!(map_path_to_uri("oci-layout:///does/not/exist:v1.0.0").is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn test_build_policy_execution_mode_from_valid_input() {
         let mut data: HashMap<String, PolicyExecutionMode> = HashMap::new();
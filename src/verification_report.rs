@@ -0,0 +1,351 @@
+use std::collections::BTreeMap;
+
+use policy_evaluator::policy_fetcher::{
+    sigstore::cosign::signature_layers::{CertificateSubject, SignatureLayer},
+    verify::{
+        config::{LatestVerificationConfig, Signature},
+        verify_signatures_against_config,
+    },
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{config::verification::VerificationOptions, identity::IdentityConstraint};
+
+/// Outcome of the verification of a policy, reporting which signature
+/// satisfied each constraint of the verification config
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct VerificationReport {
+    pub policy: String,
+    pub verified: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manifest_digest: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub all_of: Vec<ConstraintReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub any_of: Option<AnyOfReport>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub certificate_identities: Vec<IdentityReport>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AnyOfReport {
+    pub minimum_matches: u8,
+    pub satisfied: bool,
+    pub signatures: Vec<ConstraintReport>,
+}
+
+/// A signature constraint of the verification config
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ConstraintReport {
+    pub constraint: Signature,
+    pub satisfied: bool,
+    /// The signatures satisfying the constraint
+    pub satisfied_by: Vec<SignerReport>,
+}
+
+/// A certificate identity constraint checked by kwctl
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IdentityReport {
+    pub constraint: IdentityConstraint,
+    pub satisfied: bool,
+    pub satisfied_by: Vec<SignerReport>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SignerReport {
+    /// Digest of the signature layer
+    pub signature: String,
+    /// Fingerprint of the public key that produced the signature
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_fingerprint: Option<String>,
+    /// Certificate of keyless signatures
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<CertificateReport>,
+    pub annotations: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CertificateReport {
+    pub subject: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub github_workflow: Option<GithubWorkflowReport>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GithubWorkflowReport {
+    pub name: Option<String>,
+    pub repository: Option<String>,
+    #[serde(rename = "ref")]
+    pub git_ref: Option<String>,
+    pub sha: Option<String>,
+    pub trigger: Option<String>,
+}
+
+impl VerificationReport {
+    /// Builds the report of a verification. `result` is the outcome of the
+    /// verification, `trusted_layers` are the signatures of the policy whose
+    /// integrity has already been checked
+    pub(crate) fn new(
+        policy: &str,
+        result: &anyhow::Result<String>,
+        verification_options: &VerificationOptions,
+        trusted_layers: &[SignatureLayer],
+    ) -> Self {
+        let verification_config = &verification_options.config;
+        let all_of = verification_config
+            .all_of
+            .iter()
+            .flatten()
+            .map(|constraint| ConstraintReport::new(constraint, trusted_layers))
+            .collect();
+        let any_of = verification_config.any_of.as_ref().map(|any_of| {
+            let signatures: Vec<ConstraintReport> = any_of
+                .signatures
+                .iter()
+                .map(|constraint| ConstraintReport::new(constraint, trusted_layers))
+                .collect();
+            let matches = signatures.iter().filter(|report| report.satisfied).count();
+            AnyOfReport {
+                minimum_matches: any_of.minimum_matches,
+                satisfied: matches >= usize::from(any_of.minimum_matches.max(1)),
+                signatures,
+            }
+        });
+
+        Self {
+            policy: policy.to_string(),
+            verified: result.is_ok(),
+            manifest_digest: result.as_ref().ok().cloned(),
+            error: result.as_ref().err().map(|e| format!("{e:#}")),
+            all_of,
+            any_of,
+            certificate_identities: verification_options
+                .identities
+                .iter()
+                .map(|constraint| IdentityReport::new(constraint, trusted_layers))
+                .collect(),
+        }
+    }
+}
+
+impl IdentityReport {
+    fn new(constraint: &IdentityConstraint, trusted_layers: &[SignatureLayer]) -> Self {
+        let satisfied_by: Vec<SignerReport> = trusted_layers
+            .iter()
+            .filter(|layer| constraint.is_satisfied_by(layer))
+            .map(|layer| SignerReport::new(None, layer))
+            .collect();
+
+        Self {
+            constraint: constraint.clone(),
+            satisfied: !satisfied_by.is_empty(),
+            satisfied_by,
+        }
+    }
+}
+
+impl ConstraintReport {
+    fn new(constraint: &Signature, trusted_layers: &[SignatureLayer]) -> Self {
+        let config = LatestVerificationConfig {
+            all_of: Some(vec![constraint.clone()]),
+            any_of: None,
+        };
+        let satisfied_by: Vec<SignerReport> = trusted_layers
+            .iter()
+            .filter(|layer| {
+                verify_signatures_against_config(&config, std::slice::from_ref(*layer)).is_ok()
+            })
+            .map(|layer| {
+                let key = match constraint {
+                    Signature::PubKey { key, .. } => Some(key.as_str()),
+                    _ => None,
+                };
+                SignerReport::new(key, layer)
+            })
+            .collect();
+
+        Self {
+            constraint: constraint.clone(),
+            satisfied: !satisfied_by.is_empty(),
+            satisfied_by,
+        }
+    }
+}
+
+impl SignerReport {
+    /// `key` is the public key that verified the signature, if any
+    fn new(key: Option<&str>, layer: &SignatureLayer) -> Self {
+        let key_fingerprint = key.and_then(key_fingerprint);
+        let certificate =
+            layer.certificate_signature.as_ref().map(|certificate| {
+                let github_workflow = certificate.github_workflow_repository.is_some().then(|| {
+                    GithubWorkflowReport {
+                        name: certificate.github_workflow_name.clone(),
+                        repository: certificate.github_workflow_repository.clone(),
+                        git_ref: certificate.github_workflow_ref.clone(),
+                        sha: certificate.github_workflow_sha.clone(),
+                        trigger: certificate.github_workflow_trigger.clone(),
+                    }
+                });
+                CertificateReport {
+                    subject: match &certificate.subject {
+                        CertificateSubject::Email(email) => email.clone(),
+                        CertificateSubject::Uri(uri) => uri.clone(),
+                    },
+                    issuer: certificate.issuer.clone(),
+                    github_workflow,
+                }
+            });
+        let annotations = layer
+            .simple_signing
+            .optional
+            .as_ref()
+            .map(|optional| {
+                optional
+                    .extra
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            signature: layer.oci_digest.clone(),
+            key_fingerprint,
+            certificate,
+            annotations,
+        }
+    }
+}
+
+/// SHA-256 fingerprint of a PEM encoded public key, computed over its DER
+/// encoding like `cosign` does
+fn key_fingerprint(key: &str) -> Option<String> {
+    let key = pem::parse(key).ok()?;
+    Some(format!("sha256:{:x}", Sha256::digest(key.contents())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::{GITHUB_ACTIONS_ISSUER, IdentityMatcher};
+    use anyhow::anyhow;
+    use policy_evaluator::policy_fetcher::verify::config::AnyOf;
+
+    const KEY: &str = "-----BEGIN PUBLIC KEY-----
+MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEQiTy5S+2JFvVlhUwWPLziM7iTM2j
+byLgh2IjpNQN0Uio/9pZOTP/CsJmXoUNshfpTUHd3OxgHgz/6adtf2nBwQ==
+-----END PUBLIC KEY-----";
+
+    fn pub_key() -> Signature {
+        Signature::PubKey {
+            owner: None,
+            key: KEY.to_string(),
+            annotations: None,
+        }
+    }
+
+    #[test]
+    fn test_report_without_signatures() {
+        let config = LatestVerificationConfig {
+            all_of: Some(vec![pub_key()]),
+            any_of: Some(AnyOf {
+                minimum_matches: 1,
+                signatures: vec![Signature::GithubAction {
+                    owner: "kubewarden".to_string(),
+                    repo: None,
+                    annotations: None,
+                }],
+            }),
+        };
+        let result = Err(anyhow!("no signatures found"));
+        let report = VerificationReport::new(
+            "registry://example.com/policy:v1",
+            &result,
+            &config.into(),
+            &[],
+        );
+
+        assert!(!report.verified);
+        assert_eq!(report.manifest_digest, None);
+        assert_eq!(report.error.as_deref(), Some("no signatures found"));
+        assert_eq!(report.all_of.len(), 1);
+        assert!(!report.all_of[0].satisfied);
+        assert!(report.all_of[0].satisfied_by.is_empty());
+        let any_of = report.any_of.unwrap();
+        assert!(!any_of.satisfied);
+        assert!(!any_of.signatures[0].satisfied);
+    }
+
+    #[test]
+    fn test_report_serialization() {
+        let config = LatestVerificationConfig {
+            all_of: Some(vec![pub_key()]),
+            any_of: None,
+        };
+        let result = Ok("sha256:1234".to_string());
+        let report = VerificationReport::new(
+            "registry://example.com/policy:v1",
+            &result,
+            &config.into(),
+            &[],
+        );
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["verified"], true);
+        assert_eq!(json["manifestDigest"], "sha256:1234");
+        assert!(json.get("error").is_none());
+        assert!(json.get("anyOf").is_none());
+        assert!(json.get("certificateIdentities").is_none());
+        assert_eq!(json["allOf"][0]["satisfied"], false);
+    }
+
+    #[test]
+    fn test_report_certificate_identities() {
+        let options = VerificationOptions {
+            config: LatestVerificationConfig {
+                all_of: None,
+                any_of: None,
+            },
+            identities: vec![IdentityConstraint {
+                issuer: Some(IdentityMatcher::Equal(GITHUB_ACTIONS_ISSUER.to_string())),
+                identity: Some(IdentityMatcher::Regexp(
+                    "^https://github.com/kubewarden/".to_string(),
+                )),
+                ..Default::default()
+            }],
+            attestations: Vec::new(),
+        };
+        let result = Err(anyhow!("no signature satisfies the certificate identity"));
+        let report =
+            VerificationReport::new("registry://example.com/policy:v1", &result, &options, &[]);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert!(json["allOf"].as_array().unwrap().is_empty());
+        let identity = &json["certificateIdentities"][0];
+        assert_eq!(identity["satisfied"], false);
+        assert_eq!(
+            identity["constraint"]["identity"]["regexp"],
+            "^https://github.com/kubewarden/"
+        );
+    }
+
+    #[test]
+    fn test_key_fingerprint() {
+        let fingerprint = key_fingerprint(KEY).unwrap();
+        assert!(fingerprint.starts_with("sha256:"));
+        assert_eq!(fingerprint.len(), "sha256:".len() + 64);
+        assert_eq!(key_fingerprint("not a key"), None);
+    }
+}
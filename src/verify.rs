@@ -1,31 +1,85 @@
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use policy_evaluator::policy_fetcher::{
     policy::Policy,
-    sigstore::trust::sigstore::SigstoreTrustRoot,
+    sigstore::{
+        bundle::{
+            Bundle,
+            verify::{PolicyError, VerificationPolicy, Verifier as BundleVerifier},
+        },
+        cosign::signature_layers::SignatureLayer,
+        rekor::apis::configuration::Configuration as RekorConfiguration,
+        trust::sigstore::SigstoreTrustRoot,
+    },
     sources::Sources,
-    verify::{Verifier, config::LatestVerificationConfig},
+    verify::{Verifier, config::Signature, verify_signatures_against_config},
 };
+use sha2::{Digest, Sha256};
 use std::collections::BTreeMap;
-use std::sync::Arc;
-use tracing::{debug, info};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tracing::{debug, info, warn};
+use x509_cert::Certificate;
+
+use crate::{config::verification::VerificationOptions, verification_report::VerificationReport};
 
 pub(crate) type VerificationAnnotations = BTreeMap<String, String>;
 
 pub(crate) async fn verify(
     url: &str,
     sources: Option<&Sources>,
-    verification_config: &LatestVerificationConfig,
+    verification_options: &VerificationOptions,
     sigstore_trust_root: Option<Arc<SigstoreTrustRoot>>,
 ) -> Result<String> {
     debug!(
         policy = url,
         ?sources,
-        ?verification_config,
+        ?verification_options,
         "Verifying policy"
     );
-    let mut verifier = Verifier::new(sources.cloned(), sigstore_trust_root).await?;
-    let verified_manifest_digest = verifier.verify(url, verification_config).await?;
+    let mut verified_manifest_digest = None;
+    if verification_options.has_signature_constraints() {
+        let mut verifier = Verifier::new(sources.cloned(), sigstore_trust_root.clone()).await?;
+        verified_manifest_digest = Some(verifier.verify(url, &verification_options.config).await?);
+    }
+
+    if !verification_options.identities.is_empty() {
+        // the policy fetcher cannot match identities with prefixes or regular
+        // expressions, the signatures are checked by kwctl
+        let signatures = crate::signatures::fetch(url, sources).await?;
+        let image_digest = signatures.image_digest();
+        if let Some(digest) = &verified_manifest_digest
+            && digest != &image_digest
+        {
+            return Err(anyhow!(
+                "policy {} changed during the verification: {} is not {}",
+                url,
+                image_digest,
+                digest
+            ));
+        }
+        let trusted_layers = crate::signatures::trusted_layers(
+            &signatures,
+            &image_digest,
+            sigstore_trust_root.clone(),
+        )?;
+        crate::identity::verify_identities(&verification_options.identities, &trusted_layers)?;
+        verified_manifest_digest = Some(image_digest);
+    }
 
+    let verified_manifest_digest = verified_manifest_digest
+        .ok_or_else(|| anyhow!("the verification config does not contain any constraint"))?;
+
+    if !verification_options.attestations.is_empty() {
+        let layers = crate::attestations::fetch(url, sources, &verified_manifest_digest).await?;
+        let attestations = crate::attestations::trusted_attestations(
+            &layers,
+            &verified_manifest_digest,
+            sigstore_trust_root,
+        )?;
+        crate::attestations::verify_attestations(verification_options, &attestations)?;
+        info!("Policy attestations successfully verified");
+    }
     info!("Policy successfully verified");
     Ok(verified_manifest_digest)
 }
@@ -42,5 +96,273 @@ pub(crate) async fn verify_local_checksum(
         .await?;
 
     info!("Local checksum successfully verified");
+
+    let store = crate::store::open();
+    if policy.local_path.starts_with(&store.root)
+        && let Err(e) = crate::store::record_verification(&store, policy)
+    {
+        warn!(error = %e, "cannot record the verification of the policy");
+    }
+    Ok(())
+}
+
+/// Verifies a policy of the store using the signatures loaded together with
+/// it from an air-gap bundle. No network access is performed.
+pub(crate) fn verify_offline(
+    uri: &str,
+    verification_options: &VerificationOptions,
+    sigstore_trust_root: Option<Arc<SigstoreTrustRoot>>,
+) -> Result<String> {
+    debug!(
+        policy = uri,
+        ?verification_options,
+        "Verifying policy offline"
+    );
+    let store = crate::store::open();
+    let policy = store
+        .get_policy_by_uri(uri)?
+        .ok_or_else(|| anyhow!("cannot find policy {} inside of the store", uri))?;
+    let signatures = crate::store::load_signatures(&store, uri)?.ok_or_else(|| {
+        anyhow!(
+            "policy {} has no bundled signatures. Load it from a bundle created with `kwctl save --include-signatures`",
+            uri
+        )
+    })?;
+
+    let verified_manifest_digest = crate::signatures::verify_offline(
+        &policy,
+        &signatures,
+        verification_options,
+        sigstore_trust_root,
+    )?;
+
+    if let Err(e) = crate::store::record_verification(&store, &policy) {
+        warn!(error = %e, "cannot record the verification of the policy");
+    }
+    Ok(verified_manifest_digest)
+}
+
+/// Verifies a local policy using a Sigstore bundle, without any network
+/// access. The signature, the certificate chain and the inclusion proof of
+/// the transparency log entry are checked against the trust root, then the
+/// identity of the certificate is matched against the verification options.
+///
+/// Returns the digest of the policy module.
+pub(crate) async fn verify_bundle(
+    uri: &str,
+    bundle_path: &Path,
+    verification_options: &VerificationOptions,
+    sigstore_trust_root: SigstoreTrustRoot,
+) -> Result<String> {
+    debug!(
+        policy = uri,
+        bundle = %bundle_path.display(),
+        ?verification_options,
+        "Verifying policy with Sigstore bundle"
+    );
+    let policy_path = uri
+        .strip_prefix("file://")
+        .ok_or_else(|| anyhow!("Sigstore bundles can be used only with file:// policies"))?;
+    check_bundle_constraints(verification_options)?;
+
+    let module =
+        fs::read(policy_path).map_err(|e| anyhow!("cannot read policy {}: {}", policy_path, e))?;
+    let bundle: Bundle = fs::read(bundle_path)
+        .map_err(|e| anyhow!("cannot read bundle {}: {}", bundle_path.display(), e))
+        .and_then(|contents| {
+            serde_json::from_slice(&contents)
+                .map_err(|e| anyhow!("cannot parse bundle {}: {}", bundle_path.display(), e))
+        })?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(&module);
+    let module_hex_digest = format!("{:x}", hasher.clone().finalize());
+    let module_digest = format!("sha256:{module_hex_digest}");
+
+    // the transparency log entry is checked by kwctl, the verifier does not
+    // check the inclusion proof and the signed entry timestamp of the entry
+    let rekor_pub_keys = crate::signatures::rekor_pub_keys(&sigstore_trust_root)?;
+    let entry = bundle
+        .verification_material
+        .as_ref()
+        .and_then(|material| material.tlog_entries.first())
+        .ok_or_else(|| {
+            anyhow!("Image verification failed: the bundle has no transparency log entry")
+        })?;
+    crate::transparency_log::verify_entry(entry, &rekor_pub_keys, &module_hex_digest)
+        .map_err(|e| anyhow!("Image verification failed: {}", e))?;
+
+    let verifier = BundleVerifier::new(RekorConfiguration::default(), sigstore_trust_root)?;
+    let certificate = TrustedCertificate::default();
+    verifier
+        .verify_digest(hasher, bundle, &certificate, true)
+        .await
+        .map_err(|e| anyhow!("Image verification failed: {}", e))?;
+
+    let certificate = certificate
+        .0
+        .into_inner()
+        .ok()
+        .flatten()
+        .ok_or_else(|| anyhow!("Image verification failed: the bundle has no certificate"))?;
+    let layer = SignatureLayer {
+        simple_signing: crate::signatures::simple_signing(&module_digest)?,
+        oci_digest: module_digest.clone(),
+        certificate_signature: Some(crate::identity::certificate_signature(&certificate)?),
+        bundle: None,
+        signature: None,
+        raw_data: Vec::new(),
+    };
+    if verification_options.has_signature_constraints() {
+        verify_signatures_against_config(
+            &verification_options.config,
+            std::slice::from_ref(&layer),
+        )?;
+    }
+    crate::identity::verify_identities(
+        &verification_options.identities,
+        std::slice::from_ref(&layer),
+    )?;
+
+    info!("Policy successfully verified using the Sigstore bundle");
+    Ok(module_digest)
+}
+
+/// Sigstore bundles hold a single keyless signature without annotations,
+/// reject the constraints that could never be satisfied, as well as configs
+/// that would accept any signature
+fn check_bundle_constraints(verification_options: &VerificationOptions) -> Result<()> {
+    if !verification_options.has_signature_constraints()
+        && verification_options.identities.is_empty()
+    {
+        return Err(anyhow!(
+            "the verification config does not contain any constraint"
+        ));
+    }
+    let config = &verification_options.config;
+    let signatures = config
+        .all_of
+        .iter()
+        .flatten()
+        .chain(config.any_of.iter().flat_map(|any_of| &any_of.signatures));
+    for signature in signatures {
+        match signature {
+            Signature::PubKey { .. } => {
+                return Err(anyhow!(
+                    "Sigstore bundles can be verified only against keyless signature constraints"
+                ));
+            }
+            Signature::GenericIssuer { annotations, .. }
+            | Signature::GithubAction { annotations, .. }
+                if annotations.is_some() =>
+            {
+                return Err(anyhow!(
+                    "Sigstore bundles do not hold annotations, they cannot be verified"
+                ));
+            }
+            _ => {}
+        }
+    }
+    if verification_options
+        .identities
+        .iter()
+        .any(|identity| identity.annotations.is_some())
+    {
+        return Err(anyhow!(
+            "Sigstore bundles do not hold annotations, they cannot be verified"
+        ));
+    }
+    if !verification_options.attestations.is_empty() {
+        return Err(anyhow!(
+            "attestations cannot be verified using a Sigstore bundle"
+        ));
+    }
     Ok(())
 }
+
+/// Keeps the certificate of the bundle once the verifier has established it
+/// can be trusted. Its identity is matched by kwctl, like the one of the
+/// signatures hosted on registries
+#[derive(Default)]
+struct TrustedCertificate(Mutex<Option<Certificate>>);
+
+impl VerificationPolicy for TrustedCertificate {
+    fn verify(&self, cert: &Certificate) -> Result<(), PolicyError> {
+        if let Ok(mut certificate) = self.0.lock() {
+            *certificate = Some(cert.clone());
+        }
+        Ok(())
+    }
+}
+
+/// Verifies the policy, online or using its bundled signatures, and reports
+/// which signature satisfied each constraint of the verification config.
+/// A failed verification is reported too, with the constraints that are not
+/// satisfied.
+pub(crate) async fn verify_with_report(
+    uri: &str,
+    sources: Option<&Sources>,
+    verification_options: &VerificationOptions,
+    sigstore_trust_root: Option<Arc<SigstoreTrustRoot>>,
+    offline: bool,
+) -> Result<VerificationReport> {
+    let (result, signatures) = if offline {
+        let result = verify_offline(uri, verification_options, sigstore_trust_root.clone());
+        let store = crate::store::open();
+        (
+            result,
+            crate::store::load_signatures(&store, uri).transpose(),
+        )
+    } else {
+        let result = verify(
+            uri,
+            sources,
+            verification_options,
+            sigstore_trust_root.clone(),
+        )
+        .await;
+        // the signatures of the verified manifest are fetched by its digest,
+        // the tag could have been moved in the meantime
+        let signatures = match &result {
+            Ok(digest) => match crate::pull::pinned_uri(uri, digest) {
+                Ok(pinned_uri) => crate::signatures::fetch(&pinned_uri, sources).await,
+                Err(e) => Err(e),
+            },
+            Err(_) => crate::signatures::fetch(uri, sources).await,
+        };
+        (result, Some(signatures))
+    };
+
+    let trusted_layers = signatures
+        .unwrap_or_else(|| Err(anyhow!("policy {} has no signatures", uri)))
+        .and_then(|signatures| match &result {
+            Ok(digest) if *digest != signatures.image_digest() => Err(anyhow!(
+                "the signatures of manifest {} do not belong to the verified one {}",
+                signatures.image_digest(),
+                digest
+            )),
+            _ => Ok(signatures),
+        })
+        .and_then(|signatures| {
+            crate::signatures::trusted_layers(
+                &signatures,
+                &signatures.image_digest(),
+                sigstore_trust_root,
+            )
+        });
+    let trusted_layers = match (trusted_layers, &result) {
+        (Ok(layers), _) => layers,
+        // the report already explains why the verification failed
+        (Err(_), Err(_)) => Vec::new(),
+        (Err(e), Ok(_)) => {
+            return Err(anyhow!("cannot inspect the signatures of {}: {}", uri, e));
+        }
+    };
+
+    Ok(VerificationReport::new(
+        uri,
+        &result,
+        verification_options,
+        &trusted_layers,
+    ))
+}
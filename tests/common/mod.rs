@@ -9,7 +9,8 @@ pub fn setup_command(path: &Path) -> Command {
     cmd.current_dir(path)
         .env("XDG_CONFIG_HOME", path.join(".config"))
         .env("XDG_CACHE_HOME", path.join(".cache"))
-        .env("XDG_DATA_HOME", path.join(".local/share"));
+        .env("XDG_DATA_HOME", path.join(".local/share"))
+        .env_remove("KWCTL_STORE");
 
     cmd
 }
@@ -105,6 +105,47 @@ fn test_policies() {
         .stdout(contains("v0.1.13"));
 }
 
+#[test]
+fn test_policies_json_output() {
+    let tempdir = tempdir().unwrap();
+    pull_policies(tempdir.path(), POLICIES);
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("policies").arg("--output").arg("json");
+
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let policies: Vec<serde_json::Value> =
+        serde_json::from_slice(&output).expect("cannot parse policies output");
+
+    assert_eq!(policies.len(), POLICIES.len());
+    for policy in policies {
+        let uri = policy["uri"].as_str().unwrap();
+        assert!(POLICIES.contains(&uri));
+        assert_eq!(policy["digest"].as_str().unwrap().len(), 64);
+        assert!(policy["size"].as_u64().unwrap() > 0);
+    }
+}
+
+#[rstest]
+#[case::matching_registry("ghcr.io", POLICIES.len())]
+#[case::other_registry("docker.io", 0)]
+fn test_policies_filter_by_registry(#[case] registry: &str, #[case] expected: usize) {
+    let tempdir = tempdir().unwrap();
+    pull_policies(tempdir.path(), POLICIES);
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("policies")
+        .arg("--output")
+        .arg("json")
+        .arg("--registry")
+        .arg(registry);
+
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let policies: Vec<serde_json::Value> =
+        serde_json::from_slice(&output).expect("cannot parse policies output");
+    assert_eq!(policies.len(), expected);
+}
+
 #[rstest]
 #[case::https(
     "https://github.com/kubewarden/pod-privileged-policy/releases/download/v0.2.5/policy.wasm"
@@ -125,6 +166,108 @@ fn test_pull(#[case] uri: &str) {
     cmd.assert().stdout(contains(uri));
 }
 
+#[test]
+fn test_pull_https_with_sha256() {
+    let tempdir = tempdir().unwrap();
+    let uri =
+        "https://github.com/kubewarden/pod-privileged-policy/releases/download/v0.2.5/policy.wasm";
+    let policy_path = tempdir.path().join("policy.wasm");
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("pull").arg(uri).arg("-o").arg(&policy_path);
+    cmd.assert().success();
+    let checksum = calculate_file_checksum(&policy_path).expect("cannot calculate policy sha256");
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("pull")
+        .arg(uri)
+        .arg("--sha256")
+        .arg(format!("sha256:{}", "0".repeat(64)));
+    cmd.assert().failure();
+    cmd.assert().stderr(contains("has not been stored"));
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("policies");
+    cmd.assert().success();
+    cmd.assert().stdout(contains(uri).not());
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("pull").arg(uri).arg("--sha256").arg(&checksum);
+    cmd.assert().success();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("policies");
+    cmd.assert().success();
+    cmd.assert().stdout(contains(uri));
+}
+
+/// Starts an HTTP server answering `502 Bad Gateway` to the first `failures`
+/// connections, and serving a Wasm module afterwards. Returns its port
+fn flaky_http_server(failures: usize) -> u16 {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("cannot bind listener");
+    let port = listener.local_addr().unwrap().port();
+    std::thread::spawn(move || {
+        for (connection, stream) in listener.incoming().enumerate() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            let mut request = [0u8; 4096];
+            let _ = stream.read(&mut request);
+            let response: Vec<u8> = if connection < failures {
+                b"HTTP/1.1 502 Bad Gateway\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_vec()
+            } else {
+                let module = b"\0asm\x01\0\0\0";
+                let mut response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    module.len()
+                )
+                .into_bytes();
+                response.extend_from_slice(module);
+                response
+            };
+            let _ = stream.write_all(&response);
+        }
+    });
+    port
+}
+
+#[rstest]
+#[case::disabled("0", false)]
+#[case::enabled("3", true)]
+fn test_pull_retries_transient_failures(#[case] retries: &str, #[case] success: bool) {
+    let tempdir = tempdir().unwrap();
+    let port = flaky_http_server(2);
+    let uri = format!("http://127.0.0.1:{port}/policy.wasm");
+    std::fs::write(
+        tempdir.path().join("sources.yml"),
+        format!("insecure_sources:\n  - \"127.0.0.1:{port}\"\n"),
+    )
+    .unwrap();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("--retries")
+        .arg(retries)
+        .arg("pull")
+        .arg("--sources-path")
+        .arg("sources.yml")
+        .arg(&uri);
+
+    if success {
+        // every run consumes the failures of the server, assert on a single one
+        cmd.assert().success().stderr(contains("retrying"));
+
+        let mut cmd = setup_command(tempdir.path());
+        cmd.arg("policies");
+        cmd.assert().success();
+        cmd.assert().stdout(contains(uri.as_str()));
+    } else {
+        cmd.assert().failure();
+    }
+}
+
 #[test]
 fn test_pull_registry_no_tag() {
     let tempdir = tempdir().unwrap();
@@ -144,6 +287,46 @@ fn test_pull_registry_no_tag() {
     ));
 }
 
+#[test]
+fn test_pull_into_custom_store() {
+    let tempdir = tempdir().unwrap();
+    let store_path = tempdir.path().join("custom-store");
+    let uri = "registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5";
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("--store-path")
+        .arg(&store_path)
+        .arg("pull")
+        .arg(uri);
+
+    cmd.assert().success();
+    assert!(store_path.exists());
+
+    // the default store is left untouched
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("policies");
+
+    cmd.assert().success();
+    cmd.assert().stdout("");
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.env("KWCTL_STORE", &store_path).arg("policies");
+
+    cmd.assert().success();
+    cmd.assert().stdout(contains(uri));
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("rm").arg(uri).arg("--store-path").arg(&store_path);
+
+    cmd.assert().success();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("policies").arg("--store-path").arg(&store_path);
+
+    cmd.assert().success();
+    cmd.assert().stdout("");
+}
+
 #[rstest]
 #[case::allowed("unprivileged-pod.json", true)]
 #[case::rejected("privileged-pod.json", false)]
@@ -164,6 +347,22 @@ fn test_run_individual_policy_from_cli(#[case] request: &str, #[case] allowed: b
         .stdout(contains(format!("\"allowed\":{}", allowed)));
 }
 
+#[test]
+fn test_run_require_verified_policy() {
+    let tempdir = tempdir().unwrap();
+    pull_policies(tempdir.path(), POLICIES);
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("run")
+        .arg("--require-verified")
+        .arg("--request-path")
+        .arg(test_data("unprivileged-pod.json"))
+        .arg("registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5");
+
+    cmd.assert().failure();
+    cmd.assert().stderr(contains("has not been verified"));
+}
+
 #[rstest]
 #[case::admission_review_rejected("unprivileged-pod.json", "settings_cel_type_error.json")]
 fn test_run_multiline_error(#[case] request: &str, #[case] settings: &str) {
@@ -215,6 +414,182 @@ fn test_run_individual_policy_from_yaml(#[case] request: &str, #[case] allowed:
         .stdout(contains(format!("\"allowed\":{}", allowed)));
 }
 
+#[test]
+fn test_run_locked_policy_from_yaml() {
+    let tempdir = tempdir().expect("cannot create tempdir");
+    pull_policies(tempdir.path(), POLICIES);
+
+    let crd = admission_policy(
+        "pod-privileged-policy",
+        "registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5",
+    );
+    let yaml_file = write_tmp_yaml_file(
+        serde_yaml::to_string(&crd)
+            .expect("cannot serialize CRD")
+            .as_bytes(),
+    );
+    let mut lock_path = yaml_file.path().as_os_str().to_owned();
+    lock_path.push(".lock");
+    let lock_path = std::path::PathBuf::from(lock_path);
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("run")
+        .arg("--locked")
+        .arg("--request-path")
+        .arg(test_data("unprivileged-pod.json"))
+        .arg(yaml_file.path());
+    cmd.assert().failure();
+    cmd.assert().stderr(contains("kwctl lock"));
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("lock").arg(yaml_file.path());
+    cmd.assert().success();
+    let lock_file = std::fs::read_to_string(&lock_path).expect("cannot read lock file");
+    assert!(lock_file.contains("registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5"));
+    assert!(lock_file.contains("sha256:"));
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("run")
+        .arg("--locked")
+        .arg("--request-path")
+        .arg(test_data("unprivileged-pod.json"))
+        .arg(yaml_file.path());
+    cmd.assert().success();
+    cmd.assert().stdout(contains("\"allowed\":true"));
+
+    // pretend the tag has been moved upstream
+    let tampered = regex::Regex::new("sha256:[0-9a-f]{64}")
+        .unwrap()
+        .replace(&lock_file, format!("sha256:{}", "0".repeat(64)));
+    std::fs::write(&lock_path, tampered.as_bytes()).expect("cannot write lock file");
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("run")
+        .arg("--locked")
+        .arg("--request-path")
+        .arg(test_data("unprivileged-pod.json"))
+        .arg(yaml_file.path());
+    cmd.assert().failure();
+    cmd.assert().stderr(contains("while the lock file expects"));
+
+    std::fs::remove_file(&lock_path).expect("cannot remove lock file");
+}
+
+#[test]
+fn test_pin_modules_of_yaml_file() {
+    let tempdir = tempdir().expect("cannot create tempdir");
+
+    let policy = admission_policy(
+        "pod-privileged-policy",
+        "registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5",
+    );
+    let group = cluster_admission_policy_group::ClusterAdmissionPolicyGroup {
+        metadata: k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta {
+            name: Some("group-policy".to_string()),
+            ..Default::default()
+        },
+        spec: Some(
+            cluster_admission_policy_group::ClusterAdmissionPolicyGroupSpec {
+                expression: "labels()".to_string(),
+                message: "you shall not pass!".to_string(),
+                policies: HashMap::from([(
+                    "labels".to_string(),
+                    cluster_admission_policy_group::PolicyGroupMemberWithContext {
+                        module: "registry://ghcr.io/kubewarden/tests/safe-labels:v0.1.13"
+                            .to_string(),
+                        ..Default::default()
+                    },
+                )]),
+                ..Default::default()
+            },
+        ),
+        ..Default::default()
+    };
+    let contents = format!(
+        "# pod privileged policy\n{}---\n# policy group\n{}",
+        serde_yaml::to_string(&policy).expect("cannot serialize CRD"),
+        serde_yaml::to_string(&group).expect("cannot serialize CRD"),
+    );
+    let yaml_file = write_tmp_yaml_file(contents.as_bytes());
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("pin").arg("--dry-run").arg(yaml_file.path());
+    cmd.assert().success();
+    cmd.assert().stdout(contains("would pin"));
+    assert_eq!(std::fs::read_to_string(yaml_file.path()).unwrap(), contents);
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("pin").arg(yaml_file.path());
+    cmd.assert().success();
+
+    let pinned = std::fs::read_to_string(yaml_file.path()).unwrap();
+    let pinned_module = regex::Regex::new(
+        r"(?m)^\s*module: ghcr.io/kubewarden/tests/pod-privileged:v0.2.5@sha256:[0-9a-f]{64}$",
+    )
+    .unwrap();
+    assert!(pinned_module.is_match(&pinned), "{pinned}");
+    let pinned_member = regex::Regex::new(
+        r"(?m)^\s*module: ghcr.io/kubewarden/tests/safe-labels:v0.1.13@sha256:[0-9a-f]{64}$",
+    )
+    .unwrap();
+    assert!(pinned_member.is_match(&pinned), "{pinned}");
+    assert!(pinned.starts_with("# pod privileged policy\n"));
+    assert!(pinned.contains("---\n# policy group\n"));
+    assert!(pinned.find("pod-privileged") < pinned.find("safe-labels"));
+
+    // pinned modules are left untouched
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("pin").arg(yaml_file.path());
+    cmd.assert().success();
+    assert_eq!(std::fs::read_to_string(yaml_file.path()).unwrap(), pinned);
+}
+
+#[test]
+fn test_outdated_policies_of_yaml_file() {
+    let tempdir = tempdir().expect("cannot create tempdir");
+
+    let crd = admission_policy(
+        "pod-privileged-policy",
+        "registry://ghcr.io/kubewarden/tests/pod-privileged:v0.1.9",
+    );
+    let yaml_file = write_tmp_yaml_file(
+        format!(
+            "# outdated policy\n{}",
+            serde_yaml::to_string(&crd).expect("cannot serialize CRD")
+        )
+        .as_bytes(),
+    );
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("outdated").arg(yaml_file.path());
+    cmd.assert()
+        .success()
+        .stdout(contains(
+            "registry://ghcr.io/kubewarden/tests/pod-privileged:v0.1.9",
+        ))
+        .stdout(contains("v0.2.5"));
+
+    // `--update` rewrites the YAML file, the command must run only once
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("outdated")
+        .arg("--update")
+        .arg("--update-level")
+        .arg("major")
+        .arg(yaml_file.path());
+    cmd.assert().success().stdout(contains("updated"));
+
+    let updated = std::fs::read_to_string(yaml_file.path()).unwrap();
+    assert!(updated.starts_with("# outdated policy\n"));
+    assert!(!updated.contains("pod-privileged:v0.1.9"));
+    assert!(updated.contains("registry://ghcr.io/kubewarden/tests/pod-privileged:v"));
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("outdated").arg(yaml_file.path());
+    cmd.assert()
+        .success()
+        .stdout(contains("All the policies are up to date"));
+}
+
 #[test]
 fn test_run_multiple_policies_from_crd() {
     use serde::Serialize;
@@ -420,6 +795,88 @@ fn test_run_context(
         .stdout(contains(format!("\"allowed\":{}", allowed)));
 }
 
+#[rstest]
+#[case::strict(true)]
+#[case::not_strict(false)]
+fn test_run_context_with_mocks(#[case] strict: bool) {
+    let tempdir = tempdir().unwrap();
+    pull_policies(tempdir.path(), POLICIES);
+
+    let mut cmd = setup_command(tempdir.path());
+
+    cmd.arg("run")
+        .arg("--allow-context-aware")
+        .arg("--request-path")
+        .arg(test_data(
+            "context-aware-policy-request-pod-creation-all-labels.json",
+        ))
+        .arg("--host-capabilities-mocks")
+        .arg(test_data(
+            "host-capabilities-mocks/context-aware-demo-namespace-found.yml",
+        ));
+    if strict {
+        cmd.arg("--host-capabilities-mocks-strict");
+    }
+    cmd.arg("registry://ghcr.io/kubewarden/tests/context-aware-policy-demo:v0.1.0");
+
+    cmd.assert().success();
+    cmd.assert().stdout(contains("\"allowed\":true"));
+}
+
+#[rstest]
+#[case::allowed("--allow-host-capabilities", "kubernetes", true)]
+#[case::not_allowed("--allow-host-capabilities", "dns,oci", false)]
+#[case::denied("--deny-host-capabilities", "kubernetes", false)]
+#[case::not_denied("--deny-host-capabilities", "dns", true)]
+fn test_run_context_with_host_capabilities_allow_list(
+    #[case] flag: &str,
+    #[case] capabilities: &str,
+    #[case] allowed: bool,
+) {
+    let tempdir = tempdir().unwrap();
+    pull_policies(tempdir.path(), POLICIES);
+
+    let mut cmd = setup_command(tempdir.path());
+
+    cmd.arg("run")
+        .arg("--allow-context-aware")
+        .arg("--request-path")
+        .arg(test_data(
+            "context-aware-policy-request-pod-creation-all-labels.json",
+        ))
+        .arg("--host-capabilities-mocks")
+        .arg(test_data(
+            "host-capabilities-mocks/context-aware-demo-namespace-found.yml",
+        ))
+        .arg("--host-capabilities-mocks-strict")
+        .arg(flag)
+        .arg(capabilities)
+        .arg("registry://ghcr.io/kubewarden/tests/context-aware-policy-demo:v0.1.0");
+
+    cmd.assert().success();
+    cmd.assert()
+        .stdout(contains(format!("\"allowed\":{}", allowed)));
+}
+
+#[rstest]
+#[case::allow_list("--allow-host-capabilities")]
+#[case::deny_list("--deny-host-capabilities")]
+fn test_run_crypto_host_capabilities_cannot_be_restricted(#[case] flag: &str) {
+    let tempdir = tempdir().unwrap();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("run")
+        .arg("--request-path")
+        .arg(test_data("privileged-pod.json"))
+        .arg(flag)
+        .arg("crypto")
+        .arg("registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5");
+
+    cmd.assert()
+        .failure()
+        .stderr(contains("invalid value 'crypto'"));
+}
+
 #[rstest]
 #[case::allowed(
     "registry://ghcr.io/kubewarden/tests/context-aware-policy-demo:v0.1.0",
@@ -687,18 +1144,74 @@ fn test_rm(
     cmd.assert().stdout(contains(policy_ref).not());
 }
 
-#[test]
-fn test_save_and_load() {
+#[rstest]
+#[case::dry_run(true)]
+#[case::remove(false)]
+fn test_prune_not_referenced_by(#[case] dry_run: bool) {
     let tempdir = tempdir().unwrap();
     pull_policies(tempdir.path(), POLICIES);
 
+    let crd = cluster_admission_policy("policy", POLICIES[0], &[]);
+    let yaml_file = write_tmp_yaml_file(
+        serde_yaml::to_string(&crd)
+            .expect("cannot serialize CRD")
+            .as_bytes(),
+    );
+
     let mut cmd = setup_command(tempdir.path());
-    cmd.arg("save").arg("--output").arg("policies.tar.gz");
+    cmd.arg("prune")
+        .arg("--not-referenced-by")
+        .arg(yaml_file.path());
+    if dry_run {
+        cmd.arg("--dry-run");
+    }
+
+    // prune is not idempotent, the output of its only run is checked
+    cmd.assert()
+        .success()
+        .stdout(contains(POLICIES[1]))
+        .stdout(contains(POLICIES[0]).not());
+
+    let mut cmd = setup_command(tempdir.path());
+    let assert = cmd.arg("policies").assert().success();
+    let assert = assert.stdout(contains(POLICIES[0]));
+    if dry_run {
+        assert.stdout(contains(POLICIES[1]));
+    } else {
+        assert.stdout(contains(POLICIES[1]).not());
+    }
+}
+
+#[rstest]
+#[case::tar_gz("tar.gz", "policies.tar.gz")]
+#[case::oci_layout("oci-layout", "policies")]
+fn test_save_and_load(#[case] format: &str, #[case] bundle: &str) {
+    let tempdir = tempdir().unwrap();
+    pull_policies(tempdir.path(), POLICIES);
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("save")
+        .arg("--format")
+        .arg(format)
+        .arg("--output")
+        .arg(bundle);
     for policy in POLICIES {
         cmd.arg(policy);
     }
     cmd.assert().success();
 
+    if format == "oci-layout" {
+        let layout = tempdir.path().join(bundle);
+        assert!(layout.join("oci-layout").exists());
+        let index: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(layout.join("index.json")).unwrap()).unwrap();
+        let manifests = index["manifests"].as_array().unwrap();
+        assert_eq!(manifests.len(), POLICIES.len());
+        // the manifests of the registry are kept, the digest does not change
+        assert!(manifests.iter().any(|manifest| manifest["digest"]
+            == "sha256:5ddb9b97ac5e466ae81c34b856d526eed784784024133ba67b1a907f63dfa0a2"));
+    }
+
     for policy in POLICIES {
         let mut cmd = setup_command(tempdir.path());
         cmd.arg("rm").arg(policy);
@@ -706,7 +1219,11 @@ fn test_save_and_load() {
     }
 
     let mut cmd = setup_command(tempdir.path());
-    cmd.arg("load").arg("--input").arg("policies.tar.gz");
+    cmd.arg("load")
+        .arg("--format")
+        .arg(format)
+        .arg("--input")
+        .arg(bundle);
     cmd.assert().success();
 
     let mut cmd = setup_command(tempdir.path());
@@ -717,6 +1234,221 @@ fn test_save_and_load() {
     }
 }
 
+#[test]
+fn test_run_and_pull_from_oci_layout() {
+    let tempdir = tempdir().unwrap();
+    pull_policies(tempdir.path(), POLICIES);
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("save")
+        .arg("--format")
+        .arg("oci-layout")
+        .arg("--output")
+        .arg("policies");
+    for policy in POLICIES {
+        cmd.arg(policy);
+    }
+    cmd.assert().success();
+
+    for policy in POLICIES {
+        let mut cmd = setup_command(tempdir.path());
+        cmd.arg("rm").arg(policy);
+        cmd.assert().success();
+    }
+
+    // the layout holds more than one policy, the tag is required
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("run")
+        .arg("--request-path")
+        .arg(test_data("unprivileged-pod.json"))
+        .arg("oci-layout://policies");
+    cmd.assert().failure();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("run")
+        .arg("--request-path")
+        .arg(test_data("privileged-pod.json"))
+        .arg("oci-layout://policies:v0.2.5");
+    cmd.assert().success();
+    cmd.assert().stdout(contains("\"allowed\":false"));
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("pull").arg("oci-layout://policies:v0.1.13");
+    cmd.assert().success();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("policies");
+    cmd.assert().success();
+    cmd.assert().stdout(contains(POLICIES[1]));
+}
+
+#[test]
+fn test_load_json_output() {
+    let tempdir = tempdir().unwrap();
+    pull_policies(tempdir.path(), POLICIES);
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("save").arg("--output").arg("policies.tar.gz");
+    for policy in POLICIES {
+        cmd.arg(policy);
+    }
+    cmd.assert().success();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("load")
+        .arg("--input")
+        .arg("policies.tar.gz")
+        .arg("--output")
+        .arg("json");
+
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let loaded: Vec<serde_json::Value> =
+        serde_json::from_slice(&output).expect("cannot parse load output");
+    assert_eq!(loaded.len(), POLICIES.len());
+    for policy in loaded {
+        assert!(POLICIES.contains(&policy["uri"].as_str().unwrap()));
+        assert_eq!(policy["digest"].as_str().unwrap().len(), 64);
+    }
+}
+
+#[test]
+fn test_load_legacy_archive() {
+    let tempdir = tempdir().unwrap();
+    pull_policies(tempdir.path(), &POLICIES[..1]);
+
+    // archives created by older releases only hold the modules, laid out
+    // like the policy store
+    let policy_path = "registry/ghcr.io/kubewarden/tests/pod-privileged:v0.2.5";
+    let module = std::fs::read(
+        tempdir
+            .path()
+            .join(".cache/kubewarden/store")
+            .join(policy_path),
+    )
+    .unwrap();
+    let archive = std::fs::File::create(tempdir.path().join("policies.tar.gz")).unwrap();
+    let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(
+        archive,
+        flate2::Compression::default(),
+    ));
+    let mut header = tar::Header::new_gnu();
+    header.set_size(module.len() as u64);
+    header.set_mode(0o644);
+    tar.append_data(&mut header, policy_path, module.as_slice())
+        .unwrap();
+    tar.into_inner().unwrap().finish().unwrap();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("rm").arg(POLICIES[0]);
+    cmd.assert().success();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("load")
+        .arg("--input")
+        .arg("policies.tar.gz")
+        .arg("--output")
+        .arg("json");
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let loaded: Vec<serde_json::Value> =
+        serde_json::from_slice(&output).expect("cannot parse load output");
+    assert_eq!(loaded.len(), 1);
+    assert_eq!(loaded[0]["uri"], POLICIES[0]);
+    assert_eq!(
+        loaded[0]["digest"],
+        format!("{:x}", Sha256::digest(&module))
+    );
+}
+
+#[rstest]
+#[case::path_traversal("../../evil.wasm", b"\0asm", "unsafe path")]
+#[case::unexpected_file("registry/ghcr.io/evil.wasm", b"\0asm", "unexpected archive entry")]
+#[case::digest_mismatch(
+    "registry/ghcr.io/kubewarden/tests/pod-privileged:v0.2.5",
+    b"\0asm-tampered",
+    "does not match the digest"
+)]
+fn test_load_rejects_unsafe_archive(
+    #[case] entry_path: &str,
+    #[case] contents: &[u8],
+    #[case] error: &str,
+) {
+    let tempdir = tempdir().unwrap();
+    let manifest = serde_json::json!({
+        "policies": [{
+            "uri": "registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5",
+            "path": "registry/ghcr.io/kubewarden/tests/pod-privileged:v0.2.5",
+            "digest": format!("{:x}", Sha256::digest(b"\0asm")),
+        }]
+    });
+    let manifest = serde_json::to_vec(&manifest).unwrap();
+
+    let archive = std::fs::File::create(tempdir.path().join("policies.tar.gz")).unwrap();
+    let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(
+        archive,
+        flate2::Compression::default(),
+    ));
+    for (path, data) in [
+        ("bundle-manifest.json", manifest.as_slice()),
+        (entry_path, contents),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        // bypass the validation done by `set_path`, to craft malicious entries
+        header.as_gnu_mut().unwrap().name[..path.len()].copy_from_slice(path.as_bytes());
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append(&header, data).unwrap();
+    }
+    tar.into_inner().unwrap().finish().unwrap();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("load").arg("--input").arg("policies.tar.gz");
+    cmd.assert().failure();
+    cmd.assert().stderr(contains(error));
+
+    // nothing has been written to the store
+    assert!(!tempdir.path().join(".cache/evil.wasm").exists());
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("policies");
+    cmd.assert().success();
+    cmd.assert().stdout("");
+}
+
+#[test]
+fn test_store_verify() {
+    let tempdir = tempdir().unwrap();
+    pull_policies(tempdir.path(), POLICIES);
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("store").arg("verify");
+    cmd.assert().success();
+    cmd.assert().stdout(contains("truncated").not());
+
+    // truncate one of the policies of the store
+    let mut dirs = vec![tempdir.path().join(".cache/kubewarden/store")];
+    let mut policy_file = None;
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.ends_with(".records") {
+                continue;
+            } else if path.is_dir() {
+                dirs.push(path);
+            } else {
+                policy_file = Some(path);
+            }
+        }
+    }
+    let policy_file = policy_file.expect("cannot find any policy inside of the store");
+    let contents = std::fs::read(&policy_file).unwrap();
+    std::fs::write(&policy_file, &contents[..contents.len() / 2]).unwrap();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("store").arg("verify");
+    cmd.assert().failure();
+    cmd.assert().stdout(contains("truncated"));
+}
+
 #[test]
 fn test_push() {
     let registry_image = testcontainers::GenericImage::new("docker.io/library/registry", "2")
@@ -801,6 +1533,102 @@ fn test_push() {
         .stdout(contains("my-pod-privileged-policy:v0.1.10"));
 }
 
+#[test]
+fn test_mirror() {
+    let registry_image = testcontainers::GenericImage::new("docker.io/library/registry", "2")
+        .with_wait_for(WaitFor::message_on_stderr("listening on "));
+    let testcontainer = registry_image
+        .start()
+        .expect("Failed to start registry container");
+    let port = testcontainer
+        .get_host_port_ipv4(5000)
+        .expect("Failed to get port");
+
+    let tempdir = tempdir().unwrap();
+    let sources_yaml = format!(
+        r#"
+        insecure_sources:
+            - "localhost:{}"
+        "#,
+        port
+    );
+    std::fs::write(tempdir.path().join("sources.yml"), sources_yaml).unwrap();
+    std::fs::write(
+        tempdir.path().join("policies.txt"),
+        "# approved policies\nghcr.io/kubewarden/tests/pod-privileged:v0.2.5\n",
+    )
+    .unwrap();
+
+    let source_image = "registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5";
+    let target_image = format!(
+        "registry://localhost:{}/kubewarden/tests/pod-privileged:v0.2.5",
+        port
+    );
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("mirror")
+        .arg("--sources-path")
+        .arg("sources.yml")
+        .arg("--destination")
+        .arg(format!("localhost:{}", port))
+        .arg("--policies-file")
+        .arg("policies.txt")
+        .arg("--dry-run");
+    cmd.assert().success();
+    cmd.assert().stdout(contains(format!(
+        "Would mirror {} to {}",
+        source_image, target_image
+    )));
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("digest")
+        .arg("--sources-path")
+        .arg("sources.yml")
+        .arg(&target_image);
+    cmd.assert().failure();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("mirror")
+        .arg("--sources-path")
+        .arg("sources.yml")
+        .arg("--destination")
+        .arg(format!("localhost:{}", port))
+        .arg("--policies-file")
+        .arg("policies.txt");
+    cmd.assert().success();
+
+    let sources = policy_fetcher::sources::Sources {
+        insecure_sources: HashSet::from([format!("localhost:{}", port)]),
+        ..Default::default()
+    };
+    let source_annotations = get_manifest_annotations(source_image, &sources)
+        .expect("cannot get OCI manifest annotations");
+    let target_annotations = get_manifest_annotations(&target_image, &sources)
+        .expect("cannot get OCI manifest annotations");
+    assert!(!target_annotations.is_empty());
+    assert_eq!(source_annotations, target_annotations);
+
+    let registry = policy_fetcher::registry::Registry::new();
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let (source_digest, target_digest) = rt.block_on(async {
+        (
+            registry.manifest_digest(source_image, None).await.unwrap(),
+            registry
+                .manifest_digest(&target_image, Some(&sources))
+                .await
+                .unwrap(),
+        )
+    });
+    assert_eq!(source_digest, target_digest);
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("pull")
+        .arg("--sources-path")
+        .arg("sources.yml")
+        .arg(&target_image);
+    cmd.assert().success();
+}
+
 #[rstest]
 #[case::pull_policies_before_scaffold(true)]
 #[case::pull_policies_on_demand(false)]
@@ -4,6 +4,7 @@ use predicates::{prelude::*, str::contains};
 use rstest::rstest;
 use std::{fs, path::Path};
 use tempfile::tempdir;
+use testcontainers::{core::WaitFor, runners::SyncRunner};
 
 mod common;
 
@@ -37,6 +38,40 @@ fn test_verify_tuf_integration() {
     // assert!(std::fs::metadata(fulcio_and_rekor_data_path.join("rekor.pub")).is_ok());
 }
 
+#[rstest]
+#[case::identity_regexp(
+    &["--cert-oidc-issuer", "https://token.actions.githubusercontent.com", "--cert-identity-regexp", "^https://github.com/kubewarden/"],
+    true
+)]
+#[case::issuer_regexp(
+    &["--cert-oidc-issuer-regexp", r"^https://token\.actions\.githubusercontent\.com$", "--cert-identity-prefix", "https://github.com/kubewarden/"],
+    true
+)]
+#[case::github_regexp(
+    &["--github-owner-regexp", "^kube"],
+    true
+)]
+#[case::identity_regexp_mismatch(
+    &["--cert-oidc-issuer", "https://token.actions.githubusercontent.com", "--cert-identity-regexp", "^https://github.com/other/"],
+    false
+)]
+fn test_verify_keyless_identity_flags(#[case] flags: &[&str], #[case] success: bool) {
+    let tempdir = tempdir().unwrap();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("verify")
+        .args(flags)
+        .arg("registry://ghcr.io/kubewarden/tests/capabilities-psp:v0.1.9");
+
+    if success {
+        cmd.assert().success();
+    } else {
+        cmd.assert()
+            .failure()
+            .stderr(contains("certificate identity constraint"));
+    }
+}
+
 #[test]
 fn test_verify_fulcio_cert_path() {
     let tempdir = tempdir().unwrap();
@@ -109,6 +144,176 @@ fn test_verify_missing_signatures() {
         .stderr(contains("Image verification failed: missing signatures"));
 }
 
+#[test]
+fn test_verify_json_output() {
+    let tempdir = tempdir().unwrap();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("verify")
+        .arg("--output")
+        .arg("json")
+        .arg("--verification-config-path")
+        .arg(test_data("sigstore/verification-config.yml"))
+        .arg("registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5");
+
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let report: serde_json::Value =
+        serde_json::from_slice(&output).expect("cannot parse verification report");
+    assert_eq!(report["verified"], true);
+    assert!(
+        report["manifestDigest"]
+            .as_str()
+            .unwrap()
+            .starts_with("sha256:")
+    );
+    let all_of = report["allOf"].as_array().unwrap();
+    assert_eq!(all_of.len(), 2);
+    for constraint in all_of {
+        assert_eq!(constraint["satisfied"], true);
+        let signer = &constraint["satisfiedBy"][0];
+        assert!(
+            signer["keyFingerprint"]
+                .as_str()
+                .unwrap()
+                .starts_with("sha256:")
+        );
+        assert_eq!(signer["annotations"]["env"], "prod");
+    }
+}
+
+#[test]
+fn test_verify_json_output_missing_signatures() {
+    let tempdir = tempdir().unwrap();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("verify")
+        .arg("--output")
+        .arg("json")
+        .arg("--verification-config-path")
+        .arg(test_data("sigstore/verification-config.yml"))
+        .arg("registry://ghcr.io/kubewarden/tests/capabilities-psp:v0.1.9");
+
+    let output = cmd.assert().failure().get_output().stdout.clone();
+    let report: serde_json::Value =
+        serde_json::from_slice(&output).expect("cannot parse verification report");
+    assert_eq!(report["verified"], false);
+    assert!(report["error"].as_str().is_some());
+    assert!(
+        report["allOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|constraint| constraint["satisfied"] == false)
+    );
+}
+
+#[test]
+fn test_verify_missing_attestations() {
+    let tempdir = tempdir().unwrap();
+    let verification_config = fs::read_to_string(test_data("sigstore/verification-config.yml"))
+        .expect("cannot read verification config");
+    let verification_config_path = tempdir.path().join("verification-config.yml");
+    fs::write(
+        &verification_config_path,
+        format!(
+            "{verification_config}
+attestations:
+  - predicateType: https://slsa.dev/provenance/v1
+    predicate:
+      - path: runDetails.builder.id
+        prefix: https://github.com/slsa-framework/slsa-github-generator/
+"
+        ),
+    )
+    .expect("cannot write verification config");
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("verify")
+        .arg("--verification-config-path")
+        .arg(&verification_config_path)
+        .arg("registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5");
+
+    cmd.assert()
+        .failure()
+        .stderr(contains("attestations of policy"));
+}
+
+#[test]
+fn test_verify_bundle_requires_trust_config() {
+    let tempdir = tempdir().unwrap();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("verify")
+        .arg("--verification-config-path")
+        .arg(test_data("sigstore/verification-config-keyless.yml"))
+        .arg("--bundle")
+        .arg("policy.sigstore.json")
+        .arg("file://policy.wasm");
+
+    cmd.assert()
+        .failure()
+        .stderr(contains("--sigstore-trust-config"));
+}
+
+#[rstest]
+#[case::valid("policy.sigstore.json", None)]
+#[case::tampered_inclusion_proof(
+    "tampered-inclusion-proof.sigstore.json",
+    Some("the inclusion proof does not match the root hash of the log")
+)]
+#[case::tampered_set("tampered-set.sigstore.json", Some("invalid signed entry timestamp"))]
+fn test_verify_bundle(#[case] bundle: &str, #[case] error: Option<&str>) {
+    let tempdir = tempdir().unwrap();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("verify")
+        .arg("--sigstore-trust-config")
+        .arg(test_data("sigstore/bundle/trust_config.json"))
+        .arg("--cert-oidc-issuer")
+        .arg("https://oauth2.example.com/auth")
+        .arg("--cert-email")
+        .arg("kwctl-tests@example.com")
+        .arg("--bundle")
+        .arg(test_data(&format!("sigstore/bundle/{bundle}")))
+        .arg(format!(
+            "file://{}",
+            test_data("sigstore/bundle/policy.wasm")
+        ));
+
+    match error {
+        None => {
+            cmd.assert().success();
+        }
+        Some(error) => {
+            cmd.assert().failure().stderr(contains(error));
+        }
+    }
+}
+
+#[test]
+fn test_verify_bundle_without_constraints() {
+    let tempdir = tempdir().unwrap();
+    let verification_config_path = tempdir.path().join("verification-config.yml");
+    fs::write(&verification_config_path, "apiVersion: v1\nallOf: []\n").unwrap();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("verify")
+        .arg("--verification-config-path")
+        .arg(&verification_config_path)
+        .arg("--sigstore-trust-config")
+        .arg(test_data("sigstore/bundle/trust_config.json"))
+        .arg("--bundle")
+        .arg(test_data("sigstore/bundle/policy.sigstore.json"))
+        .arg(format!(
+            "file://{}",
+            test_data("sigstore/bundle/policy.wasm")
+        ));
+
+    cmd.assert().failure().stderr(contains(
+        "the verification config does not contain any constraint",
+    ));
+}
+
 #[test]
 fn test_verify_keyless() {
     let tempdir = tempdir().unwrap();
@@ -322,3 +527,170 @@ fn test_run_signed_policy_verification_config(
 
     cmd.assert().stderr(predicate);
 }
+
+#[rstest]
+#[case::good_key("sigstore/cosign1.pub", true)]
+#[case::missing_signatures("sigstore/cosign2.pub", false)]
+fn test_verify_offline_bundled_signatures(#[case] key: &str, #[case] success: bool) {
+    let tempdir = tempdir().unwrap();
+    let policy = "registry://ghcr.io/kubewarden/tests/pod-privileged:v0.1.9";
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("pull").arg(policy);
+    cmd.assert().success();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("save")
+        .arg("--include-signatures")
+        .arg("--output")
+        .arg("policies.tar.gz")
+        .arg(policy);
+    cmd.assert().success();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("rm").arg(policy);
+    cmd.assert().success();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("load").arg("--input").arg("policies.tar.gz");
+    cmd.assert().success();
+    cmd.assert().stdout(contains("yes"));
+
+    // any attempt to reach the registry fails
+    let mut cmd = setup_command(tempdir.path());
+    cmd.env("HTTPS_PROXY", "http://127.0.0.1:1")
+        .env("HTTP_PROXY", "http://127.0.0.1:1")
+        .arg("verify")
+        .arg("--offline")
+        .arg("-a")
+        .arg("env=prod")
+        .arg("-a")
+        .arg("stable=true")
+        .arg("-k")
+        .arg(test_data(key))
+        .arg(policy);
+
+    if success {
+        cmd.assert().success();
+        cmd.assert().stderr(contains(
+            "Policy successfully verified using the bundled signatures",
+        ));
+    } else {
+        cmd.assert().failure();
+    }
+}
+
+#[test]
+fn test_sign_and_verify() {
+    let registry_image = testcontainers::GenericImage::new("docker.io/library/registry", "2")
+        .with_wait_for(WaitFor::message_on_stderr("listening on "));
+    let testcontainer = registry_image
+        .start()
+        .expect("Failed to start registry container");
+    let port = testcontainer
+        .get_host_port_ipv4(5000)
+        .expect("Failed to get port");
+
+    let tempdir = tempdir().unwrap();
+    fs::write(
+        tempdir.path().join("sources.yml"),
+        format!("insecure_sources:\n  - \"localhost:{port}\"\n"),
+    )
+    .unwrap();
+    let policy = format!("registry://localhost:{port}/pod-privileged:v0.2.5");
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("pull")
+        .arg("registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5");
+    cmd.assert().success();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("push")
+        .arg("--sources-path")
+        .arg("sources.yml")
+        .arg("registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.5")
+        .arg(&policy);
+    cmd.assert().success();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.env("COSIGN_PASSWORD", "kwctl")
+        .arg("generate-key-pair")
+        .arg("--output-key-prefix")
+        .arg("kwctl");
+    cmd.assert().success();
+    assert!(tempdir.path().join("kwctl.key").exists());
+    assert!(tempdir.path().join("kwctl.pub").exists());
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.env("COSIGN_PASSWORD", "wrong")
+        .arg("sign")
+        .arg("--sources-path")
+        .arg("sources.yml")
+        .arg("-k")
+        .arg("kwctl.key")
+        .arg(&policy);
+    cmd.assert().failure();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.env("COSIGN_PASSWORD", "kwctl")
+        .arg("sign")
+        .arg("--sources-path")
+        .arg("sources.yml")
+        .arg("-k")
+        .arg("kwctl.key")
+        .arg("-a")
+        .arg("env=prod")
+        .arg(&policy);
+    cmd.assert()
+        .success()
+        .stdout(contains("Policy successfully signed"));
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("verify")
+        .arg("--sources-path")
+        .arg("sources.yml")
+        .arg("-k")
+        .arg("kwctl.pub")
+        .arg("-a")
+        .arg("env=prod")
+        .arg(&policy);
+    cmd.assert().success();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("verify")
+        .arg("--sources-path")
+        .arg("sources.yml")
+        .arg("-k")
+        .arg(test_data("sigstore/cosign1.pub"))
+        .arg(&policy);
+    cmd.assert().failure();
+
+    // signing again keeps the existing signatures
+    let mut cmd = setup_command(tempdir.path());
+    cmd.env("COSIGN_PASSWORD", "other")
+        .arg("generate-key-pair")
+        .arg("--output-key-prefix")
+        .arg("other");
+    cmd.assert().success();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.env("COSIGN_PASSWORD", "other")
+        .arg("sign")
+        .arg("--sources-path")
+        .arg("sources.yml")
+        .arg("-k")
+        .arg("other.key")
+        .arg(&policy);
+    cmd.assert().success();
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("verify")
+        .arg("--sources-path")
+        .arg("sources.yml")
+        .arg("-k")
+        .arg("kwctl.pub")
+        .arg("-k")
+        .arg("other.pub")
+        .arg(&policy);
+    cmd.assert().success();
+}
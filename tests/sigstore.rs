@@ -50,3 +50,36 @@ fn test_sigstore_trust_config() {
     cmd.arg("registry://registry.local:5000/policies/testing:latest");
     cmd.assert().success();
 }
+
+/// Like `test_sigstore_trust_config`, this test requires the local sigstore instance and its
+/// trust_config.json and verification_config.yaml files. It also expects a "policy.wasm" module
+/// signed with `cosign sign-blob --bundle policy.sigstore.json` in the local sigstore instance.
+/// The bundle is verified without reaching the sigstore instance.
+#[test]
+#[cfg(feature = "sigstore-testing")]
+fn test_sigstore_bundle() {
+    let tempdir = tempdir().unwrap();
+    for file in [
+        "trust_config.json",
+        "verification_config.yaml",
+        "policy.wasm",
+        "policy.sigstore.json",
+    ] {
+        std::fs::copy(file, tempdir.path().join(file))
+            .unwrap_or_else(|e| panic!("cannot copy {file}: {e}"));
+    }
+
+    let mut cmd = setup_command(tempdir.path());
+    cmd.arg("verify")
+        .arg("--sigstore-trust-config")
+        .arg("trust_config.json")
+        .arg("--verification-config-path")
+        .arg("verification_config.yaml")
+        .arg("--bundle")
+        .arg("policy.sigstore.json")
+        .arg(format!(
+            "file://{}",
+            tempdir.path().join("policy.wasm").display()
+        ));
+    cmd.assert().success();
+}